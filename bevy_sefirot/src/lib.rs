@@ -7,6 +7,7 @@ use bevy::ecs::schedule::{NodeId, ScheduleLabel, SystemTypeSet};
 use bevy::ecs::system::FunctionSystem;
 use bevy::prelude::*;
 use bevy::utils::HashMap;
+use keter::frame::FramePacer;
 use sefirot::graph::{AsNodes, ComputeGraph, NodeHandle};
 use sefirot::kernel::{Kernel, KernelSignature};
 
@@ -154,6 +155,12 @@ impl MirrorGraph {
         self.graph.execute();
         self.reinit();
     }
+    /// Executes the graph and ends the frame of the `pacer`, blocking if too many frames are in flight.
+    pub fn execute_paced(&mut self, pacer: &mut FramePacer) {
+        self.graph.execute();
+        pacer.end_frame_on(&sefirot::DEVICE.default_stream());
+        self.reinit();
+    }
     #[cfg(feature = "debug")]
     pub fn execute_dbg(&mut self) {
         self.graph.execute_dbg();
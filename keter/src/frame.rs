@@ -0,0 +1,235 @@
+use std::fmt::Debug;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+use luisa_compute::runtime::Stream;
+use parking_lot::{Condvar, Mutex};
+
+use crate::graph::{AsNodes, ComputeGraph, NodeConfigs};
+use crate::DEVICE;
+
+#[derive(Debug, Default)]
+struct FenceState {
+    signaled: Mutex<bool>,
+    condvar: Condvar,
+}
+
+/// A host-side fence, which is signaled once the device finishes executing a graph.
+#[derive(Debug, Clone, Default)]
+pub struct Fence(Arc<FenceState>);
+impl Fence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn new_signaled() -> Self {
+        let fence = Self::new();
+        fence.signal();
+        fence
+    }
+    pub fn signal(&self) {
+        *self.0.signaled.lock() = true;
+        self.0.condvar.notify_all();
+    }
+    pub fn reset(&self) {
+        *self.0.signaled.lock() = false;
+    }
+    pub fn is_signaled(&self) -> bool {
+        *self.0.signaled.lock()
+    }
+    pub fn wait(&self) {
+        let mut signaled = self.0.signaled.lock();
+        while !*signaled {
+            self.0.condvar.wait(&mut signaled);
+        }
+    }
+    /// A node which signals the fence after the graph containing it finishes executing.
+    pub fn signal_node(&self) -> NodeConfigs<'static> {
+        let fence = self.clone();
+        NodeConfigs::default()
+            .debug("signal-fence")
+            .release_fn(move || fence.signal())
+    }
+}
+
+/// Limits the number of frames queued on the device, so that the host can record the next frame
+/// while the device is still executing previous ones.
+pub struct FramePacer {
+    fences: Vec<Fence>,
+    // Resources to be released once the device finishes the frame in the given slot.
+    deferred: Vec<Vec<Box<dyn Send>>>,
+    frame: u64,
+}
+impl Debug for FramePacer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FramePacer")
+            .field("fences", &self.fences)
+            .field("frame", &self.frame)
+            .finish()
+    }
+}
+impl FramePacer {
+    pub fn new(frames_in_flight: usize) -> Self {
+        assert!(
+            frames_in_flight > 0,
+            "Must allow at least one frame in flight."
+        );
+        Self {
+            fences: (0..frames_in_flight)
+                .map(|_| Fence::new_signaled())
+                .collect(),
+            deferred: (0..frames_in_flight).map(|_| Vec::new()).collect(),
+            frame: 0,
+        }
+    }
+    pub fn frames_in_flight(&self) -> usize {
+        self.fences.len()
+    }
+    /// The index of the frame currently being recorded.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+    /// The slot of the frame currently being recorded, for indexing per-frame resources.
+    pub fn slot(&self) -> usize {
+        (self.frame % self.fences.len() as u64) as usize
+    }
+    pub fn fence(&self) -> &Fence {
+        &self.fences[self.slot()]
+    }
+    /// Keeps `value` alive until the device finishes the current frame.
+    pub fn defer(&mut self, value: impl Send + 'static) {
+        let slot = self.slot();
+        self.deferred[slot].push(Box::new(value));
+    }
+    /// Ends the current frame, signaling its fence once every previously submitted command finishes.
+    /// Then waits until the slot of the next frame is free, blocking if there are already
+    /// `frames_in_flight` frames queued on the device.
+    pub fn end_frame(&mut self) {
+        self.end_frame_on(&DEVICE.default_stream());
+    }
+    /// Same as [`FramePacer::end_frame`], but for frames submitted to `stream`,
+    /// which may belong to a different device.
+    pub fn end_frame_on(&mut self, stream: &Stream) {
+        let fence = self.fence().clone();
+        fence.reset();
+        let scope = stream.scope();
+        fence.signal_node().execute_in(&scope);
+        scope.detach();
+
+        self.frame += 1;
+        let slot = self.slot();
+        self.fences[slot].wait();
+        self.deferred[slot].clear();
+    }
+    /// Executes the graph and ends the frame.
+    pub fn execute(&mut self, graph: &mut ComputeGraph<'static>) {
+        graph.execute();
+        self.end_frame();
+    }
+    /// Blocks until the device finishes every submitted frame.
+    pub fn wait_idle(&mut self) {
+        for fence in &self.fences {
+            fence.wait();
+        }
+        for deferred in &mut self.deferred {
+            deferred.clear();
+        }
+    }
+}
+impl Drop for FramePacer {
+    fn drop(&mut self) {
+        self.wait_idle();
+    }
+}
+
+/// A set of resources with one copy per frame in flight, such as staging buffers and readback slots.
+///
+/// When indexed after [`FramePacer::end_frame`], the resource of the current slot is no longer in use by the device,
+/// so readbacks stored in it are complete, from `frames_in_flight` frames ago.
+#[derive(Debug, Clone)]
+pub struct FrameRing<T> {
+    slots: Vec<T>,
+}
+impl<T> FrameRing<T> {
+    pub fn new(pacer: &FramePacer, f: impl FnMut(usize) -> T) -> Self {
+        Self {
+            slots: (0..pacer.frames_in_flight()).map(f).collect(),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+    pub fn get(&self, pacer: &FramePacer) -> &T {
+        &self.slots[pacer.slot()]
+    }
+    pub fn get_mut(&mut self, pacer: &FramePacer) -> &mut T {
+        &mut self.slots[pacer.slot()]
+    }
+    /// The resource used by the previously recorded frame, which may still be in flight.
+    pub fn previous(&self, pacer: &FramePacer) -> &T {
+        &self.slots[(pacer.slot() + self.slots.len() - 1) % self.slots.len()]
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter()
+    }
+}
+impl<T> Index<&FramePacer> for FrameRing<T> {
+    type Output = T;
+    fn index(&self, pacer: &FramePacer) -> &T {
+        self.get(pacer)
+    }
+}
+impl<T> IndexMut<&FramePacer> for FrameRing<T> {
+    fn index_mut(&mut self, pacer: &FramePacer) -> &mut T {
+        self.get_mut(pacer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::tests::CPU_DEVICE;
+
+    #[test]
+    fn fence_signal() {
+        let fence = Fence::new();
+        assert!(!fence.is_signaled());
+        fence
+            .signal_node()
+            .execute_in(&CPU_DEVICE.default_stream().scope());
+        fence.wait();
+        assert!(fence.is_signaled());
+        fence.reset();
+        assert!(!fence.is_signaled());
+
+        let signaler = fence.clone();
+        let handle = thread::spawn(move || signaler.signal());
+        fence.wait();
+        handle.join().unwrap();
+        assert!(fence.is_signaled());
+    }
+
+    #[test]
+    fn ring_wraps_around() {
+        let stream = CPU_DEVICE.default_stream();
+        let mut pacer = FramePacer::new(3);
+        let mut ring = FrameRing::new(&pacer, |i| i * 10);
+        let deferred = Arc::new(());
+        pacer.defer(deferred.clone());
+        for frame in 0..7 {
+            assert_eq!(pacer.frame(), frame as u64);
+            assert_eq!(pacer.slot(), frame % 3);
+            assert_eq!(ring[&pacer], (frame % 3) * 10);
+            assert_eq!(*ring.previous(&pacer), ((frame + 2) % 3) * 10);
+            ring[&pacer] += 1;
+            pacer.end_frame_on(&stream);
+            // Released once the pacer returns to the slot it was deferred in.
+            assert_eq!(Arc::strong_count(&deferred), if frame < 2 { 2 } else { 1 });
+        }
+        assert_eq!(ring.iter().copied().collect::<Vec<_>>(), [3, 12, 22]);
+    }
+}
@@ -12,6 +12,8 @@ use crate::utils::FnRelease;
 
 pub mod copy;
 pub use copy::CopyExt;
+pub mod fill;
+pub use fill::{BlitExt, FillExt};
 pub mod profile;
 
 pub fn dot_graph(compute: &ComputeGraph<'_>, graph: &DiGraphMap<NodeHandle, ()>) -> String {
@@ -0,0 +1,275 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use luisa_compute::prelude::track;
+use luisa_compute::runtime::Device;
+use parking_lot::Mutex;
+
+use super::*;
+use crate::lang::types::vector::{Vec2, Vec3};
+
+type KernelCache = HashMap<(usize, TypeId, &'static str), &'static (dyn Any + Send + Sync)>;
+
+static KERNELS: LazyLock<Mutex<KernelCache>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Kernels are compiled once per device and signature and leaked, so that the dispatched commands can be `'static`.
+fn cached_kernel<K: Any + Send + Sync>(
+    device: &Device,
+    name: &'static str,
+    f: impl FnOnce() -> K,
+) -> &'static K {
+    let mut kernels = KERNELS.lock();
+    let kernel = *kernels
+        .entry((device.native_handle() as usize, TypeId::of::<K>(), name))
+        .or_insert_with(|| Box::leak(Box::new(f())));
+    kernel.downcast_ref::<K>().unwrap()
+}
+
+/// Device-side fills. The `_on` variants take the device owning the resource, which defaults to [`DEVICE`].
+pub trait FillExt<T: Value> {
+    fn fill_on(&self, device: &Device, value: T) -> NodeConfigs<'static>;
+    fn fill(&self, value: T) -> NodeConfigs<'static> {
+        self.fill_on(&DEVICE, value)
+    }
+    fn clear_on(&self, device: &Device) -> NodeConfigs<'static> {
+        // SAFETY: Values are plain-old-data, for which the zero bit pattern is valid.
+        self.fill_on(device, unsafe { std::mem::zeroed() })
+    }
+    fn clear(&self) -> NodeConfigs<'static> {
+        self.clear_on(&DEVICE)
+    }
+}
+impl<T: Value> FillExt<T> for BufferView<T> {
+    fn fill_on(&self, device: &Device, value: T) -> NodeConfigs<'static> {
+        if self.len() == 0 {
+            return NodeConfigs::default();
+        }
+        let kernel = cached_kernel(device, "fill", || {
+            device.create_kernel_async::<fn(BufferView<T>, T)>(&track!(|buffer, value| {
+                buffer.write(dispatch_id().x, value);
+            }))
+        });
+        kernel
+            .dispatch_async([self.len() as u32, 1, 1], self, &value)
+            .into_node_configs()
+    }
+}
+impl<T: Value> FillExt<T> for Buffer<T> {
+    fn fill_on(&self, device: &Device, value: T) -> NodeConfigs<'static> {
+        self.view(..).fill_on(device, value)
+    }
+}
+impl<U: IoTexel> FillExt<U> for Tex2dView<U> {
+    fn fill_on(&self, device: &Device, value: U) -> NodeConfigs<'static> {
+        let kernel = cached_kernel(device, "fill", || {
+            device.create_kernel_async::<fn(Tex2dView<U>, U)>(&track!(|texture, value| {
+                texture.write(dispatch_id().xy(), value);
+            }))
+        });
+        let [w, h] = self.size();
+        kernel
+            .dispatch_async([w, h, 1], self, &value)
+            .into_node_configs()
+    }
+}
+impl<U: IoTexel> FillExt<U> for Tex3dView<U> {
+    fn fill_on(&self, device: &Device, value: U) -> NodeConfigs<'static> {
+        let kernel = cached_kernel(device, "fill", || {
+            device.create_kernel_async::<fn(Tex3dView<U>, U)>(&track!(|texture, value| {
+                texture.write(dispatch_id(), value);
+            }))
+        });
+        kernel
+            .dispatch_async(self.size(), self, &value)
+            .into_node_configs()
+    }
+}
+impl<U: IoTexel> FillExt<U> for Tex2d<U> {
+    fn fill_on(&self, device: &Device, value: U) -> NodeConfigs<'static> {
+        self.view(0).fill_on(device, value)
+    }
+}
+impl<U: IoTexel> FillExt<U> for Tex3d<U> {
+    fn fill_on(&self, device: &Device, value: U) -> NodeConfigs<'static> {
+        self.view(0).fill_on(device, value)
+    }
+}
+
+/// Device-to-device copies.
+pub trait BlitExt {
+    /// Copies exactly, panicking if the sizes or storages differ.
+    fn copy_to_device(&self, dst: &Self) -> NodeConfigs<'static>;
+    /// Resamples textures of differing sizes using nearest-neighbor filtering, with a kernel
+    /// compiled on the device owning both textures.
+    /// Buffers can't be resampled, so this is the same as [`BlitExt::copy_to_device`].
+    fn blit_scaled_on(&self, device: &Device, dst: &Self) -> NodeConfigs<'static>;
+    fn blit_scaled(&self, dst: &Self) -> NodeConfigs<'static> {
+        self.blit_scaled_on(&DEVICE, dst)
+    }
+}
+impl<T: Value> BlitExt for BufferView<T> {
+    fn copy_to_device(&self, dst: &Self) -> NodeConfigs<'static> {
+        assert_eq!(
+            self.len(),
+            dst.len(),
+            "Cannot copy between buffers of different lengths."
+        );
+        self.copy_to_buffer_async(dst).into_node_configs()
+    }
+    fn blit_scaled_on(&self, _device: &Device, dst: &Self) -> NodeConfigs<'static> {
+        self.copy_to_device(dst)
+    }
+}
+impl<T: Value> BlitExt for Buffer<T> {
+    fn copy_to_device(&self, dst: &Self) -> NodeConfigs<'static> {
+        self.view(..).copy_to_device(&dst.view(..))
+    }
+    fn blit_scaled_on(&self, _device: &Device, dst: &Self) -> NodeConfigs<'static> {
+        self.copy_to_device(dst)
+    }
+}
+impl<U: IoTexel> BlitExt for Tex2dView<U> {
+    fn copy_to_device(&self, dst: &Self) -> NodeConfigs<'static> {
+        assert_eq!(
+            self.size(),
+            dst.size(),
+            "Cannot copy between textures of different sizes, use `blit_scaled` to resample."
+        );
+        assert_eq!(
+            self.storage(),
+            dst.storage(),
+            "Cannot copy between textures of different storages, use `blit_scaled` to convert."
+        );
+        self.copy_to_texture_async(dst).into_node_configs()
+    }
+    fn blit_scaled_on(&self, device: &Device, dst: &Self) -> NodeConfigs<'static> {
+        let kernel = cached_kernel(device, "blit", || {
+            device.create_kernel_async::<fn(Tex2dView<U>, Tex2dView<U>, Vec2<f32>)>(&track!(
+                |src, dst, scale| {
+                    let pos = ((dispatch_id().xy().cast_f32() + 0.5) * scale).cast_u32();
+                    dst.write(dispatch_id().xy(), src.read(pos));
+                }
+            ))
+        });
+        let [sw, sh] = self.size();
+        let [dw, dh] = dst.size();
+        let scale = Vec2::new(sw as f32 / dw as f32, sh as f32 / dh as f32);
+        kernel
+            .dispatch_async([dw, dh, 1], self, dst, &scale)
+            .into_node_configs()
+    }
+}
+impl<U: IoTexel> BlitExt for Tex3dView<U> {
+    fn copy_to_device(&self, dst: &Self) -> NodeConfigs<'static> {
+        assert_eq!(
+            self.size(),
+            dst.size(),
+            "Cannot copy between textures of different sizes, use `blit_scaled` to resample."
+        );
+        assert_eq!(
+            self.storage(),
+            dst.storage(),
+            "Cannot copy between textures of different storages, use `blit_scaled` to convert."
+        );
+        self.copy_to_texture_async(dst).into_node_configs()
+    }
+    fn blit_scaled_on(&self, device: &Device, dst: &Self) -> NodeConfigs<'static> {
+        let kernel = cached_kernel(device, "blit", || {
+            device.create_kernel_async::<fn(Tex3dView<U>, Tex3dView<U>, Vec3<f32>)>(&track!(
+                |src, dst, scale| {
+                    let pos = ((dispatch_id().cast_f32() + 0.5) * scale).cast_u32();
+                    dst.write(dispatch_id(), src.read(pos));
+                }
+            ))
+        });
+        let src_size = self.size();
+        let dst_size = dst.size();
+        let scale = Vec3::new(
+            src_size[0] as f32 / dst_size[0] as f32,
+            src_size[1] as f32 / dst_size[1] as f32,
+            src_size[2] as f32 / dst_size[2] as f32,
+        );
+        kernel
+            .dispatch_async(dst_size, self, dst, &scale)
+            .into_node_configs()
+    }
+}
+impl<U: IoTexel> BlitExt for Tex2d<U> {
+    fn copy_to_device(&self, dst: &Self) -> NodeConfigs<'static> {
+        self.view(0).copy_to_device(&dst.view(0))
+    }
+    fn blit_scaled_on(&self, device: &Device, dst: &Self) -> NodeConfigs<'static> {
+        self.view(0).blit_scaled_on(device, &dst.view(0))
+    }
+}
+impl<U: IoTexel> BlitExt for Tex3d<U> {
+    fn copy_to_device(&self, dst: &Self) -> NodeConfigs<'static> {
+        self.view(0).copy_to_device(&dst.view(0))
+    }
+    fn blit_scaled_on(&self, device: &Device, dst: &Self) -> NodeConfigs<'static> {
+        self.view(0).blit_scaled_on(device, &dst.view(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::CPU_DEVICE;
+
+    fn execute(nodes: impl AsNodes<'static>) {
+        nodes.execute_in(&CPU_DEVICE.default_stream().scope());
+    }
+
+    #[test]
+    fn fill_and_clear() {
+        let buffer = CPU_DEVICE.create_buffer_from_fn(10, |i| i as u32);
+        execute(buffer.view(2..6).fill_on(&CPU_DEVICE, 7));
+        assert_eq!(buffer.copy_to_vec(), [0, 1, 7, 7, 7, 7, 6, 7, 8, 9]);
+        execute(buffer.clear_on(&CPU_DEVICE));
+        assert_eq!(buffer.copy_to_vec(), [0; 10]);
+
+        let texture = CPU_DEVICE.create_tex2d::<f32>(PixelStorage::Float1, 4, 3, 1);
+        execute(texture.fill_on(&CPU_DEVICE, 0.5));
+        let values: Vec<f32> = texture.view(0).copy_to_vec();
+        assert_eq!(values, [0.5; 12]);
+    }
+
+    #[test]
+    fn blit_scaled() {
+        let src = CPU_DEVICE.create_tex2d::<f32>(PixelStorage::Float1, 2, 2, 1);
+        src.view(0).copy_from(&[1.0_f32, 2.0, 3.0, 4.0]);
+        let dst = CPU_DEVICE.create_tex2d::<f32>(PixelStorage::Float1, 4, 4, 1);
+        execute(src.blit_scaled_on(&CPU_DEVICE, &dst));
+        #[rustfmt::skip]
+        let expected = [
+            1.0, 1.0, 2.0, 2.0,
+            1.0, 1.0, 2.0, 2.0,
+            3.0, 3.0, 4.0, 4.0,
+            3.0, 3.0, 4.0, 4.0,
+        ];
+        let values: Vec<f32> = dst.view(0).copy_to_vec();
+        assert_eq!(values, expected);
+
+        let copy = CPU_DEVICE.create_tex2d::<f32>(PixelStorage::Float1, 4, 4, 1);
+        execute(dst.copy_to_device(&copy));
+        let values: Vec<f32> = copy.view(0).copy_to_vec();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "different sizes")]
+    fn copy_mismatched_sizes() {
+        let src = CPU_DEVICE.create_tex2d::<f32>(PixelStorage::Float1, 2, 2, 1);
+        let dst = CPU_DEVICE.create_tex2d::<f32>(PixelStorage::Float1, 4, 4, 1);
+        let _ = src.copy_to_device(&dst);
+    }
+
+    #[test]
+    #[should_panic(expected = "different storages")]
+    fn copy_mismatched_storages() {
+        let src = CPU_DEVICE.create_tex2d::<f32>(PixelStorage::Float1, 2, 2, 1);
+        let dst = CPU_DEVICE.create_tex2d::<f32>(PixelStorage::Half1, 2, 2, 1);
+        let _ = src.copy_to_device(&dst);
+    }
+}
@@ -7,10 +7,14 @@ use luisa_compute::runtime::Device;
 pub use luisa_compute::*;
 
 pub mod bindless;
+pub mod frame;
 pub mod graph;
 pub mod pixel_storage;
 pub mod utils;
 
+#[cfg(test)]
+mod tests;
+
 #[doc(hidden)]
 pub use luisa_compute as _luisa;
 
@@ -26,7 +30,8 @@ pub mod prelude {
     pub use luisa_compute::prelude::*;
 
     pub use super::DEVICE;
-    pub use crate::graph::{AsNodes, CopyExt};
+    pub use crate::frame::{FramePacer, FrameRing};
+    pub use crate::graph::{AsNodes, BlitExt, CopyExt, FillExt};
     pub use crate::pixel_storage::HasPixelStorage;
     pub use crate::utils::{Angle, Direction, Singleton};
 }
@@ -0,0 +1,9 @@
+use std::sync::LazyLock;
+
+use luisa_compute::runtime::Device;
+
+// Tests run on the CPU backend, so that they don't require a GPU.
+pub static CPU_DEVICE: LazyLock<Device> = LazyLock::new(|| {
+    let ctx = luisa_compute::Context::new(std::env::current_exe().unwrap());
+    ctx.create_device(luisa_compute::DeviceType::Cpu)
+});
@@ -55,6 +55,7 @@ pub mod prelude {
     };
     pub use crate::graph::{AsNodes, CopyExt};
     pub use crate::kernel::Kernel;
+    pub use crate::utils::fill::FillExt;
     pub use crate::{track, tracked};
 }
 
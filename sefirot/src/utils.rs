@@ -8,6 +8,7 @@ use crate::graph::{AsNodes, CopyExt, NodeConfigs};
 use crate::luisa::prelude::*;
 use crate::DEVICE;
 
+pub mod fill;
 pub mod tag;
 
 /// A struct that runs a given function upon drop.
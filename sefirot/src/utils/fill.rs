@@ -0,0 +1,47 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+
+use crate::graph::{AsNodes, NodeConfigs};
+use crate::luisa::prelude::*;
+use crate::luisa::runtime::Kernel;
+use crate::{track, DEVICE};
+
+static KERNELS: LazyLock<Mutex<HashMap<TypeId, &'static (dyn Any + Send + Sync)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Device-side fills of buffers, matching `keter::graph::FillExt`.
+pub trait FillExt<T: Value> {
+    fn fill(&self, value: T) -> NodeConfigs<'static>;
+    fn clear(&self) -> NodeConfigs<'static> {
+        // SAFETY: Values are plain-old-data, for which the zero bit pattern is valid.
+        self.fill(unsafe { std::mem::zeroed() })
+    }
+}
+impl<T: Value> FillExt<T> for BufferView<T> {
+    fn fill(&self, value: T) -> NodeConfigs<'static> {
+        if self.len() == 0 {
+            return NodeConfigs::default();
+        }
+        // Kernels are compiled once per type and leaked, so that the dispatched commands can be `'static`.
+        let kernel = *KERNELS.lock().entry(TypeId::of::<T>()).or_insert_with(|| {
+            Box::leak(Box::new(
+                DEVICE.create_kernel_async::<fn(BufferView<T>, T)>(&track!(|buffer, value| {
+                    buffer.write(dispatch_id().x, value);
+                })),
+            ))
+        });
+        kernel
+            .downcast_ref::<Kernel<fn(BufferView<T>, T)>>()
+            .unwrap()
+            .dispatch_async([self.len() as u32, 1, 1], self, &value)
+            .into_node_configs()
+    }
+}
+impl<T: Value> FillExt<T> for Buffer<T> {
+    fn fill(&self, value: T) -> NodeConfigs<'static> {
+        self.view(..).fill(value)
+    }
+}
@@ -1,6 +1,5 @@
 use std::rc::Rc;
 use std::sync::Arc;
-use std::vec;
 
 use luisa::lang::types::vector::Vec2;
 use parking_lot::{Mutex, RwLock};
@@ -164,15 +163,8 @@ impl TileArray {
             calculate_buffers_kernel,
         })
     }
-    // TODO: Is this the most optimal way of doing it?
-    // Probably not; letting the reset be done parallel would be better.
     pub fn reset(&self) -> NodeConfigs<'static> {
-        (
-            self.count_buffer.copy_from_vec(vec![0; 64]),
-            self.active_mask_buffer
-                .copy_from_vec(vec![0; self.active_mask_buffer.len()]),
-        )
-            .into_node_configs()
+        (self.count_buffer.clear(), self.active_mask_buffer.clear()).into_node_configs()
     }
     pub fn update(&self) -> NodeConfigs<'static> {
         (
@@ -37,7 +37,7 @@ fn main() {
             yaw: rt.tick as f32 * 0.003,
             pitch: -2.0 * (rt.active_cursor_position().y / rt.size()[1] as f32 - 0.5),
             pos: Vec3::splat(0.0).into(),
-            fov: 1.15,
+            ..Default::default()
         }
         .orbit(5.0);
 
@@ -0,0 +1,67 @@
+use keter::lang::types::vector::{Vec2, Vec3};
+use keter::prelude::*;
+use keter_testbed::{App, KeyCode};
+use yesod::camera::{Bindings, Camera, CameraController, View};
+use yesod::sdf::*;
+
+#[tracked]
+fn scene(p: Expr<Vec3<f32>>) -> Expr<f32> {
+    let ground = plane(p, Vec3::expr(0.0, 0.0, 1.0), 0.0_f32);
+    let blob = smooth_union(
+        sphere(translate(p, Vec3::expr(0.0, 0.0, 1.0)), 0.8_f32),
+        torus(
+            translate(p, Vec3::expr(0.0, 0.0, 0.6)),
+            Vec2::expr(1.0, 0.25),
+        ),
+        0.2_f32,
+    );
+    let pillars = rounded_cuboid(
+        repeat_limited(p, Vec3::expr(4.0, 4.0, 1.0), Vec3::expr(2.0, 2.0, 0.0)),
+        Vec3::expr(0.3, 0.3, 2.0),
+        0.1_f32,
+    );
+    let pillars = subtraction(pillars, sphere(p, 2.0_f32));
+    union(ground, union(blob, pillars))
+}
+
+fn main() {
+    let app = App::new("SDF", [1024; 2]).scale(2).resize().finish();
+
+    let draw_kernel = DEVICE.create_kernel::<fn(View)>(&track!(|view| {
+        set_block_size([8, 8, 1]);
+
+        let pixel = dispatch_id().xy();
+        let (start, dir) = view.ray(pixel, 0_u32.expr());
+        let t = SphereTracer::default().trace(start, dir, scene);
+        let sky = Vec3::expr(0.5, 0.7, 1.0);
+        let color = if t < f32::INFINITY {
+            let pos = start + dir * t;
+            let normal = normal(pos, scene, 1e-3_f32);
+            let light = Vec3::expr(0.6, -0.4, 1.0).normalize();
+            let shadow = soft_shadow(pos, light, scene, 0.01_f32, 20.0_f32, 8.0_f32);
+            let diffuse = keter::max(normal.dot(light), 0.0) * shadow;
+            let ambient = ambient_occlusion(pos, normal, scene) * 0.2;
+            Vec3::splat_expr(0.8_f32) * (diffuse + ambient) + sky * ambient * 0.5
+        } else {
+            sky
+        };
+        app.set_pixel(pixel.cast_i32(), color);
+    }));
+
+    let mut camera = Camera {
+        pos: Vec3::new(0.0, -6.0, 1.5).into(),
+        pitch: 0.15,
+        ..Default::default()
+    };
+    let mut controller = CameraController::fly();
+    let bindings = Bindings::keyboard();
+
+    app.run(|rt| {
+        if rt.key_pressed(KeyCode::Tab) {
+            rt.grab_cursor = !rt.grab_cursor;
+        }
+        camera.screen_size = rt.size().map(|x| x as f32).into();
+        controller.update(&mut camera, &rt.camera_input(&bindings), rt.frame_time());
+        draw_kernel.dispatch(rt.dispatch_size(), &camera.view());
+    });
+}
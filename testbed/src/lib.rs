@@ -2,19 +2,21 @@ use std::collections::HashSet;
 use std::ops::{Deref, DerefMut};
 use std::time::Instant;
 
-use agx::AgXParameters;
 use keter::lang::types::vector::{Vec2, Vec3, Vec4};
 use keter::prelude::*;
 use take_mut::take;
 use winit::application::ApplicationHandler;
 use winit::dpi::{LogicalSize, PhysicalSize, Size};
 pub use winit::event::MouseButton;
-use winit::event::{ElementState, WindowEvent};
+use winit::event::{DeviceEvent, DeviceId, ElementState, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
 pub use winit::keyboard::KeyCode;
 use winit::keyboard::PhysicalKey;
-use winit::window::{Window, WindowId};
-use yesod::agx;
+use winit::window::{CursorGrabMode, Window, WindowId};
+use yesod::agx::{agx_tonemap, AgXParameters};
+use yesod::camera::{Bindings, ControllerInput};
+use yesod::color::srgb_oetf;
+use yesod::tonemap::Tonemapper;
 
 pub struct Runtime {
     swapchain: Swapchain,
@@ -23,7 +25,13 @@ pub struct Runtime {
     overlay_texture: Tex2d<Vec4<f32>>,
     tonemap_display: Kernel<fn(Tex2d<Vec4<f32>>, bool)>,
     perform_tonemapping: bool,
+    pub pacer: FramePacer,
     pub mouse_scroll: Vec2<f32>,
+    // Raw mouse movement since the last frame, which continues while the cursor is grabbed.
+    pub mouse_motion: Vec2<f32>,
+    // Hides the cursor and locks it to the window, for mouse-look.
+    pub grab_cursor: bool,
+    cursor_grabbed: bool,
     pub keys_down: HashSet<KeyCode>,
     pub keys_pressed: HashSet<KeyCode>,
     pub buttons_down: HashSet<MouseButton>,
@@ -119,6 +127,23 @@ impl Runtime {
             self.cursor_position
         }
     }
+    // Input for a camera controller: held keys are mapped through `bindings`, raw mouse motion
+    // looks around while the cursor is grabbed, and dragging with the left button pans.
+    pub fn camera_input(&self, bindings: &Bindings<KeyCode>) -> ControllerInput {
+        let (look, pan) = if self.cursor_grabbed {
+            (self.mouse_motion, Vec2::splat(0.0))
+        } else if self.button_down(MouseButton::Left) {
+            (Vec2::splat(0.0), self.cursor_velocity())
+        } else {
+            (Vec2::splat(0.0), Vec2::splat(0.0))
+        };
+        ControllerInput {
+            actions: bindings.actions(&self.keys_down),
+            look: [look.x, look.y].into(),
+            pan: [pan.x, pan.y].into(),
+            scroll: self.mouse_scroll.y,
+        }
+    }
     pub fn width(&self) -> u32 {
         self.grid_size[0]
     }
@@ -202,6 +227,17 @@ struct RunningApp<F: FnMut(&mut Runtime)> {
 }
 impl<F: FnMut(&mut Runtime)> ApplicationHandler for RunningApp<F> {
     fn resumed(&mut self, _event_loop: &ActiveEventLoop) {}
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.runtime.mouse_motion.x += delta.0 as f32;
+            self.runtime.mouse_motion.y += delta.1 as f32;
+        }
+    }
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
@@ -287,7 +323,7 @@ impl<F: FnMut(&mut Runtime)> ApplicationHandler for RunningApp<F> {
                 runtime.last_frame_time = (start - runtime.last_frame_start_time).as_secs_f64();
                 runtime.last_frame_start_time = start;
                 (self.update_fn)(runtime);
-                DEVICE.default_stream().scope().synchronize();
+                runtime.pacer.end_frame();
                 let delta = start.elapsed().as_secs_f64();
                 runtime.average_frame_time = runtime.average_frame_time * 0.99 + delta * 0.01;
                 runtime.last_frame_time = delta;
@@ -300,6 +336,7 @@ impl<F: FnMut(&mut Runtime)> ApplicationHandler for RunningApp<F> {
                 {
                     runtime.resize_time = None;
                     let size = window.inner_size();
+                    runtime.pacer.wait_idle();
                     // Windows does not allow multiple swapchains.
                     // It also recreates the actual swapchain and auto-stretches the texture for some reason.
                     take(&mut runtime.swapchain, |swapchain| {
@@ -330,6 +367,23 @@ impl<F: FnMut(&mut Runtime)> ApplicationHandler for RunningApp<F> {
                 runtime.keys_pressed.clear();
                 runtime.buttons_pressed.clear();
                 runtime.mouse_scroll = Vec2::splat(0.0);
+                runtime.mouse_motion = Vec2::splat(0.0);
+
+                if runtime.grab_cursor != runtime.cursor_grabbed {
+                    let mode = if runtime.grab_cursor {
+                        CursorGrabMode::Locked
+                    } else {
+                        CursorGrabMode::None
+                    };
+                    // Not every platform supports locking the cursor in place.
+                    let result = window
+                        .set_cursor_grab(mode)
+                        .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined));
+                    if result.is_ok() {
+                        window.set_cursor_visible(!runtime.grab_cursor);
+                        runtime.cursor_grabbed = runtime.grab_cursor;
+                    }
+                }
 
                 #[cfg(feature = "video")]
                 if let Some((encoder, position)) = &mut runtime.encoder {
@@ -401,12 +455,14 @@ impl App {
             name: name.into(),
             grid_size,
             scale: 1,
-            agx: None,
+            tonemapper: Tonemapper::Linear,
             gamma: 2.2,
+            srgb: false,
             resize: false,
             hide_cursor: false,
             adjust_dpi: false,
             dither: None,
+            frames_in_flight: 1,
         }
     }
 }
@@ -415,30 +471,41 @@ pub struct AppBuilder {
     pub name: String,
     pub grid_size: [u32; 2],
     pub scale: u32,
-    pub agx: Option<Option<AgXParameters>>,
+    pub tonemapper: Tonemapper,
     pub gamma: f32,
+    pub srgb: bool,
     pub resize: bool,
     pub hide_cursor: bool,
     pub adjust_dpi: bool,
     pub dither: Option<u32>,
+    pub frames_in_flight: usize,
 }
 impl AppBuilder {
     pub fn scale(mut self, scale: u32) -> Self {
         self.scale = scale;
         self
     }
-    pub fn agx(mut self) -> Self {
-        self.agx = Some(None);
+    /// Tonemaps the display, before encoding it with the gamma. [`Tonemapper::AgX`] is used
+    /// without the encoding, as it outputs display-encoded values.
+    pub fn tonemapper(mut self, tonemapper: Tonemapper) -> Self {
+        self.tonemapper = tonemapper;
         self
     }
-    pub fn agx_params(mut self, params: AgXParameters) -> Self {
-        self.agx = Some(Some(params));
-        self
+    pub fn agx(self) -> Self {
+        self.tonemapper(Tonemapper::AgX(None))
+    }
+    pub fn agx_params(self, params: AgXParameters) -> Self {
+        self.tonemapper(Tonemapper::AgX(Some(params)))
     }
     pub fn gamma(mut self, gamma: f32) -> Self {
         self.gamma = gamma;
         self
     }
+    /// Encodes the display with the sRGB transfer function rather than a pure gamma.
+    pub fn srgb(mut self) -> Self {
+        self.srgb = true;
+        self
+    }
     pub fn resize(mut self) -> Self {
         self.resize = true;
         self
@@ -458,6 +525,12 @@ impl AppBuilder {
         self.dither = Some(bayer_size);
         self
     }
+    /// Allows recording the next frames while the device is still executing previous ones.
+    /// Defaults to 1, which waits for every frame to finish.
+    pub fn frames_in_flight(mut self, frames: usize) -> Self {
+        self.frames_in_flight = frames;
+        self
+    }
     pub fn init(self) -> App {
         keter::init_logger();
 
@@ -468,12 +541,14 @@ impl AppBuilder {
             name,
             grid_size,
             mut scale,
-            agx,
+            tonemapper,
             gamma,
+            srgb,
             resize,
             hide_cursor,
             adjust_dpi,
             dither,
+            frames_in_flight,
         } = self;
 
         let mut w = grid_size[0] * scale;
@@ -548,14 +623,22 @@ impl AppBuilder {
         let tonemap_display = DEVICE.create_kernel_async::<fn(Tex2d<Vec4<f32>>, bool)>(&track!(
             |display_texture, perform_tonemapping| {
                 let value = staging_texture.read(dispatch_id().xy());
-                let value = if let Some(params) = agx {
-                    if perform_tonemapping {
-                        agx::agx_tonemap(value, params)
+                let encoded = |value: Expr<Vec3<f32>>| {
+                    if srgb {
+                        srgb_oetf(value)
                     } else {
                         value.powf(1.0 / gamma)
                     }
+                };
+                let value = if perform_tonemapping {
+                    if let Tonemapper::AgX(params) = tonemapper {
+                        // AgX already outputs display-encoded values, so it skips the encoding.
+                        agx_tonemap(value, params)
+                    } else {
+                        encoded(tonemapper.tonemap(value))
+                    }
                 } else {
-                    value.powf(1.0 / gamma)
+                    encoded(value)
                 };
                 for i in 0..scale {
                     for j in 0..scale {
@@ -588,6 +671,7 @@ impl AppBuilder {
                 overlay_texture,
                 tonemap_display,
                 perform_tonemapping: true,
+                pacer: FramePacer::new(frames_in_flight),
                 keys_down: HashSet::new(),
                 keys_pressed: HashSet::new(),
                 buttons_down: HashSet::new(),
@@ -595,6 +679,9 @@ impl AppBuilder {
                 cursor_position: Vec2::splat(f32::NEG_INFINITY),
                 last_cursor_position: Vec2::splat(f32::NEG_INFINITY),
                 mouse_scroll: Vec2::splat(0.0),
+                mouse_motion: Vec2::splat(0.0),
+                grab_cursor: false,
+                cursor_grabbed: false,
                 tick: 0,
                 average_frame_time: 0.016,
                 last_frame_start_time: Instant::now(),
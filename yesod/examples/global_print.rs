@@ -21,5 +21,5 @@ fn main() {
     test_fn.dispatch([4, 1, 1], &0.7);
     DEVICE.default_stream().scope().synchronize();
     println!("Finished");
-    flush_printer();
+    flush_printer().unwrap();
 }
@@ -22,5 +22,5 @@ fn main() {
     test_fn.dispatch([4, 1, 1], &0.7);
     DEVICE.default_stream().scope().synchronize();
     println!("Finished");
-    print_buffer.flush();
+    print_buffer.flush().unwrap();
 }
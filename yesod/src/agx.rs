@@ -43,36 +43,62 @@ fn agx_default_contrast_approx(x: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
         - 0.00232
 }
 
+#[allow(clippy::excessive_precision)]
+const AGX_MAT: [[f32; 3]; 3] = [
+    [0.842479062253094, 0.0423282422610123, 0.0423756549057051],
+    [0.0784335999999992, 0.878468636469772, 0.0784336],
+    [0.0792237451477643, 0.0791661274605434, 0.879142973793104],
+];
+#[allow(clippy::excessive_precision)]
+const AGX_MAT_INV: [[f32; 3]; 3] = [
+    [1.19687900512017, -0.0528968517574562, -0.0529716355144438],
+    [-0.0980208811401368, 1.15190312990417, -0.0980434501171241],
+    [-0.0990297440797205, -0.0989611768448433, 1.15107367264116],
+];
+const MIN_EV: f32 = -12.47393;
+const MAX_EV: f32 = 4.026069;
+
 #[tracked]
 pub fn agx(color: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
-    #[allow(clippy::excessive_precision)]
-    let agx_mat = Mat3::from_column_array(&[
-        [0.842479062253094, 0.0423282422610123, 0.0423756549057051],
-        [0.0784335999999992, 0.878468636469772, 0.0784336],
-        [0.0792237451477643, 0.0791661274605434, 0.879142973793104],
-    ]);
-    let min_ev = -12.47393_f32;
-    let max_ev = 4.026069_f32;
-
-    let color = agx_mat.expr() * color;
-    let color = color.log2().clamp(min_ev, max_ev);
-    let color = (color - min_ev) / (max_ev - min_ev);
+    let color = Mat3::from_column_array(&AGX_MAT).expr() * color;
+    let color = color.log2().clamp(MIN_EV, MAX_EV);
+    let color = (color - MIN_EV) / (MAX_EV - MIN_EV);
     agx_default_contrast_approx(color)
 }
 
 #[tracked]
 pub fn agx_eotf(color: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
-    #[allow(clippy::excessive_precision)]
-    let agx_mat_inv = Mat3::from_column_array(&[
-        [1.19687900512017, -0.0528968517574562, -0.0529716355144438],
-        [-0.0980208811401368, 1.15190312990417, -0.0980434501171241],
-        [-0.0990297440797205, -0.0989611768448433, 1.15107367264116],
-    ]);
-
-    agx_mat_inv.expr() * color
+    Mat3::from_column_array(&AGX_MAT_INV).expr() * color
     // No need to linearize since outputting to sRGB.
 }
 
+/// The inverse of [`agx`], by bisecting the contrast curve.
+///
+/// Values outside of the range of the curve are clamped, so colors beyond the exposure range of
+/// [`agx`] are not recovered.
+#[tracked]
+pub fn agx_inverse(color: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let lower = Vec3::splat_expr(0.0_f32).var();
+    let upper = Vec3::splat_expr(1.0_f32).var();
+    for _ in 0_u32..24 {
+        let mid = (**lower + **upper) * 0.5;
+        let below = agx_default_contrast_approx(mid) < color;
+        *lower = below.select(mid, **lower);
+        *upper = below.select(**upper, mid);
+    }
+    let color = (**lower + **upper) * 0.5;
+    let color = (color * (MAX_EV - MIN_EV) + MIN_EV).exp2();
+    let inverse = glam::Mat3::from_cols_array_2d(&AGX_MAT).inverse();
+    Mat3::from(inverse).expr() * color
+}
+
+/// The inverse of [`agx_eotf`].
+#[tracked]
+pub fn agx_eotf_inverse(color: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let inverse = glam::Mat3::from_cols_array_2d(&AGX_MAT_INV).inverse();
+    Mat3::from(inverse).expr() * color
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Value, PartialEq)]
 pub struct AgXParameters {
@@ -130,4 +156,35 @@ pub fn agx_tonemap(color: Expr<Vec3<f32>>, params: Option<AgXParameters>) -> Exp
     agx_eotf(color)
 }
 
-// For inverse: https://discord.com/channels/318590007881236480/714940749707214890/1454589752987877592
+/// Recovers the scene-referred color from the output of [`agx_tonemap`] without a look, such as
+/// to composite tonemapped UI back into the scene.
+///
+/// Looks aren't generally invertible, and so are not supported.
+// Based on https://discord.com/channels/318590007881236480/714940749707214890/1454589752987877592
+#[tracked]
+pub fn agx_tonemap_inverse(color: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    agx_inverse(agx_eotf_inverse(color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::eval;
+
+    #[test]
+    fn inverse_round_trips() {
+        let results = eval::<Vec3<f32>>(
+            64,
+            track!(|i| {
+                let i = i.cast_f32();
+                let color = Vec3::expr(0.05, 0.2, 1.0) * (i * 0.1 - 4.0).exp2();
+                let round_trip = agx_tonemap_inverse(agx_tonemap(color, None));
+                ((round_trip - color) / color).abs()
+            }),
+        );
+        for (i, error) in results.into_iter().enumerate() {
+            let error = error.x.max(error.y).max(error.z);
+            assert!(error < 1e-3, "Relative error of {error} at {i}.");
+        }
+    }
+}
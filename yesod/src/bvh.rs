@@ -0,0 +1,576 @@
+use glam::Vec3 as FVec3;
+use keter::lang::types::vector::Vec3;
+use keter::prelude::*;
+use keter::runtime::Device;
+
+use crate::printer::PushBytes;
+use crate::shapes::{Hit, intersect_aabb, intersect_triangle};
+
+const BINS: usize = 16;
+const MAX_LEAF_SIZE: usize = 8;
+/// The maximum depth of a [`Bvh`], which bounds the traversal stack.
+pub const MAX_DEPTH: usize = 64;
+// The cost of traversing a node, relative to intersecting a triangle.
+const TRAVERSAL_COST: f32 = 1.0;
+
+/// A node of a flattened [`Bvh`], in depth-first order.
+///
+/// Interior nodes have `count == 0`, with the first child directly after the node and the
+/// second child at `offset`. The first child is the lower one along `axis`, which is used to
+/// visit the nearer child first. Leaves hold `count` triangles starting at `offset`.
+#[derive(Debug, Clone, Copy, Value, PushBytes)]
+#[repr(C)]
+pub struct BvhNode {
+    pub min: Vec3<f32>,
+    pub max: Vec3<f32>,
+    pub offset: u32,
+    pub count: u32,
+    pub axis: u32,
+}
+
+/// A triangle in the order used by the [`Bvh`], with its index in the original list.
+#[derive(Debug, Clone, Copy, Value, PushBytes)]
+#[repr(C)]
+pub struct BvhTriangle {
+    pub v0: Vec3<f32>,
+    pub v1: Vec3<f32>,
+    pub v2: Vec3<f32>,
+    pub index: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Aabb {
+    min: FVec3,
+    max: FVec3,
+}
+impl Aabb {
+    const EMPTY: Self = Self {
+        min: FVec3::INFINITY,
+        max: FVec3::NEG_INFINITY,
+    };
+    fn grow(&mut self, other: &Aabb) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+    fn grow_point(&mut self, p: FVec3) {
+        self.min = self.min.min(p);
+        self.max = self.max.max(p);
+    }
+    fn area(&self) -> f32 {
+        let size = (self.max - self.min).max(FVec3::ZERO);
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Primitive {
+    bounds: Aabb,
+    centroid: FVec3,
+    index: u32,
+}
+
+/// A bounding volume hierarchy over a list of triangles, built on the host with the surface
+/// area heuristic.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    pub triangles: Vec<BvhTriangle>,
+}
+impl Bvh {
+    // Wald 2007, On fast Construction of SAH-based Bounding Volume Hierarchies.
+    pub fn build(triangles: &[[FVec3; 3]]) -> Self {
+        let mut primitives = triangles
+            .iter()
+            .enumerate()
+            .map(|(index, triangle)| {
+                let mut bounds = Aabb::EMPTY;
+                for &v in triangle {
+                    bounds.grow_point(v);
+                }
+                Primitive {
+                    bounds,
+                    centroid: (bounds.min + bounds.max) / 2.0,
+                    index: index as u32,
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut nodes = vec![];
+        if !primitives.is_empty() {
+            build_node(&mut nodes, &mut primitives, 0, 0);
+        }
+        let triangles = primitives
+            .iter()
+            .map(|primitive| {
+                let [v0, v1, v2] = triangles[primitive.index as usize];
+                BvhTriangle {
+                    v0: v0.into(),
+                    v1: v1.into(),
+                    v2: v2.into(),
+                    index: primitive.index,
+                }
+            })
+            .collect();
+        Self { nodes, triangles }
+    }
+    pub fn upload(&self) -> DeviceBvh {
+        self.upload_to(&DEVICE)
+    }
+    pub fn upload_to(&self, device: &Device) -> DeviceBvh {
+        // Empty buffers aren't allowed, so an empty hierarchy gets placeholders, which are never
+        // read since traversal checks for it on the host.
+        let nodes = if self.nodes.is_empty() {
+            vec![BvhNode {
+                min: Vec3::splat(f32::INFINITY),
+                max: Vec3::splat(f32::NEG_INFINITY),
+                offset: 0,
+                count: 0,
+                axis: 0,
+            }]
+        } else {
+            self.nodes.clone()
+        };
+        let triangles = if self.triangles.is_empty() {
+            vec![BvhTriangle {
+                v0: Vec3::splat(0.0),
+                v1: Vec3::splat(0.0),
+                v2: Vec3::splat(0.0),
+                index: 0,
+            }]
+        } else {
+            self.triangles.clone()
+        };
+        DeviceBvh {
+            nodes: device.create_buffer_from_slice(&nodes),
+            triangles: device.create_buffer_from_slice(&triangles),
+            empty: self.nodes.is_empty(),
+        }
+    }
+}
+
+fn node_bounds(primitives: &[Primitive]) -> (Aabb, Aabb) {
+    let mut bounds = Aabb::EMPTY;
+    let mut centroid_bounds = Aabb::EMPTY;
+    for primitive in primitives {
+        bounds.grow(&primitive.bounds);
+        centroid_bounds.grow_point(primitive.centroid);
+    }
+    (bounds, centroid_bounds)
+}
+
+// Finds the cheapest binned split, returning the axis, the bin to split after and its cost.
+fn find_split(primitives: &[Primitive], centroid_bounds: &Aabb) -> Option<(usize, usize, f32)> {
+    let extent = centroid_bounds.max - centroid_bounds.min;
+    let mut best: Option<(usize, usize, f32)> = None;
+    for axis in 0..3 {
+        if extent[axis] <= 0.0 {
+            continue;
+        }
+        let mut bins = [(Aabb::EMPTY, 0_usize); BINS];
+        for primitive in primitives {
+            let bin = bin_index(primitive.centroid, centroid_bounds, axis);
+            bins[bin].0.grow(&primitive.bounds);
+            bins[bin].1 += 1;
+        }
+        // The area and count of everything to the right of each split.
+        let mut right = [(0.0, 0); BINS];
+        let mut bounds = Aabb::EMPTY;
+        let mut count = 0;
+        for i in (1..BINS).rev() {
+            bounds.grow(&bins[i].0);
+            count += bins[i].1;
+            right[i - 1] = (bounds.area(), count);
+        }
+        let mut bounds = Aabb::EMPTY;
+        let mut count = 0;
+        for i in 0..BINS - 1 {
+            bounds.grow(&bins[i].0);
+            count += bins[i].1;
+            let (right_area, right_count) = right[i];
+            if count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = bounds.area() * count as f32 + right_area * right_count as f32;
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, i, cost));
+            }
+        }
+    }
+    best
+}
+
+fn bin_index(centroid: FVec3, centroid_bounds: &Aabb, axis: usize) -> usize {
+    let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+    let relative = (centroid[axis] - centroid_bounds.min[axis]) / extent;
+    ((relative * BINS as f32) as usize).min(BINS - 1)
+}
+
+// Builds the subtree over `primitives`, which start at `offset` in the final order.
+fn build_node(
+    nodes: &mut Vec<BvhNode>,
+    primitives: &mut [Primitive],
+    offset: usize,
+    depth: usize,
+) -> usize {
+    debug_assert!(depth < MAX_DEPTH, "BVH exceeded the maximum depth.");
+    let (bounds, centroid_bounds) = node_bounds(primitives);
+    let index = nodes.len();
+    nodes.push(BvhNode {
+        min: bounds.min.into(),
+        max: bounds.max.into(),
+        offset: offset as u32,
+        count: primitives.len() as u32,
+        axis: 0,
+    });
+    if primitives.len() == 1 {
+        return index;
+    }
+
+    // Median splits reach single primitives within `ceil(log2(len))` levels, so they are used
+    // once the remaining depth gets close to that, such as for long chains of triangles.
+    let median_depth = primitives.len().next_power_of_two().trailing_zeros() as usize;
+    let (axis, mid) = if depth + median_depth + 1 >= MAX_DEPTH {
+        let extent = centroid_bounds.max - centroid_bounds.min;
+        let axis = (0..3)
+            .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+            .unwrap();
+        let mid = primitives.len() / 2;
+        primitives
+            .select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+        (axis, mid)
+    } else {
+        let leaf_cost = primitives.len() as f32;
+        let split = find_split(primitives, &centroid_bounds).filter(|&(_, _, cost)| {
+            TRAVERSAL_COST + cost / bounds.area() < leaf_cost || primitives.len() > MAX_LEAF_SIZE
+        });
+        match split {
+            Some((axis, bin, _)) => (
+                axis,
+                partition(primitives, |p| {
+                    bin_index(p.centroid, &centroid_bounds, axis) <= bin
+                }),
+            ),
+            // Only happens when every centroid is the same, and the leaf would be too large.
+            None if primitives.len() > MAX_LEAF_SIZE => (0, primitives.len() / 2),
+            None => return index,
+        }
+    };
+
+    let (left, right) = primitives.split_at_mut(mid);
+    build_node(nodes, left, offset, depth + 1);
+    let second = build_node(nodes, right, offset + mid, depth + 1);
+    nodes[index].offset = second as u32;
+    nodes[index].count = 0;
+    nodes[index].axis = axis as u32;
+    index
+}
+
+fn partition<T>(values: &mut [T], f: impl Fn(&T) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..values.len() {
+        if f(&values[i]) {
+            values.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+/// A [`Bvh`] on the device.
+#[derive(Debug)]
+pub struct DeviceBvh {
+    pub nodes: Buffer<BvhNode>,
+    pub triangles: Buffer<BvhTriangle>,
+    empty: bool,
+}
+impl DeviceBvh {
+    // Calls `f` with every triangle in a leaf whose bounds overlap the ray before `t_max`,
+    // stopping if it returns true. `t_max` is evaluated again for every node.
+    fn traverse(
+        &self,
+        start: Expr<Vec3<f32>>,
+        dir: Expr<Vec3<f32>>,
+        t_max: impl Fn() -> Expr<f32>,
+        f: impl Fn(Expr<BvhTriangle>) -> Expr<bool>,
+    ) {
+        if !self.empty {
+            self.traverse_nodes(start, dir, t_max, f);
+        }
+    }
+    #[tracked]
+    fn traverse_nodes(
+        &self,
+        start: Expr<Vec3<f32>>,
+        dir: Expr<Vec3<f32>>,
+        t_max: impl Fn() -> Expr<f32>,
+        f: impl Fn(Expr<BvhTriangle>) -> Expr<bool>,
+    ) {
+        let inv_dir = dir.recip();
+        let stack = <[u32; MAX_DEPTH]>::var_zeroed();
+        let stack_size = 0_u32.var();
+        let node_index = 0_u32.var();
+        let done = false.var();
+        loop {
+            let node = self.nodes.read(**node_index);
+            let interval = intersect_aabb(start, inv_dir, node.min, node.max);
+            let visit = interval.x <= interval.y && interval.y >= 0.0 && interval.x <= t_max();
+            if visit && node.count == 0 {
+                // Visit the nearer child first, and return to the other later.
+                let negative = if node.axis == 0 {
+                    dir.x < 0.0
+                } else if node.axis == 1 {
+                    dir.y < 0.0
+                } else {
+                    dir.z < 0.0
+                };
+                let first = **node_index + 1;
+                if negative {
+                    stack.write(**stack_size, first);
+                    *node_index = node.offset;
+                } else {
+                    stack.write(**stack_size, node.offset);
+                    *node_index = first;
+                }
+                *stack_size += 1;
+            } else {
+                if visit {
+                    let i = node.offset.var();
+                    loop {
+                        if **i >= node.offset + node.count {
+                            break;
+                        }
+                        if f(self.triangles.read(**i)) {
+                            *done = true;
+                            break;
+                        }
+                        *i += 1;
+                    }
+                }
+                if **done || **stack_size == 0 {
+                    break;
+                }
+                *stack_size -= 1;
+                *node_index = stack.read(**stack_size);
+            }
+        }
+    }
+    /// Returns the closest hit before `t_max` and the original index of the triangle that was
+    /// hit, which is only valid for hits.
+    #[tracked]
+    pub fn closest_hit(
+        &self,
+        start: Expr<Vec3<f32>>,
+        dir: Expr<Vec3<f32>>,
+        t_max: impl AsExpr<Value = f32>,
+    ) -> (Expr<Hit>, Expr<u32>) {
+        let closest = t_max.as_expr().var();
+        let hit = Hit::miss();
+        let index = 0_u32.var();
+        self.traverse(
+            start,
+            dir,
+            || **closest,
+            |triangle| {
+                let candidate =
+                    intersect_triangle(start, dir, triangle.v0, triangle.v1, triangle.v2);
+                if candidate.t < **closest {
+                    *closest = candidate.t;
+                    *hit = candidate;
+                    *index = triangle.index;
+                }
+                false.expr()
+            },
+        );
+        (**hit, **index)
+    }
+    /// Returns whether any triangle is hit before `t_max`, stopping at the first one found.
+    #[tracked]
+    pub fn any_hit(
+        &self,
+        start: Expr<Vec3<f32>>,
+        dir: Expr<Vec3<f32>>,
+        t_max: impl AsExpr<Value = f32>,
+    ) -> Expr<bool> {
+        let t_max = t_max.as_expr();
+        let hit = false.var();
+        self.traverse(
+            start,
+            dir,
+            || t_max,
+            |triangle| {
+                let candidate =
+                    intersect_triangle(start, dir, triangle.v0, triangle.v1, triangle.v2);
+                *hit = candidate.t < t_max;
+                **hit
+            },
+        );
+        **hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use keter::lang::types::vector::Vec2;
+
+    use super::*;
+    use crate::rand::Rng;
+    use crate::tests::{CPU_DEVICE, eval};
+
+    fn random_triangles(count: usize, seed: u64) -> Vec<[FVec3; 3]> {
+        let mut rng = Rng::new(seed, 0);
+        let mut point = |scale: f32| {
+            FVec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32()) * scale - scale / 2.0
+        };
+        (0..count)
+            .map(|_| {
+                let center = point(10.0);
+                [
+                    center + point(1.0),
+                    center + point(1.0),
+                    center + point(1.0),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn structure() {
+        let triangles = random_triangles(1000, 0);
+        let bvh = Bvh::build(&triangles);
+        let mut seen = vec![false; triangles.len()];
+        for triangle in &bvh.triangles {
+            assert!(!seen[triangle.index as usize]);
+            seen[triangle.index as usize] = true;
+        }
+        assert!(seen.iter().all(|&x| x));
+        // Every child lies within its parent.
+        let v = |x: Vec3<f32>| FVec3::new(x.x, x.y, x.z);
+        for (i, node) in bvh.nodes.iter().enumerate() {
+            if node.count == 0 {
+                for child in [&bvh.nodes[i + 1], &bvh.nodes[node.offset as usize]] {
+                    assert!(v(node.min).cmple(v(child.min)).all());
+                    assert!(v(child.max).cmple(v(node.max)).all());
+                }
+            }
+        }
+    }
+
+    fn depth(bvh: &Bvh, node: usize) -> usize {
+        let node_ref = &bvh.nodes[node];
+        if node_ref.count == 0 {
+            1 + depth(bvh, node + 1).max(depth(bvh, node_ref.offset as usize))
+        } else {
+            1
+        }
+    }
+
+    #[test]
+    fn degenerate_chain() {
+        // Exponentially spaced triangles, for which each binned split only separates the last.
+        let triangles = (0..400)
+            .map(|i| {
+                let x = 1.05_f32.powi(i);
+                [
+                    FVec3::new(x, 0.0, 0.0),
+                    FVec3::new(x + 0.1, 0.0, 0.0),
+                    FVec3::new(x, 0.1, 0.0),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let bvh = Bvh::build(&triangles);
+        assert_eq!(bvh.triangles.len(), triangles.len());
+        assert!(depth(&bvh, 0) <= MAX_DEPTH);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let triangles = random_triangles(500, 1);
+        let bvh = Bvh::build(&triangles).upload_to(&CPU_DEVICE);
+        let original = triangles
+            .iter()
+            .enumerate()
+            .map(|(index, &[v0, v1, v2])| BvhTriangle {
+                v0: v0.into(),
+                v1: v1.into(),
+                v2: v2.into(),
+                index: index as u32,
+            })
+            .collect::<Vec<_>>();
+        let brute_force = CPU_DEVICE.create_buffer_from_slice(&original);
+        let results = eval::<Vec3<f32>>(
+            4096,
+            track!(|i| {
+                let rng = Rng::new_var(7_u64, i.cast_u64());
+                let start = (rng.next_vec3() - 0.5) * 16.0;
+                let dir = (rng.next_vec3() - 0.5).normalize();
+                let (hit, index) = bvh.closest_hit(start, dir, 100.0_f32);
+                let any = bvh.any_hit(start, dir, 100.0_f32);
+                let expected = brute_force_hit(&brute_force, start, dir, triangles.len() as u32);
+                let matches =
+                    hit.t == expected.x && (!hit.is_hit() || index.cast_f32() == expected.y);
+                Vec3::expr(matches.cast_f32(), any.cast_f32(), hit.is_hit().cast_f32())
+            }),
+        );
+        let mut hits = 0;
+        for result in results {
+            assert_eq!(result.x, 1.0);
+            assert_eq!(result.y, result.z);
+            hits += result.z as u32;
+        }
+        // Enough rays should hit for the test to be meaningful.
+        assert!(hits > 200, "Only {hits} rays hit.");
+    }
+
+    #[test]
+    fn empty() {
+        let bvh = Bvh::build(&[]);
+        assert!(bvh.nodes.is_empty());
+        assert!(bvh.triangles.is_empty());
+        let bvh = bvh.upload_to(&CPU_DEVICE);
+        let results = eval::<Vec2<f32>>(
+            64,
+            track!(|i| {
+                let rng = Rng::new_var(3_u64, i.cast_u64());
+                let start = (rng.next_vec3() - 0.5) * 16.0;
+                let dir = (rng.next_vec3() - 0.5).normalize();
+                let (hit, _) = bvh.closest_hit(start, dir, 100.0_f32);
+                let any = bvh.any_hit(start, dir, 100.0_f32);
+                Vec2::expr(hit.is_hit().cast_f32(), any.cast_f32())
+            }),
+        );
+        for result in results {
+            assert_eq!(result.x, 0.0);
+            assert_eq!(result.y, 0.0);
+        }
+    }
+
+    // Returns the closest distance and original index over every triangle.
+    #[tracked]
+    fn brute_force_hit(
+        triangles: &Buffer<BvhTriangle>,
+        start: Expr<Vec3<f32>>,
+        dir: Expr<Vec3<f32>>,
+        count: u32,
+    ) -> Expr<Vec2<f32>> {
+        let closest = 100.0_f32.var();
+        let index = 0_u32.var();
+        let i = 0_u32.var();
+        loop {
+            if **i >= count {
+                break;
+            }
+            let triangle = triangles.read(**i);
+            let hit = intersect_triangle(start, dir, triangle.v0, triangle.v1, triangle.v2);
+            if hit.t < **closest {
+                *closest = hit.t;
+                *index = triangle.index;
+            }
+            *i += 1;
+        }
+        let t = if **closest < 100.0 {
+            **closest
+        } else {
+            f32::INFINITY.expr()
+        };
+        Vec2::expr(t, index.cast_f32())
+    }
+}
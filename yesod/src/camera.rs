@@ -1,41 +1,236 @@
 use std::collections::HashSet;
+use std::f32::consts::TAU;
+use std::io;
+use std::path::Path;
 
-use glam::{Mat3 as FMat3, Vec2 as FVec2, Vec3 as FVec3};
-use keter::lang::types::vector::{Mat3, Vec2, Vec3};
+use glam::{Mat3 as FMat3, Mat4 as FMat4, Vec2 as FVec2, Vec3 as FVec3, Vec4 as FVec4};
+use keter::lang::types::vector::{Mat3, Mat4, Vec2, Vec3, Vec4};
 use keter::prelude::*;
 use winit::keyboard::KeyCode;
 
+use crate::printer::PushBytes;
+use crate::rand::hash::{murmur3, unit_vec2, xxhash32};
+use crate::rand::{GOLDEN_ROOTS, halton};
+
+mod controller;
+pub use controller::{Action, Bindings, CameraController, ControllerInput, ControllerMode};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// `fov` is the vertical field of view, in radians.
+    Perspective { fov: f32 },
+    /// `height` is the vertical extent of the view, in world units.
+    Orthographic { height: f32 },
+}
+
+/// A sequence of sub-pixel offsets, for temporal antialiasing and progressive accumulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum Jitter {
+    /// Every ray passes through the pixel centre.
+    #[default]
+    None,
+    /// The Halton sequence in bases 2 and 3.
+    Halton,
+    /// The R2 sequence, which is well distributed for any number of samples.
+    R2,
+}
+impl Jitter {
+    /// The offset from the pixel centre for the given sample, in `[-0.5, 0.5)`.
+    pub fn offset(self, sample: u32) -> FVec2 {
+        // The first point of both sequences is at the corner, so it is skipped.
+        let index = sample.wrapping_add(1);
+        match self {
+            Jitter::None => FVec2::ZERO,
+            Jitter::Halton => {
+                FVec2::new(radical_inverse(index, 2), radical_inverse(index, 3)) - 0.5
+            }
+            Jitter::R2 => {
+                let ig = 1.0 / GOLDEN_ROOTS[1];
+                let max = u32::MAX as f64 + 1.0;
+                let x = index.wrapping_mul((ig * max) as u32);
+                let y = index.wrapping_mul((ig * ig * max) as u32);
+                FVec2::new(x as f32, y as f32) / max as f32 - 0.5
+            }
+        }
+    }
+}
+
+fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let mut scale = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        scale /= base as f32;
+        result += scale * (index % base) as f32;
+        index /= base;
+    }
+    result.min(1.0 - f32::EPSILON / 2.0)
+}
+
+/// A camera in a Z-up world.
+///
+/// View space has x pointing right, y pointing down and z pointing forward, matching pixel
+/// coordinates. NDC has y pointing up and depth in `[0, 1]` from the near to the far plane.
+/// `far` may be infinite, which orthographic projections replace with a far plane
+/// [`Camera::INFINITE_ORTHOGRAPHIC_DEPTH`] past the near plane.
+///
+/// A nonzero `aperture` gives depth of field with a thin lens of that radius, with objects at
+/// `focus_distance` along the view axis in focus. This is ignored for orthographic projections.
+///
+/// The vertical field of view used to be the `fov` field, and is now part of `projection`.
+/// Use [`Camera::fov`] to read it.
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub screen_size: FVec2,
     pub yaw: f32,
     pub pitch: f32,
     pub pos: FVec3,
-    pub fov: f32,
+    pub projection: Projection,
+    pub near: f32,
+    pub far: f32,
+    pub aperture: f32,
+    pub focus_distance: f32,
+    pub jitter: Jitter,
+}
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            screen_size: FVec2::ONE,
+            yaw: 0.0,
+            pitch: 0.0,
+            pos: FVec3::ZERO,
+            projection: Projection::Perspective { fov: 1.15 },
+            near: 0.01,
+            far: f32::INFINITY,
+            aperture: 0.0,
+            focus_distance: 1.0,
+            jitter: Jitter::None,
+        }
+    }
 }
 impl Camera {
+    /// The depth range of orthographic projections with an infinite far plane.
+    pub const INFINITE_ORTHOGRAPHIC_DEPTH: f32 = 1000.0;
+
     pub fn orbit(self, radius: f32) -> Self {
         Self {
             pos: self.pos + self.rotation() * FVec3::Z * radius,
             ..self
         }
     }
+    /// The vertical field of view, if the projection is perspective.
+    pub fn fov(&self) -> Option<f32> {
+        match self.projection {
+            Projection::Perspective { fov } => Some(fov),
+            Projection::Orthographic { .. } => None,
+        }
+    }
     pub fn rotation(&self) -> FMat3 {
         let view = FMat3::from_euler(glam::EulerRot::YXZ, self.yaw, self.pitch, 0.0).transpose();
         FMat3::from_cols(view.x_axis, view.z_axis, view.y_axis).transpose()
     }
-    pub fn view(&self) -> View {
+    // The columns are the right, down and forward directions.
+    fn transform(&self) -> FMat3 {
         let view = self.rotation();
-        let ratio = (self.fov / 2.0).tan() / (self.screen_size.y / 2.0);
-        let transform = FMat3::from_cols(view.x_axis, -view.y_axis, view.z_axis);
+        FMat3::from_cols(view.x_axis, -view.y_axis, view.z_axis)
+    }
+    pub fn view(&self) -> View {
+        let (scaling, orthographic) = match self.projection {
+            Projection::Perspective { fov } => {
+                ((fov / 2.0).tan() / (self.screen_size.y / 2.0), false)
+            }
+            Projection::Orthographic { height } => (height / self.screen_size.y, true),
+        };
+        let view = self.view_matrix();
+        let projection = self.projection_matrix();
+        let view_projection = projection * view;
 
         View {
             screen_size: self.screen_size.into(),
-            scaling: ratio,
+            scaling,
             pos: self.pos.into(),
-            transform: transform.into(),
+            transform: self.transform().into(),
+            orthographic,
+            aperture: self.aperture,
+            focus_distance: self.focus_distance,
+            jitter: self.jitter as u32,
+            view: view.into(),
+            projection: projection.into(),
+            view_projection: view_projection.into(),
+            inverse_view_projection: view_projection.inverse().into(),
         }
     }
+    /// The transformation from world space to view space.
+    pub fn view_matrix(&self) -> FMat4 {
+        FMat4::from_mat3(self.transform().transpose()) * FMat4::from_translation(-self.pos)
+    }
+    /// The transformation from view space to clip space.
+    pub fn projection_matrix(&self) -> FMat4 {
+        let aspect = self.screen_size.x / self.screen_size.y;
+        let (near, far) = (self.near, self.far);
+        match self.projection {
+            Projection::Perspective { fov } => {
+                let sy = 1.0 / (fov / 2.0).tan();
+                let (a, b) = if far.is_infinite() {
+                    (1.0, -near)
+                } else {
+                    (far / (far - near), -near * far / (far - near))
+                };
+                FMat4::from_cols(
+                    FVec4::new(sy / aspect, 0.0, 0.0, 0.0),
+                    FVec4::new(0.0, -sy, 0.0, 0.0),
+                    FVec4::new(0.0, 0.0, a, 1.0),
+                    FVec4::new(0.0, 0.0, b, 0.0),
+                )
+            }
+            Projection::Orthographic { height } => {
+                let far = if far.is_finite() {
+                    far
+                } else {
+                    near + Self::INFINITE_ORTHOGRAPHIC_DEPTH
+                };
+                let sy = 2.0 / height;
+                FMat4::from_cols(
+                    FVec4::new(sy / aspect, 0.0, 0.0, 0.0),
+                    FVec4::new(0.0, -sy, 0.0, 0.0),
+                    FVec4::new(0.0, 0.0, 1.0 / (far - near), 0.0),
+                    FVec4::new(0.0, 0.0, -near / (far - near), 1.0),
+                )
+            }
+        }
+    }
+    pub fn view_projection(&self) -> FMat4 {
+        self.projection_matrix() * self.view_matrix()
+    }
+    pub fn pixel_to_ndc(&self, pixel: FVec2) -> FVec2 {
+        let uv = pixel / self.screen_size * 2.0 - 1.0;
+        FVec2::new(uv.x, -uv.y)
+    }
+    pub fn ndc_to_pixel(&self, ndc: FVec2) -> FVec2 {
+        (FVec2::new(ndc.x, -ndc.y) + 1.0) / 2.0 * self.screen_size
+    }
+    /// Converts a view-space depth to the NDC depth stored in a depth buffer.
+    pub fn ndc_depth(&self, linear_depth: f32) -> f32 {
+        let clip = self.projection_matrix() * FVec4::new(0.0, 0.0, linear_depth, 1.0);
+        clip.z / clip.w
+    }
+    /// Converts an NDC depth back to the view-space depth.
+    pub fn linear_depth(&self, ndc_depth: f32) -> f32 {
+        let projection = self.projection_matrix();
+        let (a, b) = (projection.z_axis.z, projection.w_axis.z);
+        let (c, d) = (projection.z_axis.w, projection.w_axis.w);
+        (b - ndc_depth * d) / (ndc_depth * c - a)
+    }
+    /// Returns the pixel position and NDC depth of a point.
+    pub fn project(&self, world_pos: FVec3) -> FVec3 {
+        let ndc = self.view_projection().project_point3(world_pos);
+        self.ndc_to_pixel(ndc.truncate()).extend(ndc.z)
+    }
+    /// Returns the world position of a pixel with the given NDC depth.
+    pub fn unproject(&self, pixel: FVec2, ndc_depth: f32) -> FVec3 {
+        let ndc = self.pixel_to_ndc(pixel).extend(ndc_depth);
+        self.view_projection().inverse().project_point3(ndc)
+    }
     pub fn forward(&self) -> FVec3 {
         self.rotation().z_axis
     }
@@ -45,6 +240,76 @@ impl Camera {
     pub fn right(&self) -> FVec3 {
         self.rotation().x_axis
     }
+    pub fn up(&self) -> FVec3 {
+        -self.transform().y_axis
+    }
+    /// Writes the position, orientation and lens of the camera to a text file, so that a view
+    /// can be restored exactly. The screen size is not saved.
+    pub fn save_state(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let projection = match self.projection {
+            Projection::Perspective { fov } => format!("perspective {fov}"),
+            Projection::Orthographic { height } => format!("orthographic {height}"),
+        };
+        let jitter = match self.jitter {
+            Jitter::None => "none",
+            Jitter::Halton => "halton",
+            Jitter::R2 => "r2",
+        };
+        let pos = self.pos;
+        let lines = [
+            format!("pos {} {} {}", pos.x, pos.y, pos.z),
+            format!("yaw {}", self.yaw),
+            format!("pitch {}", self.pitch),
+            projection,
+            format!("near {}", self.near),
+            format!("far {}", self.far),
+            format!("aperture {}", self.aperture),
+            format!("focus_distance {}", self.focus_distance),
+            format!("jitter {jitter}"),
+        ];
+        std::fs::write(path, lines.join("\n") + "\n")
+    }
+    /// Reads a state written by [`Camera::save_state`], keeping the current screen size and
+    /// any values missing from the file.
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid camera state: {line}"),
+            )
+        };
+        let text = std::fs::read_to_string(path)?;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let value = |i: usize| {
+                words
+                    .get(i + 1)
+                    .and_then(|word| word.parse::<f32>().ok())
+                    .ok_or_else(|| invalid(line))
+            };
+            match words[0] {
+                "pos" => self.pos = FVec3::new(value(0)?, value(1)?, value(2)?),
+                "yaw" => self.yaw = value(0)?,
+                "pitch" => self.pitch = value(0)?,
+                "perspective" => self.projection = Projection::Perspective { fov: value(0)? },
+                "orthographic" => self.projection = Projection::Orthographic { height: value(0)? },
+                "near" => self.near = value(0)?,
+                "far" => self.far = value(0)?,
+                "aperture" => self.aperture = value(0)?,
+                "focus_distance" => self.focus_distance = value(0)?,
+                "jitter" => {
+                    self.jitter = match words.get(1).copied() {
+                        Some("none") => Jitter::None,
+                        Some("halton") => Jitter::Halton,
+                        Some("r2") => Jitter::R2,
+                        _ => return Err(invalid(line)),
+                    }
+                }
+                _ => return Err(invalid(line)),
+            }
+        }
+        Ok(())
+    }
     pub fn apply_movement(
         &mut self,
         keys: &HashSet<KeyCode>,
@@ -75,35 +340,270 @@ impl Camera {
     }
 }
 
-#[derive(Debug, Clone, Copy, Value)]
+#[derive(Debug, Clone, Copy, Value, PushBytes)]
 #[repr(C)]
 pub struct View {
     pub screen_size: Vec2<f32>,
     pub scaling: f32,
     pub pos: Vec3<f32>,
     pub transform: Mat3,
+    pub orthographic: bool,
+    pub aperture: f32,
+    pub focus_distance: f32,
+    pub jitter: u32,
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    pub inverse_view_projection: Mat4,
 }
 impl ViewExpr {
     #[tracked]
     pub fn facing(&self) -> Expr<Vec3<f32>> {
         self.transform.col(2)
     }
+    /// The pinhole ray direction through a pixel, which is only valid for perspective projections.
     #[tracked]
     pub fn ray_dir(&self, pixel: Expr<Vec2<f32>>) -> Expr<Vec3<f32>> {
         self.transform * ((pixel - self.screen_size / 2.0) * self.scaling).extend(1.0)
     }
+    /// Returns the pixel position and view-space depth of a point.
     #[tracked]
     pub fn project3(&self, world_pos: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
-        let local_pos = world_pos - self.pos;
-        // TODO: A little bit inefficient.
-        let view_pos = self.transform.transpose() * local_pos;
-        ((view_pos.xy() / view_pos.z) / self.scaling + self.screen_size / 2.0).extend(view_pos.z)
+        let depth = (self.view * world_pos.extend(1.0)).z;
+        self.project(world_pos).extend(depth)
     }
+    /// Returns the pixel position of a point, using the camera's projection.
     #[tracked]
     pub fn project(&self, world_pos: Expr<Vec3<f32>>) -> Expr<Vec2<f32>> {
-        let local_pos = world_pos - self.pos;
-        // TODO: A little bit inefficient.
-        let view_pos = self.transform.transpose() * local_pos;
-        (view_pos.xy() / view_pos.z) / self.scaling + self.screen_size / 2.0
+        self.ndc_to_pixel(self.project_ndc(world_pos).xy())
+    }
+    /// The offset from the pixel centre for the given sample, matching [`Jitter::offset`].
+    #[tracked]
+    pub fn jitter_offset(&self, sample: Expr<u32>) -> Expr<Vec2<f32>> {
+        let index = sample + 1;
+        let offset = Vec2::<f32>::splat_expr(0.5).var();
+        if self.jitter == Jitter::Halton as u32 {
+            *offset = Vec2::expr(halton(index, 2), halton(index, 3));
+        } else if self.jitter == Jitter::R2 as u32 {
+            *offset = crate::rand::r2(index);
+        }
+        **offset - 0.5
+    }
+    /// Returns the origin and normalized direction of a ray through a point on the screen, with
+    /// `lens` in `[0, 1)^2` choosing the point on the lens.
+    #[tracked]
+    pub fn ray_at(
+        &self,
+        pixel: Expr<Vec2<f32>>,
+        lens: Expr<Vec2<f32>>,
+    ) -> (Expr<Vec3<f32>>, Expr<Vec3<f32>>) {
+        let screen_pos = (pixel - self.screen_size / 2.0) * self.scaling;
+        if self.orthographic {
+            (
+                self.pos + self.transform * screen_pos.extend(0.0),
+                self.facing(),
+            )
+        } else {
+            let focus = self.transform * screen_pos.extend(1.0) * self.focus_distance;
+            let theta = TAU * lens.y;
+            let lens_pos = Vec2::expr(theta.cos(), theta.sin()) * lens.x.sqrt() * self.aperture;
+            let offset = self.transform * lens_pos.extend(0.0);
+            (self.pos + offset, (focus - offset).normalize())
+        }
+    }
+    /// Returns the origin and normalized direction of a ray for the given pixel and sample index,
+    /// with the sub-pixel position chosen by the camera's [`Jitter`] and the lens position by a
+    /// Halton sequence rotated per pixel.
+    #[tracked]
+    pub fn ray(
+        &self,
+        pixel: Expr<Vec2<u32>>,
+        sample: Expr<u32>,
+    ) -> (Expr<Vec3<f32>>, Expr<Vec3<f32>>) {
+        let index = sample + 1;
+        let rotation = unit_vec2(Vec2::expr(xxhash32(pixel), murmur3(pixel)));
+        let lens = (Vec2::expr(halton(index, 5), halton(index, 7)) + rotation).fract();
+        self.ray_at(pixel.cast_f32() + 0.5 + self.jitter_offset(sample), lens)
+    }
+    #[tracked]
+    pub fn pixel_to_ndc(&self, pixel: Expr<Vec2<f32>>) -> Expr<Vec2<f32>> {
+        let uv = pixel / self.screen_size * 2.0 - 1.0;
+        Vec2::expr(uv.x, -uv.y)
+    }
+    #[tracked]
+    pub fn ndc_to_pixel(&self, ndc: Expr<Vec2<f32>>) -> Expr<Vec2<f32>> {
+        (Vec2::expr(ndc.x, -ndc.y) + 1.0) / 2.0 * self.screen_size
+    }
+    #[tracked]
+    pub fn ndc_depth(&self, linear_depth: Expr<f32>) -> Expr<f32> {
+        let clip = self.projection * Vec4::expr(0.0, 0.0, linear_depth, 1.0);
+        clip.z / clip.w
+    }
+    #[tracked]
+    pub fn linear_depth(&self, ndc_depth: Expr<f32>) -> Expr<f32> {
+        let (z, w) = (self.projection.col(2), self.projection.col(3));
+        (w.z - ndc_depth * w.w) / (ndc_depth * z.w - z.z)
+    }
+    /// Returns the NDC position of a point, which is behind the camera if the depth is negative.
+    #[tracked]
+    pub fn project_ndc(&self, world_pos: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+        let clip = self.view_projection * world_pos.extend(1.0);
+        clip.xyz() / clip.w
+    }
+    /// Returns the world position of a pixel with the given NDC depth.
+    #[tracked]
+    pub fn unproject(&self, pixel: Expr<Vec2<f32>>, ndc_depth: Expr<f32>) -> Expr<Vec3<f32>> {
+        let ndc = self.pixel_to_ndc(pixel).extend(ndc_depth);
+        let world = self.inverse_view_projection * ndc.extend(1.0);
+        world.xyz() / world.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::eval;
+
+    fn cameras() -> [Camera; 3] {
+        let camera = Camera {
+            screen_size: FVec2::new(320.0, 240.0),
+            yaw: 0.7,
+            pitch: -0.3,
+            pos: FVec3::new(1.0, -2.0, 0.5),
+            ..Default::default()
+        };
+        [
+            camera,
+            Camera {
+                far: 50.0,
+                ..camera
+            },
+            Camera {
+                projection: Projection::Orthographic { height: 4.0 },
+                far: 20.0,
+                ..camera
+            },
+        ]
+    }
+
+    #[test]
+    fn project_unproject() {
+        for camera in cameras() {
+            let point = camera.pos + camera.forward() * 3.0 + camera.right() * 0.4;
+            let projected = camera.project(point);
+            assert!((0.0..1.0).contains(&projected.z));
+            let unprojected = camera.unproject(projected.truncate(), projected.z);
+            assert!(
+                point.distance(unprojected) < 1e-3,
+                "{point} != {unprojected}"
+            );
+            let depth = camera.linear_depth(projected.z);
+            assert!((depth - 3.0).abs() < 1e-3);
+            assert!((camera.ndc_depth(camera.near)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn orthographic_infinite_far() {
+        let camera = Camera {
+            projection: Projection::Orthographic { height: 4.0 },
+            ..cameras()[0]
+        };
+        assert!(camera.far.is_infinite());
+        assert!(camera.view_projection().is_finite());
+        let far = camera.near + Camera::INFINITE_ORTHOGRAPHIC_DEPTH;
+        assert!((camera.ndc_depth(far) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn matches_pinhole() {
+        let camera = cameras()[1];
+        let view = camera.view();
+        let pixels = eval(16, |i| {
+            let view = view.expr();
+            let pixel = Vec2::expr((i * 17).cast_f32(), (i * 11).cast_f32());
+            let world = view.pos + view.ray_dir(pixel) * 2.0;
+            view.project(world)
+        });
+        for (i, pixel) in pixels.into_iter().enumerate() {
+            let expected = FVec2::new(i as f32 * 17.0, i as f32 * 11.0);
+            assert!(FVec2::new(pixel.x, pixel.y).distance(expected) < 1e-2);
+        }
+    }
+
+    #[test]
+    fn jitter_matches_host() {
+        for jitter in [Jitter::None, Jitter::Halton, Jitter::R2] {
+            let view = Camera {
+                jitter,
+                ..cameras()[0]
+            }
+            .view();
+            let offsets = eval(64, |i| view.expr().jitter_offset(i));
+            for (i, offset) in offsets.into_iter().enumerate() {
+                let expected = jitter.offset(i as u32);
+                assert!((0.0..1.0).contains(&(offset.x + 0.5)));
+                assert!(FVec2::new(offset.x, offset.y).distance(expected) < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn thin_lens_focus() {
+        // Every ray through a pixel should pass through the same point on the focal plane.
+        let camera = Camera {
+            aperture: 0.2,
+            focus_distance: 4.0,
+            jitter: Jitter::R2,
+            ..cameras()[0]
+        };
+        let view = camera.view();
+        let points = eval(32, |i| {
+            let view = view.expr();
+            let (origin, dir) = view.ray_at(Vec2::expr(40.0, 200.0), crate::rand::r2(i));
+            let t = (view.focus_distance - (origin - view.pos).dot(view.facing()))
+                / dir.dot(view.facing());
+            origin + dir * t
+        });
+        let expected = camera.unproject(FVec2::new(40.0, 200.0), camera.ndc_depth(4.0));
+        for point in points {
+            assert!(FVec3::new(point.x, point.y, point.z).distance(expected) < 1e-3);
+        }
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let path = std::env::temp_dir().join(format!("yesod-camera-{}.txt", std::process::id()));
+        let camera = Camera {
+            aperture: 0.05,
+            jitter: Jitter::Halton,
+            ..cameras()[2]
+        };
+        camera.save_state(&path).unwrap();
+        let mut loaded = Camera::default();
+        loaded.load_state(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.pos, camera.pos);
+        assert_eq!((loaded.yaw, loaded.pitch), (camera.yaw, camera.pitch));
+        assert_eq!(loaded.projection, camera.projection);
+        assert_eq!((loaded.near, loaded.far), (camera.near, camera.far));
+        assert_eq!(loaded.aperture, camera.aperture);
+        assert_eq!(loaded.jitter, camera.jitter);
+    }
+
+    #[test]
+    fn device_unproject() {
+        for camera in cameras() {
+            let view = camera.view();
+            let points = eval(1, |_| {
+                view.expr()
+                    .unproject(Vec2::expr(100.0, 50.0), 0.5_f32.expr())
+            });
+            let expected = camera.unproject(FVec2::new(100.0, 50.0), 0.5);
+            assert!(
+                FVec3::new(points[0].x, points[0].y, points[0].z).distance(expected)
+                    < 1e-3 * expected.length().max(1.0)
+            );
+        }
     }
 }
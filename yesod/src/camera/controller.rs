@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::FRAC_PI_2;
+use std::hash::Hash;
+
+use glam::{Vec2 as FVec2, Vec3 as FVec3};
+use winit::keyboard::KeyCode;
+
+use super::{Camera, Projection};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    YawLeft,
+    YawRight,
+    PitchUp,
+    PitchDown,
+    ZoomIn,
+    ZoomOut,
+}
+
+/// A mapping from inputs, such as keys, to controller actions.
+#[derive(Debug, Clone)]
+pub struct Bindings<K> {
+    bindings: HashMap<K, Action>,
+}
+impl<K: Eq + Hash> Bindings<K> {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+    pub fn bind(mut self, key: K, action: Action) -> Self {
+        self.bindings.insert(key, action);
+        self
+    }
+    pub fn unbind(&mut self, key: &K) {
+        self.bindings.remove(key);
+    }
+    pub fn action(&self, key: &K) -> Option<Action> {
+        self.bindings.get(key).copied()
+    }
+    /// Returns the actions bound to any of the held keys.
+    pub fn actions<'a>(&self, keys: impl IntoIterator<Item = &'a K>) -> HashSet<Action>
+    where
+        K: 'a,
+    {
+        keys.into_iter()
+            .filter_map(|key| self.action(key))
+            .collect()
+    }
+}
+impl<K: Eq + Hash> Default for Bindings<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Bindings<KeyCode> {
+    /// WASD to move, space and shift to move vertically, arrow keys to look, and Q/E to zoom.
+    pub fn keyboard() -> Self {
+        Self::new()
+            .bind(KeyCode::KeyW, Action::Forward)
+            .bind(KeyCode::KeyS, Action::Back)
+            .bind(KeyCode::KeyA, Action::Left)
+            .bind(KeyCode::KeyD, Action::Right)
+            .bind(KeyCode::Space, Action::Up)
+            .bind(KeyCode::ShiftLeft, Action::Down)
+            .bind(KeyCode::ArrowLeft, Action::YawLeft)
+            .bind(KeyCode::ArrowRight, Action::YawRight)
+            .bind(KeyCode::ArrowUp, Action::PitchUp)
+            .bind(KeyCode::ArrowDown, Action::PitchDown)
+            .bind(KeyCode::KeyE, Action::ZoomIn)
+            .bind(KeyCode::KeyQ, Action::ZoomOut)
+    }
+}
+
+/// The input for a single frame, independent of the windowing library.
+#[derive(Debug, Clone, Default)]
+pub struct ControllerInput {
+    pub actions: HashSet<Action>,
+    /// The cursor movement since the last frame in pixels, which should come from raw device
+    /// motion while the cursor is captured.
+    pub look: FVec2,
+    /// The cursor movement since the last frame in pixels while dragging to pan.
+    pub pan: FVec2,
+    /// The scroll wheel movement since the last frame, positive to zoom in.
+    pub scroll: f32,
+}
+impl ControllerInput {
+    fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.actions.contains(&positive) as u32 as f32
+            - self.actions.contains(&negative) as u32 as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControllerMode {
+    /// Moves freely, with forward movement staying horizontal.
+    Fly,
+    /// Rotates around `target` at `distance`, with movement panning the target.
+    Orbit { target: FVec3, distance: f32 },
+    /// Pans across the screen plane and zooms, for viewing 2D content with an orthographic
+    /// camera.
+    PanZoom2d,
+}
+
+/// Moves a [`Camera`] from [`ControllerInput`], with velocities that are smoothed the same way
+/// regardless of the frame rate.
+#[derive(Debug, Clone)]
+pub struct CameraController {
+    pub mode: ControllerMode,
+    /// The movement speed, in world units per second.
+    pub move_speed: f32,
+    /// The rotation speed from actions, in radians per second.
+    pub rotate_speed: f32,
+    /// The rotation from mouse-look, in radians per pixel.
+    pub look_sensitivity: f32,
+    /// The zoom rate from actions, as the logarithm of the scale per second.
+    pub zoom_speed: f32,
+    /// The zoom from each unit of scrolling, as the logarithm of the scale.
+    pub scroll_sensitivity: f32,
+    /// The rate at which velocities approach their targets, per second.
+    /// Infinity disables smoothing.
+    pub damping: f32,
+    velocity: FVec3,
+    angular_velocity: FVec2,
+    zoom_velocity: f32,
+}
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new(ControllerMode::Fly)
+    }
+}
+impl CameraController {
+    pub fn new(mode: ControllerMode) -> Self {
+        Self {
+            mode,
+            move_speed: 5.0,
+            rotate_speed: 2.0,
+            look_sensitivity: 0.003,
+            zoom_speed: 2.0,
+            scroll_sensitivity: 0.1,
+            damping: 12.0,
+            velocity: FVec3::ZERO,
+            angular_velocity: FVec2::ZERO,
+            zoom_velocity: 0.0,
+        }
+    }
+    pub fn fly() -> Self {
+        Self::new(ControllerMode::Fly)
+    }
+    pub fn orbit(target: FVec3, distance: f32) -> Self {
+        Self::new(ControllerMode::Orbit { target, distance })
+    }
+    pub fn pan_zoom_2d() -> Self {
+        Self::new(ControllerMode::PanZoom2d)
+    }
+    /// Stops any remaining motion.
+    pub fn stop(&mut self) {
+        self.velocity = FVec3::ZERO;
+        self.angular_velocity = FVec2::ZERO;
+        self.zoom_velocity = 0.0;
+    }
+    // Moves `value` toward `target` exponentially, which is independent of how `dt` is divided.
+    fn damp<T>(&self, value: T, target: T, dt: f32) -> T
+    where
+        T: std::ops::Sub<Output = T>
+            + std::ops::Add<Output = T>
+            + std::ops::Mul<f32, Output = T>
+            + Copy,
+    {
+        target + (value - target) * (-self.damping * dt).exp()
+    }
+    /// Updates the camera from the input over the last `dt` seconds, returning whether it moved.
+    pub fn update(&mut self, camera: &mut Camera, input: &ControllerInput, dt: f32) -> bool {
+        let before = (camera.pos, camera.yaw, camera.pitch, camera.projection);
+
+        let movement = FVec3::new(
+            input.axis(Action::Right, Action::Left),
+            input.axis(Action::Forward, Action::Back),
+            input.axis(Action::Up, Action::Down),
+        );
+        self.velocity = self.damp(self.velocity, movement * self.move_speed, dt);
+        let rotation = FVec2::new(
+            input.axis(Action::YawRight, Action::YawLeft),
+            input.axis(Action::PitchDown, Action::PitchUp),
+        );
+        self.angular_velocity = self.damp(self.angular_velocity, rotation * self.rotate_speed, dt);
+        let zoom = input.axis(Action::ZoomIn, Action::ZoomOut);
+        self.zoom_velocity = self.damp(self.zoom_velocity, zoom * self.zoom_speed, dt);
+        let zoom = self.zoom_velocity * dt + input.scroll * self.scroll_sensitivity;
+
+        match &mut self.mode {
+            ControllerMode::Fly => {
+                let look = self.angular_velocity * dt + input.look * self.look_sensitivity;
+                rotate(camera, look);
+                let velocity = camera.right() * self.velocity.x
+                    + camera.forward_horiz() * self.velocity.y
+                    + FVec3::Z * self.velocity.z;
+                camera.pos += velocity * dt;
+            }
+            ControllerMode::Orbit { target, distance } => {
+                let look = self.angular_velocity * dt + input.look * self.look_sensitivity;
+                rotate(camera, look);
+                *distance *= (-zoom).exp();
+                let up = camera.up();
+                *target += (camera.right() * self.velocity.x
+                    + camera.forward_horiz() * self.velocity.y
+                    + FVec3::Z * self.velocity.z)
+                    * dt;
+                // Dragging moves the target with the cursor at the target's depth.
+                let pixel_size = pixel_size(camera, *distance);
+                *target += (-camera.right() * input.pan.x + up * input.pan.y) * pixel_size;
+                camera.pos = *target - camera.forward() * *distance;
+            }
+            ControllerMode::PanZoom2d => {
+                let up = camera.up();
+                let pixel_size = pixel_size(camera, camera.focus_distance);
+                let pan = FVec2::new(self.velocity.x, self.velocity.y + self.velocity.z) * dt
+                    + FVec2::new(-input.pan.x, input.pan.y) * pixel_size;
+                camera.pos += camera.right() * pan.x + up * pan.y;
+                match &mut camera.projection {
+                    Projection::Orthographic { height } => *height *= (-zoom).exp(),
+                    Projection::Perspective { .. } => {
+                        let step = camera.focus_distance * (1.0 - (-zoom).exp());
+                        camera.pos += camera.forward() * step;
+                    }
+                }
+            }
+        }
+        before != (camera.pos, camera.yaw, camera.pitch, camera.projection)
+    }
+}
+
+fn rotate(camera: &mut Camera, delta: FVec2) {
+    camera.yaw += delta.x;
+    camera.pitch = (camera.pitch + delta.y).clamp(-FRAC_PI_2 + 1e-3, FRAC_PI_2 - 1e-3);
+}
+
+// The world-space size of a pixel at the given distance along the view axis.
+fn pixel_size(camera: &Camera, distance: f32) -> f32 {
+    match camera.projection {
+        Projection::Perspective { fov } => {
+            2.0 * (fov / 2.0).tan() * distance / camera.screen_size.y
+        }
+        Projection::Orthographic { height } => height / camera.screen_size.y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(actions: &[Action]) -> ControllerInput {
+        ControllerInput {
+            actions: actions.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn damping_is_frame_rate_independent() {
+        let run = |steps: u32| {
+            let mut camera = Camera::default();
+            let mut controller = CameraController::fly();
+            for _ in 0..steps {
+                controller.update(&mut camera, &input(&[Action::Right]), 1.0 / steps as f32);
+            }
+            controller.velocity.x
+        };
+        assert!((run(30) - run(240)).abs() < 1e-4);
+        assert!((run(240) - 5.0 * (1.0 - (-12.0_f32).exp())).abs() < 1e-4);
+    }
+
+    #[test]
+    fn orbit_keeps_distance() {
+        let target = FVec3::new(1.0, 2.0, 3.0);
+        let mut camera = Camera::default();
+        let mut controller = CameraController::orbit(target, 4.0);
+        let mut look = input(&[]);
+        look.look = FVec2::new(100.0, -50.0);
+        assert!(controller.update(&mut camera, &look, 1.0 / 60.0));
+        assert!((camera.pos.distance(target) - 4.0).abs() < 1e-4);
+        let to_target = (target - camera.pos).normalize();
+        assert!(to_target.dot(camera.forward()) > 0.9999);
+    }
+
+    #[test]
+    fn pan_zoom_2d() {
+        let mut camera = Camera {
+            screen_size: FVec2::new(200.0, 100.0),
+            projection: Projection::Orthographic { height: 10.0 },
+            ..Default::default()
+        };
+        let mut controller = CameraController::pan_zoom_2d();
+        let mut drag = input(&[]);
+        drag.pan = FVec2::new(10.0, 0.0);
+        controller.update(&mut camera, &drag, 1.0 / 60.0);
+        // Dragging by 10 pixels moves the view by 10 pixels' worth of world space.
+        assert!((camera.pos + camera.right()).length() < 1e-4);
+        let mut scroll = input(&[]);
+        scroll.scroll = 1.0;
+        controller.update(&mut camera, &scroll, 1.0 / 60.0);
+        assert_eq!(
+            camera.projection,
+            Projection::Orthographic {
+                height: 10.0 * (-0.1_f32).exp()
+            }
+        );
+    }
+}
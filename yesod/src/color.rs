@@ -2,6 +2,16 @@ use keter::lang::types::vector::{Vec2, Vec3};
 use keter::lerp;
 use keter::prelude::*;
 
+mod colormap;
+pub use colormap::{Colormap, ColormapTexture};
+mod spaces;
+pub use spaces::{
+    ACES_WHITE, D65, RgbSpace, blackbody, blackbody_chromaticity, chromatic_adaptation,
+    convert_rgb, host, hsl_to_rgb, hsv_to_rgb, linear_srgb_to_oklab, oklab_to_linear_srgb,
+    oklab_to_oklch, oklch_to_oklab, rgb_to_hsl, rgb_to_hsv, rgb_to_xyz, srgb_eotf, srgb_oetf,
+    xyy_to_xyz, xyz_to_rgb,
+};
+
 pub const AXIS_COLORS: [Vec3<f32>; 4] = [
     Vec3::new(0.64178, 0.22938, 0.33132), // 0
     Vec3::new(0.47086, 0.33081, 0.08135), // 90
@@ -31,7 +41,8 @@ pub fn reinhard(x: Expr<f32>) -> Expr<f32> {
 pub fn sample_gradient<const N: usize>(map: colorous::Gradient, t: Expr<f32>) -> Expr<Vec3<f32>> {
     let map = std::array::from_fn::<_, N, _>(|i| {
         let c = map.eval_rational(i, N - 1);
-        Vec3::new(c.r, c.g, c.b).map(|c| ((c as f32) / 255.0).powf(2.2))
+        let c = host::srgb_eotf(glam::Vec3::new(c.r as f32, c.g as f32, c.b as f32) / 255.0);
+        Vec3::new(c.x, c.y, c.z)
     });
     let map = map.expr();
 
@@ -44,14 +55,17 @@ pub fn sample_gradient<const N: usize>(map: colorous::Gradient, t: Expr<f32>) ->
 
 #[tracked]
 pub fn inferno(t: Expr<f32>) -> Expr<Vec3<f32>> {
-    sample_gradient::<256>(colorous::INFERNO, t)
+    Colormap::Inferno.sample::<256>(t)
 }
 #[tracked]
 pub fn plasma(t: Expr<f32>) -> Expr<Vec3<f32>> {
-    sample_gradient::<256>(colorous::PLASMA, t)
+    Colormap::Plasma.sample::<256>(t)
 }
 
+/// The Rec.601 luma weights, for gamma-encoded values.
 pub const LUMA_WEIGHTS: Vec3<f32> = Vec3::new(0.299, 0.587, 0.114);
+/// The Rec.709 luminance weights, for linear sRGB values.
+pub const LUMINANCE_WEIGHTS: Vec3<f32> = Vec3::new(0.2126, 0.7152, 0.0722);
 
 #[tracked]
 pub fn luma(c: Expr<Vec3<f32>>) -> Expr<f32> {
@@ -62,3 +76,7 @@ pub fn with_luma(c: Expr<Vec3<f32>>, l: Expr<f32>) -> Expr<Vec3<f32>> {
     let l_orig = luma(c);
     c * (l / l_orig)
 }
+#[tracked]
+pub fn luminance(c: Expr<Vec3<f32>>) -> Expr<f32> {
+    c.dot(LUMINANCE_WEIGHTS)
+}
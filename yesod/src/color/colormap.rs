@@ -0,0 +1,229 @@
+use glam::Vec3 as FVec3;
+use keter::lang::types::vector::{Vec2, Vec3};
+use keter::prelude::*;
+use keter::runtime::Device;
+
+use super::{host, sample_gradient};
+use crate::utils::memo;
+
+/// The gradients from [`colorous`], which are in sRGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Colormap {
+    // Sequential, multi-hue.
+    Turbo,
+    Viridis,
+    Inferno,
+    Magma,
+    Plasma,
+    Cividis,
+    Warm,
+    Cool,
+    Cubehelix,
+    BlueGreen,
+    BluePurple,
+    GreenBlue,
+    OrangeRed,
+    PurpleBlueGreen,
+    PurpleBlue,
+    PurpleRed,
+    RedPurple,
+    YellowGreenBlue,
+    YellowGreen,
+    YellowOrangeBrown,
+    YellowOrangeRed,
+    // Sequential, single hue.
+    Blues,
+    Greens,
+    Greys,
+    Oranges,
+    Purples,
+    Reds,
+    // Diverging.
+    BrownGreen,
+    PurpleGreen,
+    PinkGreen,
+    PurpleOrange,
+    RedBlue,
+    RedGrey,
+    RedYellowBlue,
+    RedYellowGreen,
+    Spectral,
+    // Cyclical.
+    Rainbow,
+    Sinebow,
+}
+impl Colormap {
+    pub const ALL: [Colormap; 38] = [
+        Colormap::Turbo,
+        Colormap::Viridis,
+        Colormap::Inferno,
+        Colormap::Magma,
+        Colormap::Plasma,
+        Colormap::Cividis,
+        Colormap::Warm,
+        Colormap::Cool,
+        Colormap::Cubehelix,
+        Colormap::BlueGreen,
+        Colormap::BluePurple,
+        Colormap::GreenBlue,
+        Colormap::OrangeRed,
+        Colormap::PurpleBlueGreen,
+        Colormap::PurpleBlue,
+        Colormap::PurpleRed,
+        Colormap::RedPurple,
+        Colormap::YellowGreenBlue,
+        Colormap::YellowGreen,
+        Colormap::YellowOrangeBrown,
+        Colormap::YellowOrangeRed,
+        Colormap::Blues,
+        Colormap::Greens,
+        Colormap::Greys,
+        Colormap::Oranges,
+        Colormap::Purples,
+        Colormap::Reds,
+        Colormap::BrownGreen,
+        Colormap::PurpleGreen,
+        Colormap::PinkGreen,
+        Colormap::PurpleOrange,
+        Colormap::RedBlue,
+        Colormap::RedGrey,
+        Colormap::RedYellowBlue,
+        Colormap::RedYellowGreen,
+        Colormap::Spectral,
+        Colormap::Rainbow,
+        Colormap::Sinebow,
+    ];
+
+    pub fn gradient(self) -> colorous::Gradient {
+        match self {
+            Colormap::Turbo => colorous::TURBO,
+            Colormap::Viridis => colorous::VIRIDIS,
+            Colormap::Inferno => colorous::INFERNO,
+            Colormap::Magma => colorous::MAGMA,
+            Colormap::Plasma => colorous::PLASMA,
+            Colormap::Cividis => colorous::CIVIDIS,
+            Colormap::Warm => colorous::WARM,
+            Colormap::Cool => colorous::COOL,
+            Colormap::Cubehelix => colorous::CUBEHELIX,
+            Colormap::BlueGreen => colorous::BLUE_GREEN,
+            Colormap::BluePurple => colorous::BLUE_PURPLE,
+            Colormap::GreenBlue => colorous::GREEN_BLUE,
+            Colormap::OrangeRed => colorous::ORANGE_RED,
+            Colormap::PurpleBlueGreen => colorous::PURPLE_BLUE_GREEN,
+            Colormap::PurpleBlue => colorous::PURPLE_BLUE,
+            Colormap::PurpleRed => colorous::PURPLE_RED,
+            Colormap::RedPurple => colorous::RED_PURPLE,
+            Colormap::YellowGreenBlue => colorous::YELLOW_GREEN_BLUE,
+            Colormap::YellowGreen => colorous::YELLOW_GREEN,
+            Colormap::YellowOrangeBrown => colorous::YELLOW_ORANGE_BROWN,
+            Colormap::YellowOrangeRed => colorous::YELLOW_ORANGE_RED,
+            Colormap::Blues => colorous::BLUES,
+            Colormap::Greens => colorous::GREENS,
+            Colormap::Greys => colorous::GREYS,
+            Colormap::Oranges => colorous::ORANGES,
+            Colormap::Purples => colorous::PURPLES,
+            Colormap::Reds => colorous::REDS,
+            Colormap::BrownGreen => colorous::BROWN_GREEN,
+            Colormap::PurpleGreen => colorous::PURPLE_GREEN,
+            Colormap::PinkGreen => colorous::PINK_GREEN,
+            Colormap::PurpleOrange => colorous::PURPLE_ORANGE,
+            Colormap::RedBlue => colorous::RED_BLUE,
+            Colormap::RedGrey => colorous::RED_GREY,
+            Colormap::RedYellowBlue => colorous::RED_YELLOW_BLUE,
+            Colormap::RedYellowGreen => colorous::RED_YELLOW_GREEN,
+            Colormap::Spectral => colorous::SPECTRAL,
+            Colormap::Rainbow => colorous::RAINBOW,
+            Colormap::Sinebow => colorous::SINEBOW,
+        }
+    }
+    /// The sRGB color at `t` from 0 to 1, as displayed.
+    pub fn eval_srgb(self, t: f32) -> FVec3 {
+        let c = self.gradient().eval_continuous(t.clamp(0.0, 1.0) as f64);
+        FVec3::new(c.r as f32, c.g as f32, c.b as f32) / 255.0
+    }
+    /// The linear color at `t` from 0 to 1.
+    pub fn eval(self, t: f32) -> FVec3 {
+        host::srgb_eotf(self.eval_srgb(t))
+    }
+    /// Samples the linear color at `t`, with `N` samples inlined into the kernel.
+    pub fn sample<const N: usize>(self, t: Expr<f32>) -> Expr<Vec3<f32>> {
+        sample_gradient::<N>(self.gradient(), t)
+    }
+    /// Bakes the gradient into a texture with `resolution` samples, which must be at least 2.
+    pub fn bake(self, resolution: u32) -> ColormapTexture {
+        self.bake_to(&DEVICE, resolution)
+    }
+    pub fn bake_to(self, device: &Device, resolution: u32) -> ColormapTexture {
+        assert!(
+            resolution >= 2,
+            "A colormap needs at least 2 samples, got {resolution}."
+        );
+        let texture =
+            device.create_tex2d::<Vec3<f32>>(<Vec3<f32>>::natural_storage(), resolution, 1, 1);
+        texture.view(0).copy_from(
+            &(0..resolution)
+                .map(|i| {
+                    let c = self.eval(i as f32 / (resolution - 1) as f32);
+                    Vec3::new(c.x, c.y, c.z)
+                })
+                .collect::<Vec<_>>(),
+        );
+        ColormapTexture {
+            texture,
+            resolution,
+        }
+    }
+    /// A baked texture with 256 samples, which is shared by every kernel using this colormap.
+    pub fn texture(self) -> &'static ColormapTexture {
+        memo(self, || self.bake(256))
+    }
+}
+
+/// A [`Colormap`] baked into a texture, which avoids inlining the samples into each kernel.
+pub struct ColormapTexture {
+    texture: Tex2d<Vec3<f32>>,
+    resolution: u32,
+}
+impl ColormapTexture {
+    pub fn texture(&self) -> &Tex2d<Vec3<f32>> {
+        &self.texture
+    }
+    /// Samples the linear color at `t` from 0 to 1, interpolating linearly.
+    #[tracked]
+    pub fn sample(&self, t: Expr<f32>) -> Expr<Vec3<f32>> {
+        let t = t.clamp(0.0, 1.0) * (self.resolution - 1) as f32;
+        let index = keter::min(t.floor().cast_u32(), self.resolution - 2);
+        let fract = t - index.cast_f32();
+        let a = self.texture.read(Vec2::expr(index, 0));
+        let b = self.texture.read(Vec2::expr(index + 1, 0));
+        a.lerp(b, Vec3::splat_expr(fract))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{CPU_DEVICE, eval};
+
+    #[test]
+    fn texture_matches_inline() {
+        for colormap in [Colormap::Viridis, Colormap::Turbo, Colormap::RedBlue] {
+            let baked = colormap.bake_to(&CPU_DEVICE, 256);
+            let results = eval::<Vec3<f32>>(
+                101,
+                track!(|i| {
+                    let t = i.cast_f32() / 100.0;
+                    (baked.sample(t) - colormap.sample::<256>(t)).abs()
+                }),
+            );
+            for (i, error) in results.into_iter().enumerate() {
+                let error = error.x.max(error.y).max(error.z);
+                assert!(error < 1e-3, "{colormap:?} differs by {error} at {i}.");
+            }
+            let end = colormap.eval(1.0);
+            let c = colormap.gradient().eval_rational(1, 1);
+            let expected = host::srgb_eotf(FVec3::new(c.r as f32, c.g as f32, c.b as f32) / 255.0);
+            assert!((end - expected).abs().max_element() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use std::f32::consts::TAU;
+
+use glam::{Mat3 as FMat3, Vec3 as FVec3};
+use keter::lang::types::vector::{Mat3, Vec2, Vec3};
+use keter::prelude::*;
+
+// https://bottosson.github.io/posts/oklab/
+const OKLAB_M1: [[f32; 3]; 3] = [
+    [0.412_221_46, 0.536_332_55, 0.051_445_995],
+    [0.211_903_5, 0.680_699_5, 0.107_396_96],
+    [0.088_302_46, 0.281_718_85, 0.629_978_7],
+];
+const OKLAB_M2: [[f32; 3]; 3] = [
+    [0.210_454_26, 0.793_617_8, -0.004_072_047],
+    [1.977_998_5, -2.428_592_2, 0.450_593_7],
+    [0.025_904_037, 0.782_771_77, -0.808_675_77],
+];
+const OKLAB_M2_INVERSE: [[f32; 3]; 3] = [
+    [1.0, 0.396_337_78, 0.215_803_76],
+    [1.0, -0.105_561_346, -0.063_854_17],
+    [1.0, -0.089_484_18, -1.291_485_5],
+];
+const OKLAB_M1_INVERSE: [[f32; 3]; 3] = [
+    [4.076_741_7, -3.307_711_6, 0.230_969_94],
+    [-1.268_438, 2.609_757_4, -0.341_319_38],
+    [-0.004_196_086_3, -0.703_418_6, 1.707_614_7],
+];
+
+const BRADFORD: [[f32; 3]; 3] = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+/// The CIE 1931 chromaticity of the D65 white point.
+pub const D65: [f32; 2] = [0.3127, 0.3290];
+/// The CIE 1931 chromaticity of the ACES white point, which is close to D60.
+pub const ACES_WHITE: [f32; 2] = [0.32168, 0.33767];
+
+fn rows(rows: [[f32; 3]; 3]) -> FMat3 {
+    FMat3::from_cols_array_2d(&rows).transpose()
+}
+fn mat(matrix: FMat3) -> Expr<Mat3> {
+    Mat3::from(matrix).expr()
+}
+
+fn xy_to_xyz([x, y]: [f32; 2]) -> FVec3 {
+    FVec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+/// Adapts XYZ colors from one white point to another with the Bradford transform.
+pub fn chromatic_adaptation(from: [f32; 2], to: [f32; 2]) -> FMat3 {
+    let bradford = rows(BRADFORD);
+    let scale = (bradford * xy_to_xyz(to)) / (bradford * xy_to_xyz(from));
+    bradford.inverse() * FMat3::from_diagonal(scale) * bradford
+}
+
+/// A linear RGB color space, defined by its primaries and white point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RgbSpace {
+    /// The primaries of sRGB.
+    Rec709,
+    Rec2020,
+    /// ACES AP1, with the ACES white point.
+    AcesCg,
+    /// The primaries of DCI-P3 with a D65 white point.
+    DisplayP3,
+}
+impl RgbSpace {
+    /// The chromaticities of the red, green and blue primaries.
+    pub fn primaries(self) -> [[f32; 2]; 3] {
+        match self {
+            RgbSpace::Rec709 => [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]],
+            RgbSpace::Rec2020 => [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]],
+            RgbSpace::AcesCg => [[0.713, 0.293], [0.165, 0.830], [0.128, 0.044]],
+            RgbSpace::DisplayP3 => [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060]],
+        }
+    }
+    pub fn white(self) -> [f32; 2] {
+        match self {
+            RgbSpace::AcesCg => ACES_WHITE,
+            _ => D65,
+        }
+    }
+    /// The matrix to CIE XYZ, adapted to D65 so that all spaces share the same white.
+    pub fn to_xyz(self) -> FMat3 {
+        let [r, g, b] = self.primaries().map(xy_to_xyz);
+        let primaries = FMat3::from_cols(r, g, b);
+        let scale = primaries.inverse() * xy_to_xyz(self.white());
+        chromatic_adaptation(self.white(), D65) * primaries * FMat3::from_diagonal(scale)
+    }
+    pub fn from_xyz(self) -> FMat3 {
+        self.to_xyz().inverse()
+    }
+    /// The matrix converting colors in this space to `other`.
+    pub fn conversion(self, other: RgbSpace) -> FMat3 {
+        other.from_xyz() * self.to_xyz()
+    }
+}
+
+/// The sRGB EOTF, decoding sRGB values to linear.
+#[tracked]
+pub fn srgb_eotf(c: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    (c <= 0.04045_f32).select(c / 12.92, ((c + 0.055) / 1.055).powf(2.4))
+}
+/// The inverse of [`srgb_eotf`], encoding linear values as sRGB.
+#[tracked]
+pub fn srgb_oetf(c: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    (c <= 0.0031308_f32).select(c * 12.92, 1.055 * c.powf(1.0 / 2.4) - 0.055)
+}
+
+/// Converts linear RGB colors between color spaces.
+#[tracked]
+pub fn convert_rgb(c: Expr<Vec3<f32>>, from: RgbSpace, to: RgbSpace) -> Expr<Vec3<f32>> {
+    mat(from.conversion(to)) * c
+}
+#[tracked]
+pub fn rgb_to_xyz(c: Expr<Vec3<f32>>, space: RgbSpace) -> Expr<Vec3<f32>> {
+    mat(space.to_xyz()) * c
+}
+#[tracked]
+pub fn xyz_to_rgb(c: Expr<Vec3<f32>>, space: RgbSpace) -> Expr<Vec3<f32>> {
+    mat(space.from_xyz()) * c
+}
+
+/// Converts linear sRGB to OKLab, with lightness from 0 to 1.
+#[tracked]
+pub fn linear_srgb_to_oklab(c: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let lms = mat(rows(OKLAB_M1)) * c;
+    mat(rows(OKLAB_M2)) * lms.abs().powf(1.0 / 3.0).copysign(lms)
+}
+#[tracked]
+pub fn oklab_to_linear_srgb(lab: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let lms = mat(rows(OKLAB_M2_INVERSE)) * lab;
+    mat(rows(OKLAB_M1_INVERSE)) * (lms * lms * lms)
+}
+/// Converts OKLab to lightness, chroma and hue, with the hue in turns from 0 to 1.
+#[tracked]
+pub fn oklab_to_oklch(lab: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let hue = (lab.z.atan2(lab.y) / TAU).fract();
+    Vec3::expr(lab.x, lab.yz().length(), hue)
+}
+#[tracked]
+pub fn oklch_to_oklab(lch: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let ab = (lch.z * TAU).direction() * lch.y;
+    Vec3::expr(lch.x, ab.x, ab.y)
+}
+
+// The hue in turns, and the largest and smallest components.
+#[tracked]
+fn hue(c: Expr<Vec3<f32>>) -> (Expr<f32>, Expr<f32>, Expr<f32>) {
+    let max = c.reduce_max();
+    let min = c.reduce_min();
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0_f32.expr()
+    } else if max == c.x {
+        (c.y - c.z) / delta
+    } else if max == c.y {
+        (c.z - c.x) / delta + 2.0
+    } else {
+        (c.x - c.y) / delta + 4.0
+    };
+    ((hue / 6.0).fract(), max, min)
+}
+
+/// Converts RGB to hue, saturation and value, with the hue in turns from 0 to 1.
+#[tracked]
+pub fn rgb_to_hsv(c: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let (hue, max, min) = hue(c);
+    let saturation = if max == 0.0 {
+        0.0_f32.expr()
+    } else {
+        (max - min) / max
+    };
+    Vec3::expr(hue, saturation, max)
+}
+// https://en.wikipedia.org/wiki/HSL_and_HSV#Color_conversion_formulae
+#[tracked]
+pub fn hsv_to_rgb(hsv: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let k = (Vec3::expr(5.0, 3.0, 1.0) / 6.0 + Vec3::splat_expr(hsv.x)).fract() * 6.0;
+    let f = keter::min(k, 4.0 - k).clamp(0.0, 1.0);
+    Vec3::splat_expr(hsv.z) - (hsv.z * hsv.y) * f
+}
+/// Converts RGB to hue, saturation and lightness, with the hue in turns from 0 to 1.
+#[tracked]
+pub fn rgb_to_hsl(c: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let (hue, max, min) = hue(c);
+    let lightness = (max + min) / 2.0;
+    let saturation = if max == min {
+        0.0_f32.expr()
+    } else {
+        (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+    };
+    Vec3::expr(hue, saturation, lightness)
+}
+#[tracked]
+pub fn hsl_to_rgb(hsl: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let k = (Vec3::expr(0.0, 8.0, 4.0) / 12.0 + Vec3::splat_expr(hsl.x)).fract() * 12.0;
+    let a = hsl.y * keter::min(hsl.z, 1.0 - hsl.z);
+    let f = keter::min(k - 3.0, 9.0 - k).clamp(-1.0, 1.0);
+    Vec3::splat_expr(hsl.z) - a * f
+}
+
+/// Converts a chromaticity and luminance to CIE XYZ.
+#[tracked]
+pub fn xyy_to_xyz(xy: Expr<Vec2<f32>>, luminance: Expr<f32>) -> Expr<Vec3<f32>> {
+    Vec3::expr(xy.x, xy.y, 1.0 - xy.x - xy.y) * (luminance / xy.y)
+}
+
+/// The CIE 1931 chromaticity of a blackbody at `temperature` kelvin, which is clamped to the
+/// valid range of 1667 K to 25000 K.
+// Kim et al. 2002, Design of Advanced Color Temperature Control System for HDTV Applications.
+#[tracked]
+pub fn blackbody_chromaticity(temperature: Expr<f32>) -> Expr<Vec2<f32>> {
+    let t = temperature.clamp(1667.0, 25000.0);
+    let [t1, t2, t3] = [1000.0 / t, 1.0e6 / (t * t), 1.0e9 / (t * t * t)];
+    let x = if t <= 4000.0 {
+        -0.266_123_9 * t3 - 0.234_358_9 * t2 + 0.877_695_6 * t1 + 0.179_910
+    } else {
+        -3.025_846_9 * t3 + 2.107_037_9 * t2 + 0.222_634_7 * t1 + 0.240_390
+    };
+    let [x1, x2, x3] = [x, x * x, x * x * x];
+    let y = if t <= 2222.0 {
+        -1.106_381_4 * x3 - 1.348_110_2 * x2 + 2.185_558_3 * x1 - 0.202_196_83
+    } else if t <= 4000.0 {
+        -0.954_947_6 * x3 - 1.374_185_9 * x2 + 2.091_370_2 * x1 - 0.167_488_67
+    } else {
+        3.081_758 * x3 - 5.873_386_7 * x2 + 3.751_13 * x1 - 0.370_014_83
+    };
+    Vec2::expr(x, y)
+}
+/// The linear color of a blackbody at `temperature` kelvin, with a luminance of 1.
+#[tracked]
+pub fn blackbody(temperature: Expr<f32>, space: RgbSpace) -> Expr<Vec3<f32>> {
+    xyz_to_rgb(
+        xyy_to_xyz(blackbody_chromaticity(temperature), 1.0_f32.expr()),
+        space,
+    )
+}
+
+/// Reference implementations on the host.
+pub mod host {
+    use glam::{Vec2, Vec3};
+
+    use super::*;
+
+    fn select(mask: glam::BVec3, a: Vec3, b: Vec3) -> Vec3 {
+        Vec3::select(mask, a, b)
+    }
+
+    pub fn srgb_eotf(c: Vec3) -> Vec3 {
+        select(
+            c.cmple(Vec3::splat(0.04045)),
+            c / 12.92,
+            ((c + 0.055) / 1.055).powf(2.4),
+        )
+    }
+    pub fn srgb_oetf(c: Vec3) -> Vec3 {
+        select(
+            c.cmple(Vec3::splat(0.0031308)),
+            c * 12.92,
+            1.055 * c.powf(1.0 / 2.4) - 0.055,
+        )
+    }
+    pub fn convert_rgb(c: Vec3, from: RgbSpace, to: RgbSpace) -> Vec3 {
+        from.conversion(to) * c
+    }
+    pub fn rgb_to_xyz(c: Vec3, space: RgbSpace) -> Vec3 {
+        space.to_xyz() * c
+    }
+    pub fn xyz_to_rgb(c: Vec3, space: RgbSpace) -> Vec3 {
+        space.from_xyz() * c
+    }
+    pub fn linear_srgb_to_oklab(c: Vec3) -> Vec3 {
+        let lms = rows(OKLAB_M1) * c;
+        rows(OKLAB_M2) * lms.abs().powf(1.0 / 3.0).copysign(lms)
+    }
+    pub fn oklab_to_linear_srgb(lab: Vec3) -> Vec3 {
+        let lms = rows(OKLAB_M2_INVERSE) * lab;
+        rows(OKLAB_M1_INVERSE) * (lms * lms * lms)
+    }
+    pub fn oklab_to_oklch(lab: Vec3) -> Vec3 {
+        let hue = (lab.z.atan2(lab.y) / TAU).rem_euclid(1.0);
+        Vec3::new(lab.x, lab.y.hypot(lab.z), hue)
+    }
+    pub fn oklch_to_oklab(lch: Vec3) -> Vec3 {
+        let (sin, cos) = (lch.z * TAU).sin_cos();
+        Vec3::new(lch.x, lch.y * cos, lch.y * sin)
+    }
+    fn hue(c: Vec3) -> (f32, f32, f32) {
+        let max = c.max_element();
+        let min = c.min_element();
+        let delta = max - min;
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == c.x {
+            (c.y - c.z) / delta
+        } else if max == c.y {
+            (c.z - c.x) / delta + 2.0
+        } else {
+            (c.x - c.y) / delta + 4.0
+        };
+        ((hue / 6.0).rem_euclid(1.0), max, min)
+    }
+    pub fn rgb_to_hsv(c: Vec3) -> Vec3 {
+        let (hue, max, min) = hue(c);
+        let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+        Vec3::new(hue, saturation, max)
+    }
+    pub fn hsv_to_rgb(hsv: Vec3) -> Vec3 {
+        let k = (Vec3::new(5.0, 3.0, 1.0) / 6.0 + hsv.x).fract() * 6.0;
+        let f = k.min(4.0 - k).clamp(Vec3::ZERO, Vec3::ONE);
+        hsv.z - hsv.z * hsv.y * f
+    }
+    pub fn rgb_to_hsl(c: Vec3) -> Vec3 {
+        let (hue, max, min) = hue(c);
+        let lightness = (max + min) / 2.0;
+        let saturation = if max == min {
+            0.0
+        } else {
+            (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+        };
+        Vec3::new(hue, saturation, lightness)
+    }
+    pub fn hsl_to_rgb(hsl: Vec3) -> Vec3 {
+        let k = (Vec3::new(0.0, 8.0, 4.0) / 12.0 + hsl.x).fract() * 12.0;
+        let a = hsl.y * hsl.z.min(1.0 - hsl.z);
+        let f = (k - 3.0).min(9.0 - k).clamp(Vec3::NEG_ONE, Vec3::ONE);
+        hsl.z - a * f
+    }
+    pub fn xyy_to_xyz(xy: Vec2, luminance: f32) -> Vec3 {
+        Vec3::new(xy.x, xy.y, 1.0 - xy.x - xy.y) * (luminance / xy.y)
+    }
+    pub fn blackbody_chromaticity(temperature: f32) -> Vec2 {
+        let t = temperature.clamp(1667.0, 25000.0);
+        let [t1, t2, t3] = [1000.0 / t, 1.0e6 / (t * t), 1.0e9 / (t * t * t)];
+        let x = if t <= 4000.0 {
+            -0.266_123_9 * t3 - 0.234_358_9 * t2 + 0.877_695_6 * t1 + 0.179_910
+        } else {
+            -3.025_846_9 * t3 + 2.107_037_9 * t2 + 0.222_634_7 * t1 + 0.240_390
+        };
+        let [x1, x2, x3] = [x, x * x, x * x * x];
+        let y = if t <= 2222.0 {
+            -1.106_381_4 * x3 - 1.348_110_2 * x2 + 2.185_558_3 * x1 - 0.202_196_83
+        } else if t <= 4000.0 {
+            -0.954_947_6 * x3 - 1.374_185_9 * x2 + 2.091_370_2 * x1 - 0.167_488_67
+        } else {
+            3.081_758 * x3 - 5.873_386_7 * x2 + 3.751_13 * x1 - 0.370_014_83
+        };
+        Vec2::new(x, y)
+    }
+    pub fn blackbody(temperature: f32, space: RgbSpace) -> Vec3 {
+        xyz_to_rgb(xyy_to_xyz(blackbody_chromaticity(temperature), 1.0), space)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3 as FVec3;
+    use keter::lang::types::vector::Vec4;
+
+    use super::*;
+    use crate::tests::eval;
+
+    fn assert_close(a: FVec3, b: FVec3, tolerance: f32) {
+        assert!((a - b).abs().max_element() < tolerance, "{a} != {b}");
+    }
+
+    const SPACES: [RgbSpace; 4] = [
+        RgbSpace::Rec709,
+        RgbSpace::Rec2020,
+        RgbSpace::AcesCg,
+        RgbSpace::DisplayP3,
+    ];
+
+    #[test]
+    fn known_values() {
+        assert_close(
+            host::srgb_eotf(FVec3::new(0.0, 0.5, 1.0)),
+            FVec3::new(0.0, 0.214_041_14, 1.0),
+            1e-6,
+        );
+        assert_close(
+            RgbSpace::Rec709.to_xyz() * FVec3::ONE,
+            FVec3::new(0.950_456, 1.0, 1.089_058),
+            1e-4,
+        );
+        // The luminance row of Rec.709.
+        assert_close(
+            RgbSpace::Rec709.to_xyz().row(1),
+            FVec3::new(0.2126, 0.7152, 0.0722),
+            1e-4,
+        );
+        assert_close(
+            RgbSpace::Rec709.conversion(RgbSpace::AcesCg) * FVec3::X,
+            FVec3::new(0.613_097, 0.070_194, 0.020_616),
+            1e-3,
+        );
+        assert_close(
+            host::linear_srgb_to_oklab(FVec3::ONE),
+            FVec3::new(1.0, 0.0, 0.0),
+            1e-4,
+        );
+        assert_close(
+            host::rgb_to_hsv(FVec3::new(0.0, 0.5, 1.0)),
+            FVec3::new(7.0 / 12.0, 1.0, 1.0),
+            1e-6,
+        );
+        assert_close(
+            host::rgb_to_hsl(FVec3::new(0.0, 0.5, 1.0)),
+            FVec3::new(7.0 / 12.0, 1.0, 0.5),
+            1e-6,
+        );
+        // 6500 K is close to, but not exactly, D65.
+        let d65 = host::blackbody_chromaticity(6504.0);
+        assert!((d65.x - D65[0]).abs() < 0.005 && (d65.y - D65[1]).abs() < 0.01);
+    }
+
+    #[test]
+    fn round_trips() {
+        let colors = [
+            FVec3::new(0.2, 0.5, 0.9),
+            FVec3::new(1.0, 0.1, 0.0),
+            FVec3::new(0.3, 0.3, 0.3),
+            FVec3::new(0.7, 0.9, 0.4),
+        ];
+        for c in colors {
+            assert_close(host::srgb_eotf(host::srgb_oetf(c)), c, 1e-6);
+            assert_close(host::hsv_to_rgb(host::rgb_to_hsv(c)), c, 1e-6);
+            assert_close(host::hsl_to_rgb(host::rgb_to_hsl(c)), c, 1e-6);
+            let lab = host::linear_srgb_to_oklab(c);
+            assert_close(host::oklch_to_oklab(host::oklab_to_oklch(lab)), lab, 1e-6);
+            assert_close(host::oklab_to_linear_srgb(lab), c, 1e-5);
+            for from in SPACES {
+                for to in SPACES {
+                    let converted = host::convert_rgb(c, from, to);
+                    assert_close(host::convert_rgb(converted, to, from), c, 1e-5);
+                }
+                assert_close(host::xyz_to_rgb(host::rgb_to_xyz(c, from), from), c, 1e-5);
+            }
+        }
+        // White stays white between spaces, including those with another white point.
+        for from in SPACES {
+            for to in SPACES {
+                assert_close(host::convert_rgb(FVec3::ONE, from, to), FVec3::ONE, 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_host() {
+        let c = FVec3::new(0.8, 0.3, 0.05);
+        let results = eval::<Vec4<f32>>(
+            11,
+            track!(|i| {
+                let c = Vec3::expr(c.x, c.y, c.z);
+                let results = [
+                    srgb_eotf(c),
+                    srgb_oetf(c),
+                    convert_rgb(c, RgbSpace::DisplayP3, RgbSpace::AcesCg),
+                    linear_srgb_to_oklab(c),
+                    oklab_to_oklch(c),
+                    oklch_to_oklab(c),
+                    rgb_to_hsv(c),
+                    hsv_to_rgb(c),
+                    rgb_to_hsl(c),
+                    hsl_to_rgb(c),
+                    blackbody(8500.0_f32.expr(), RgbSpace::Rec2020),
+                ];
+                let result = Vec3::splat_expr(0.0_f32).var();
+                for (j, value) in results.into_iter().enumerate() {
+                    if i == j as u32 {
+                        *result = value;
+                    }
+                }
+                (**result).extend(0.0)
+            }),
+        );
+        let expected = [
+            host::srgb_eotf(c),
+            host::srgb_oetf(c),
+            host::convert_rgb(c, RgbSpace::DisplayP3, RgbSpace::AcesCg),
+            host::linear_srgb_to_oklab(c),
+            host::oklab_to_oklch(c),
+            host::oklch_to_oklab(c),
+            host::rgb_to_hsv(c),
+            host::hsv_to_rgb(c),
+            host::rgb_to_hsl(c),
+            host::hsl_to_rgb(c),
+            host::blackbody(8500.0, RgbSpace::Rec2020),
+        ];
+        for (result, expected) in results.into_iter().zip(expected) {
+            assert_close(FVec3::new(result.x, result.y, result.z), expected, 1e-4);
+        }
+    }
+}
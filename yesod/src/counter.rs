@@ -0,0 +1,286 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+
+use keter::frame::Fence;
+use keter::graph::NodeConfigs;
+use keter::prelude::*;
+use keter::runtime::Device;
+use parking_lot::Mutex;
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(1024);
+static HISTORY_LEN: AtomicUsize = AtomicUsize::new(256);
+static GLOBAL_COUNTERS: LazyLock<Counters> = LazyLock::new(|| {
+    Counters::new(
+        CAPACITY.load(Ordering::SeqCst),
+        HISTORY_LEN.load(Ordering::SeqCst),
+    )
+});
+
+/// Sets the number of counters and the length of their histories for [`Counters::global`].
+/// Must be called before any counter is used.
+pub fn set_global_capacity(capacity: usize, history_len: usize) {
+    CAPACITY.store(capacity, Ordering::SeqCst);
+    HISTORY_LEN.store(history_len, Ordering::SeqCst);
+    LazyLock::force(&GLOBAL_COUNTERS);
+}
+
+/// Returns the global counter with the given name, registering it if needed.
+#[macro_export]
+macro_rules! counter {
+    ($name:expr) => {
+        ::yesod::counter::Counters::global().counter($name)
+    };
+}
+
+/// The values of a counter over the last few frames.
+#[derive(Debug, Clone)]
+pub struct CounterHistory {
+    values: VecDeque<u32>,
+    len: usize,
+}
+impl CounterHistory {
+    fn new(len: usize) -> Self {
+        Self {
+            values: VecDeque::with_capacity(len),
+            len,
+        }
+    }
+    fn push(&mut self, value: u32) {
+        if self.values.len() == self.len {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+    /// The values, from oldest to newest.
+    pub fn values(&self) -> impl Iterator<Item = u32> + '_ {
+        self.values.iter().copied()
+    }
+    pub fn last(&self) -> Option<u32> {
+        self.values.back().copied()
+    }
+    pub fn min(&self) -> Option<u32> {
+        self.values.iter().copied().min()
+    }
+    pub fn max(&self) -> Option<u32> {
+        self.values.iter().copied().max()
+    }
+    pub fn sum(&self) -> u64 {
+        self.values.iter().map(|&x| x as u64).sum()
+    }
+    pub fn mean(&self) -> f64 {
+        if self.values.is_empty() {
+            0.0
+        } else {
+            self.sum() as f64 / self.values.len() as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Counter<'a> {
+    counters: &'a Counters,
+    index: u32,
+}
+impl Counter<'_> {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+    pub fn add(&self, value: impl AsExpr<Value = u32>) {
+        self.counters
+            .buffer
+            .atomic_ref(self.index)
+            .fetch_add(value.as_expr());
+    }
+    pub fn increment(&self) {
+        self.add(1_u32);
+    }
+}
+
+// The values copied back by a single readback, which are recorded once the fence is signaled.
+#[derive(Debug)]
+struct PendingReadback {
+    values: Arc<Mutex<Vec<u32>>>,
+    fence: Fence,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    indices: HashMap<String, u32>,
+    names: Vec<String>,
+    histories: Vec<CounterHistory>,
+}
+
+/// A set of named counters, backed by a single buffer of atomics.
+#[derive(Debug)]
+pub struct Counters {
+    buffer: Buffer<u32>,
+    history_len: usize,
+    registry: Mutex<Registry>,
+    pending: Mutex<VecDeque<PendingReadback>>,
+}
+impl Counters {
+    pub fn new(capacity: usize, history_len: usize) -> Self {
+        Self::new_on(&DEVICE, capacity, history_len)
+    }
+    pub fn new_on(device: &Device, capacity: usize, history_len: usize) -> Self {
+        Self {
+            buffer: device.create_buffer_from_fn(capacity, |_| 0),
+            history_len,
+            registry: Mutex::new(Registry::default()),
+            pending: Mutex::new(VecDeque::new()),
+        }
+    }
+    pub fn global() -> &'static Self {
+        &GLOBAL_COUNTERS
+    }
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+    pub fn counter(&self, name: &str) -> Counter<'_> {
+        let mut registry = self.registry.lock();
+        let index = match registry.indices.get(name) {
+            Some(&index) => index,
+            None => {
+                let index = registry.names.len() as u32;
+                assert!(
+                    (index as usize) < self.capacity(),
+                    "Too many counters registered."
+                );
+                registry.indices.insert(name.to_string(), index);
+                registry.names.push(name.to_string());
+                registry
+                    .histories
+                    .push(CounterHistory::new(self.history_len));
+                index
+            }
+        };
+        Counter {
+            counters: self,
+            index,
+        }
+    }
+    /// Copies the counters back to the host and resets them.
+    /// The values are recorded by [`Counters::poll`] once the returned nodes finish executing.
+    ///
+    /// At most `history_len` readbacks are kept waiting for a poll, after which the oldest are
+    /// dropped.
+    pub fn readback(&self) -> NodeConfigs<'static> {
+        let pending = PendingReadback {
+            values: Arc::new(Mutex::new(vec![0; self.capacity()])),
+            fence: Fence::new(),
+        };
+        let nodes = (
+            self.buffer.view(..).copy_to_shared(&pending.values),
+            // A copy rather than a fill, which would need a kernel compiled for the device of the buffer.
+            self.buffer.copy_from_vec(vec![0; self.capacity()]),
+            pending.fence.signal_node(),
+        )
+            .chain();
+        let mut queue = self.pending.lock();
+        if queue.len() >= self.history_len.max(1) {
+            queue.pop_front();
+        }
+        queue.push_back(pending);
+        nodes
+    }
+    /// Copies the counters back to the host, records them and resets them, waiting for the device.
+    pub fn readback_blocking(&self) {
+        let values = self.buffer.copy_to_vec();
+        self.buffer.copy_from(&vec![0; self.capacity()]);
+        self.record(&values);
+    }
+    fn record(&self, values: &[u32]) {
+        let mut registry = self.registry.lock();
+        for (history, &value) in registry.histories.iter_mut().zip(values) {
+            history.push(value);
+        }
+    }
+    /// Records the values of every completed [`Counters::readback`] into the histories.
+    pub fn poll(&self) {
+        loop {
+            let pending = {
+                let mut pending = self.pending.lock();
+                match pending.front() {
+                    Some(readback) if readback.fence.is_signaled() => pending.pop_front().unwrap(),
+                    _ => break,
+                }
+            };
+            self.record(&pending.values.lock());
+        }
+    }
+    pub fn history(&self, name: &str) -> Option<CounterHistory> {
+        let registry = self.registry.lock();
+        let index = *registry.indices.get(name)?;
+        Some(registry.histories[index as usize].clone())
+    }
+    /// Calls `f` with the name and history of every counter, in order of registration.
+    pub fn for_each(&self, mut f: impl FnMut(&str, &CounterHistory)) {
+        let registry = self.registry.lock();
+        for (name, history) in registry.names.iter().zip(&registry.histories) {
+            f(name, history);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{CPU_DEVICE, eval};
+
+    #[test]
+    fn history() {
+        let counters = Counters::new_on(&CPU_DEVICE, 4, 3);
+        let threads = counters.counter("threads");
+        let indices = counters.counter("indices");
+        assert_eq!(counters.counter("threads").index(), threads.index());
+        for n in 1..=4 {
+            eval::<u32>(n, |i| {
+                threads.increment();
+                indices.add(i);
+                0_u32.expr()
+            });
+            counters.readback_blocking();
+        }
+        let threads = counters.history("threads").unwrap();
+        assert_eq!(threads.values().collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!((threads.min(), threads.max()), (Some(2), Some(4)));
+        assert_eq!(threads.mean(), 3.0);
+        let indices = counters.history("indices").unwrap();
+        assert_eq!(indices.values().collect::<Vec<_>>(), [1, 3, 6]);
+        assert!(counters.history("missing").is_none());
+    }
+
+    #[test]
+    fn readback() {
+        let counters = Counters::new_on(&CPU_DEVICE, 4, 2);
+        let threads = counters.counter("threads");
+        let execute = |nodes: NodeConfigs<'static>| {
+            nodes.execute_in(&CPU_DEVICE.default_stream().scope());
+        };
+        for n in 1..=3 {
+            eval::<u32>(n, |_| {
+                threads.increment();
+                0_u32.expr()
+            });
+            execute(counters.readback());
+        }
+        // Only the last `history_len` readbacks are kept waiting for a poll.
+        counters.poll();
+        let history = || {
+            counters
+                .history("threads")
+                .unwrap()
+                .values()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(history(), [2, 3]);
+
+        let nodes = counters.readback();
+        counters.poll();
+        assert_eq!(history(), [2, 3]);
+        execute(nodes);
+        counters.poll();
+        assert_eq!(history(), [3, 0]);
+    }
+}
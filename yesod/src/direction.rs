@@ -7,6 +7,91 @@ pub trait DirectionEncoder: 'static + Copy {
     /// Encoded vector between 0 and 1.
     fn encode(dir: Expr<Vec3<f32>>) -> Expr<Vec2<f32>>;
     fn decode(uv: Expr<Vec2<f32>>) -> Expr<Vec3<f32>>;
+
+    /// Packs a direction into 16 bits per axis, rounding the encoded vector.
+    #[tracked]
+    fn encode_u32(dir: Expr<Vec3<f32>>) -> Expr<u32> {
+        pack_snorm16x2(Self::encode(dir) * 2.0 - 1.0)
+    }
+    /// Packs a normalized direction into 16 bits per axis, choosing the quantized value that
+    /// decodes closest to `dir`.
+    fn encode_u32_precise(dir: Expr<Vec3<f32>>) -> Expr<u32> {
+        join_snorm(quantize_precise::<Self>(dir, SNORM16_MAX), 16)
+    }
+    #[tracked]
+    fn decode_u32(packed: Expr<u32>) -> Expr<Vec3<f32>> {
+        Self::decode(unpack_snorm16x2(packed) * 0.5 + 0.5)
+    }
+    /// Packs a direction into 8 bits per axis, rounding the encoded vector.
+    #[tracked]
+    fn encode_u16(dir: Expr<Vec3<f32>>) -> Expr<u16> {
+        pack_snorm8x2(Self::encode(dir) * 2.0 - 1.0)
+    }
+    /// Packs a normalized direction into 8 bits per axis, choosing the quantized value that
+    /// decodes closest to `dir`.
+    fn encode_u16_precise(dir: Expr<Vec3<f32>>) -> Expr<u16> {
+        join_snorm(quantize_precise::<Self>(dir, SNORM8_MAX), 8).cast_u16()
+    }
+    #[tracked]
+    fn decode_u16(packed: Expr<u16>) -> Expr<Vec3<f32>> {
+        Self::decode(unpack_snorm8x2(packed) * 0.5 + 0.5)
+    }
+}
+
+const SNORM16_MAX: f32 = 32767.0;
+const SNORM8_MAX: f32 = 127.0;
+
+// Joins two quantized values into the low `2 * bits` bits, with x in the low half.
+#[tracked]
+fn join_snorm(quantized: Expr<Vec2<f32>>, bits: u32) -> Expr<u32> {
+    let mask = (1_u32 << bits) - 1;
+    let quantized = quantized.cast_i32().cast_u32() & mask;
+    quantized.x | (quantized.y << bits)
+}
+
+#[tracked]
+fn split_snorm(packed: Expr<u32>, bits: u32, max: f32) -> Expr<Vec2<f32>> {
+    // Shifting the sign bit to the top and back sign-extends each value.
+    let x = (packed << (32 - bits)).cast_i32() >> (32 - bits);
+    let y = (packed << (32 - 2 * bits)).cast_i32() >> (32 - bits);
+    (Vec2::expr(x, y).cast_f32() / max).clamp(-1.0, 1.0)
+}
+
+/// Packs a vector in [-1, 1]^2 as two 16-bit signed normalized integers, with x in the low bits.
+#[tracked]
+pub fn pack_snorm16x2(value: Expr<Vec2<f32>>) -> Expr<u32> {
+    join_snorm((value.clamp(-1.0, 1.0) * SNORM16_MAX).round(), 16)
+}
+#[tracked]
+pub fn unpack_snorm16x2(packed: Expr<u32>) -> Expr<Vec2<f32>> {
+    split_snorm(packed, 16, SNORM16_MAX)
+}
+/// Packs a vector in [-1, 1]^2 as two 8-bit signed normalized integers, with x in the low bits.
+#[tracked]
+pub fn pack_snorm8x2(value: Expr<Vec2<f32>>) -> Expr<u16> {
+    join_snorm((value.clamp(-1.0, 1.0) * SNORM8_MAX).round(), 8).cast_u16()
+}
+#[tracked]
+pub fn unpack_snorm8x2(packed: Expr<u16>) -> Expr<Vec2<f32>> {
+    split_snorm(packed.cast_u32(), 8, SNORM8_MAX)
+}
+
+// Tries rounding each axis both ways, keeping whichever decodes closest to `dir`.
+// https://jcgt.org/published/0003/02/01/
+#[tracked]
+fn quantize_precise<E: DirectionEncoder>(dir: Expr<Vec3<f32>>, max: f32) -> Expr<Vec2<f32>> {
+    let base = ((E::encode(dir) * 2.0 - 1.0).clamp(-1.0, 1.0) * max).floor();
+    let best = base.var();
+    let best_dot = (-2.0_f32).var();
+    for [x, y] in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]] {
+        let candidate = (base + Vec2::expr(x, y)).clamp(-max, max);
+        let dot = E::decode(candidate / max * 0.5 + 0.5).dot(dir);
+        if dot > **best_dot {
+            *best = candidate;
+            *best_dot = dot;
+        }
+    }
+    **best
 }
 
 // Check https://knarkowicz.wordpress.com/2014/04/16/octahedron-normal-vector-encoding/ as well.
@@ -108,3 +193,79 @@ impl DirectionEncoder for ClarbergEncoder {
         (horiz * r * keter::max(2.0 - r.sqr(), 0.0).sqrt()).extend(z)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use keter::lang::types::vector::Vec4;
+
+    use super::*;
+    use crate::rand::Rng;
+    use crate::tests::eval;
+
+    // Returns the largest angle between random directions and their round trips.
+    fn max_error(round_trip: impl Fn(Expr<Vec3<f32>>) -> Expr<Vec3<f32>>) -> f32 {
+        eval::<f32>(
+            4096,
+            track!(|i| {
+                let rng = Rng::new_var(5_u64, i.cast_u64());
+                let z = rng.next_f32() * 2.0 - 1.0;
+                let dir = ((rng.next_f32() * TAU).direction() * (1.0 - z * z).sqrt()).extend(z);
+                let result = round_trip(dir);
+                dir.cross(result).length().atan2(dir.dot(result))
+            }),
+        )
+        .into_iter()
+        .fold(0.0, f32::max)
+    }
+
+    // The maximum error in radians with 32 and 16 bits, without and with precise encoding.
+    fn check<E: DirectionEncoder>(bounds_u32: [f32; 2], bounds_u16: [f32; 2]) {
+        let errors_u32 = [
+            max_error(|dir| E::decode_u32(E::encode_u32(dir))),
+            max_error(|dir| E::decode_u32(E::encode_u32_precise(dir))),
+        ];
+        let errors_u16 = [
+            max_error(|dir| E::decode_u16(E::encode_u16(dir))),
+            max_error(|dir| E::decode_u16(E::encode_u16_precise(dir))),
+        ];
+        for (errors, bounds) in [(errors_u32, bounds_u32), (errors_u16, bounds_u16)] {
+            assert!(
+                errors[0] < bounds[0] && errors[1] < bounds[1] && errors[1] <= errors[0],
+                "Errors {errors:?} exceed {bounds:?}."
+            );
+        }
+    }
+
+    #[test]
+    fn octahedral_round_trip() {
+        check::<OctahedralEncoder>([1e-4, 6e-5], [0.02, 0.013]);
+    }
+
+    #[test]
+    fn spherical_round_trip() {
+        check::<SphericalEncoder>([2e-3, 2e-3], [0.1, 0.075]);
+    }
+
+    #[test]
+    fn clarberg_round_trip() {
+        check::<ClarbergEncoder>([1e-4, 6e-5], [0.02, 0.012]);
+    }
+
+    #[test]
+    fn snorm_packing() {
+        let results = eval::<Vec4<f32>>(
+            1,
+            track!(|_| {
+                let value = Vec2::expr(-1.0, 0.5);
+                let wide = unpack_snorm16x2(pack_snorm16x2(value));
+                let narrow = unpack_snorm8x2(pack_snorm8x2(value));
+                Vec4::expr(wide.x, wide.y, narrow.x, narrow.y)
+            }),
+        );
+        let result = results[0];
+        assert_eq!(result.x, -1.0);
+        assert_eq!(result.y, 16384.0 / SNORM16_MAX);
+        assert_eq!(result.z, -1.0);
+        assert_eq!(result.w, 64.0 / SNORM8_MAX);
+    }
+}
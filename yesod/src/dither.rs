@@ -4,6 +4,9 @@ use nalgebra::SVector as Vector;
 
 use crate::utils::{iter_grid, to_linear};
 
+mod blue_noise;
+pub use blue_noise::{BlueNoise, BlueNoiseKind, BlueNoiseTexture};
+
 pub fn index_matrix<const D: usize>(n: u32, ordering: &[u32]) -> Vec<u32> {
     assert!(n.is_power_of_two());
     if n == 0 {
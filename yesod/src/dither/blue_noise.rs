@@ -0,0 +1,380 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use keter::lang::types::vector::{Vec2, Vec3};
+use keter::prelude::*;
+
+use crate::rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlueNoiseKind {
+    /// Blue noise over every axis.
+    Isotropic,
+    /// Each slice along z is 2D blue noise, and each pixel is 1D blue noise along z.
+    // Wolfe et al. 2022, Spatiotemporal Blue Noise Masks.
+    Spatiotemporal,
+}
+
+const SIGMA: f32 = 1.5;
+// The filter is truncated where the weight falls below ~1%.
+const RADIUS: i32 = 5;
+const MAGIC: &[u8; 8] = b"yesodbn1";
+
+/// A blue-noise mask, storing the rank of every texel, from `0` to `len - 1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlueNoise {
+    pub size: [u32; 3],
+    pub kind: BlueNoiseKind,
+    pub ranks: Vec<u32>,
+}
+impl BlueNoise {
+    pub fn generate_2d(size: [u32; 2], seed: u64) -> Self {
+        Self::generate([size[0], size[1], 1], BlueNoiseKind::Isotropic, seed)
+    }
+    pub fn generate_3d(size: [u32; 3], seed: u64) -> Self {
+        Self::generate(size, BlueNoiseKind::Isotropic, seed)
+    }
+    pub fn generate_spatiotemporal(size: [u32; 2], frames: u32, seed: u64) -> Self {
+        Self::generate(
+            [size[0], size[1], frames],
+            BlueNoiseKind::Spatiotemporal,
+            seed,
+        )
+    }
+    /// Generates a mask using the void-and-cluster algorithm, which wraps around every axis.
+    ///
+    /// This takes time quadratic in the number of texels, so large masks should be cached using
+    /// [`BlueNoise::cached`].
+    // Ulichney 1993, The void-and-cluster method for dither array generation.
+    pub fn generate(size: [u32; 3], kind: BlueNoiseKind, seed: u64) -> Self {
+        let mut generator = Generator::new(size, kind);
+        let len = generator.len();
+        let mut rng = Rng::new(seed, 0);
+
+        // Initial binary pattern, with about a tenth of the texels set.
+        let initial = (len / 10).max(1);
+        let mut count = 0;
+        while count < initial {
+            let index = rng.next_u32() as usize % len;
+            if !generator.pattern[index] {
+                generator.set(index, true);
+                count += 1;
+            }
+        }
+        loop {
+            let cluster = generator.tightest_cluster();
+            generator.set(cluster, false);
+            let void = generator.largest_void();
+            generator.set(void, true);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut ranks = vec![0; len];
+        let mut removal = generator.clone();
+        for rank in (0..initial).rev() {
+            let cluster = removal.tightest_cluster();
+            removal.set(cluster, false);
+            ranks[cluster] = rank as u32;
+        }
+        for rank in initial..len.div_ceil(2) {
+            let void = generator.largest_void();
+            generator.set(void, true);
+            ranks[void] = rank as u32;
+        }
+        // Once more than half are set, the remaining unset texels become the minority,
+        // so the tightest cluster of those is filled next.
+        generator.invert();
+        for rank in len.div_ceil(2)..len {
+            let cluster = generator.tightest_cluster();
+            generator.set(cluster, false);
+            ranks[cluster] = rank as u32;
+        }
+        Self { size, kind, ranks }
+    }
+    /// Loads the mask from `dir` if it has been generated before, otherwise generates and saves it.
+    pub fn cached(
+        dir: impl AsRef<Path>,
+        size: [u32; 3],
+        kind: BlueNoiseKind,
+        seed: u64,
+    ) -> io::Result<Self> {
+        let kind_name = match kind {
+            BlueNoiseKind::Isotropic => "iso",
+            BlueNoiseKind::Spatiotemporal => "st",
+        };
+        let path = dir.as_ref().join(format!(
+            "blue-noise-{kind_name}-{}x{}x{}-{seed}.bin",
+            size[0], size[1], size[2]
+        ));
+        if path.exists() {
+            let noise = Self::load(&path)?;
+            if noise.size == size && noise.kind == kind {
+                return Ok(noise);
+            }
+        }
+        let noise = Self::generate(size, kind, seed);
+        std::fs::create_dir_all(dir)?;
+        noise.save(&path)?;
+        Ok(noise)
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        for x in self.size {
+            file.write_all(&x.to_le_bytes())?;
+        }
+        file.write_all(&[self.kind as u8])?;
+        for rank in &self.ranks {
+            file.write_all(&rank.to_le_bytes())?;
+        }
+        file.flush()
+    }
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a blue noise file.",
+            ));
+        }
+        let mut read_u32 = || -> io::Result<u32> {
+            let mut bytes = [0; 4];
+            file.read_exact(&mut bytes)?;
+            Ok(u32::from_le_bytes(bytes))
+        };
+        let size = [read_u32()?, read_u32()?, read_u32()?];
+        let mut kind = [0];
+        file.read_exact(&mut kind)?;
+        let kind = match kind[0] {
+            0 => BlueNoiseKind::Isotropic,
+            1 => BlueNoiseKind::Spatiotemporal,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown blue noise kind.",
+                ));
+            }
+        };
+        let len = size.iter().product::<u32>() as usize;
+        let mut bytes = vec![0; len * 4];
+        file.read_exact(&mut bytes)?;
+        let ranks = bytes
+            .chunks_exact(4)
+            .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+            .collect();
+        Ok(Self { size, kind, ranks })
+    }
+    pub fn len(&self) -> usize {
+        self.ranks.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranks.is_empty()
+    }
+    /// The threshold of each texel, uniformly distributed in `(0, 1)`.
+    pub fn values(&self) -> Vec<f32> {
+        let len = self.len() as f32;
+        self.ranks
+            .iter()
+            .map(|&rank| (rank as f32 + 0.5) / len)
+            .collect()
+    }
+    pub fn upload(&self) -> BlueNoiseTexture {
+        let [w, h, d] = self.size;
+        let texture = if d == 1 {
+            let texture = DEVICE.create_tex2d(f32::natural_storage(), w, h, 1);
+            texture.view(0).copy_from(&self.values());
+            BlueNoiseTextureInner::Tex2d(texture)
+        } else {
+            let texture = DEVICE.create_tex3d(f32::natural_storage(), w, h, d, 1);
+            texture.view(0).copy_from(&self.values());
+            BlueNoiseTextureInner::Tex3d(texture)
+        };
+        BlueNoiseTexture {
+            texture,
+            size: self.size,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum BlueNoiseTextureInner {
+    Tex2d(Tex2d<f32>),
+    Tex3d(Tex3d<f32>),
+}
+
+/// A blue-noise mask on the device, which tiles across the screen and over time.
+#[derive(Debug)]
+pub struct BlueNoiseTexture {
+    texture: BlueNoiseTextureInner,
+    size: [u32; 3],
+}
+impl BlueNoiseTexture {
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+    /// A value in `(0, 1)` for the given pixel and frame.
+    ///
+    /// 2D masks are animated by offsetting with the golden ratio, which keeps each frame blue
+    /// but is only roughly blue over time. 3D masks use the frame as the z coordinate.
+    #[tracked]
+    pub fn sample(&self, pixel: Expr<Vec2<u32>>, frame: Expr<u32>) -> Expr<f32> {
+        let [w, h, d] = self.size;
+        let pos = pixel % Vec2::new(w, h);
+        match &self.texture {
+            BlueNoiseTextureInner::Tex2d(texture) => {
+                let golden = (5.0_f32.sqrt() - 1.0) / 2.0;
+                (texture.read(pos) + (frame % 4096).cast_f32() * golden).fract()
+            }
+            BlueNoiseTextureInner::Tex3d(texture) => {
+                texture.read(Vec3::expr(pos.x, pos.y, frame % d))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Generator {
+    size: [i32; 3],
+    // The offsets within the filter, and their weights.
+    filter: Vec<([i32; 3], f32)>,
+    pattern: Vec<bool>,
+    energy: Vec<f32>,
+}
+impl Generator {
+    fn new(size: [u32; 3], kind: BlueNoiseKind) -> Self {
+        let size = size.map(|x| x as i32);
+        // Limiting the radius avoids the filter wrapping onto itself for small masks.
+        let radius = size.map(|x| RADIUS.min((x - 1) / 2));
+        let weight = |d2: i32| (-(d2 as f32) / (2.0 * SIGMA * SIGMA)).exp();
+        let mut filter = vec![];
+        for z in -radius[2]..=radius[2] {
+            for y in -radius[1]..=radius[1] {
+                for x in -radius[0]..=radius[0] {
+                    let included = match kind {
+                        BlueNoiseKind::Isotropic => true,
+                        BlueNoiseKind::Spatiotemporal => z == 0 || (x == 0 && y == 0),
+                    };
+                    if included {
+                        filter.push(([x, y, z], weight(x * x + y * y + z * z)));
+                    }
+                }
+            }
+        }
+        let len = size.iter().product::<i32>() as usize;
+        Self {
+            size,
+            filter,
+            pattern: vec![false; len],
+            energy: vec![0.0; len],
+        }
+    }
+    fn len(&self) -> usize {
+        self.pattern.len()
+    }
+    fn set(&mut self, index: usize, value: bool) {
+        if self.pattern[index] == value {
+            return;
+        }
+        self.pattern[index] = value;
+        let sign = if value { 1.0 } else { -1.0 };
+        let [w, h, d] = self.size;
+        let index = index as i32;
+        let pos = [index % w, (index / w) % h, index / (w * h)];
+        for &(offset, weight) in &self.filter {
+            let x = (pos[0] + offset[0]).rem_euclid(w);
+            let y = (pos[1] + offset[1]).rem_euclid(h);
+            let z = (pos[2] + offset[2]).rem_euclid(d);
+            self.energy[(x + w * (y + h * z)) as usize] += sign * weight;
+        }
+    }
+    fn invert(&mut self) {
+        let pattern = std::mem::take(&mut self.pattern);
+        self.pattern = vec![false; pattern.len()];
+        self.energy.fill(0.0);
+        for (index, set) in pattern.into_iter().enumerate() {
+            self.set(index, !set);
+        }
+    }
+    fn tightest_cluster(&self) -> usize {
+        self.extremum(true, |a, b| a > b)
+    }
+    fn largest_void(&self) -> usize {
+        self.extremum(false, |a, b| a < b)
+    }
+    fn extremum(&self, value: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best = None;
+        for (index, (&set, &energy)) in self.pattern.iter().zip(&self.energy).enumerate() {
+            if set == value && best.is_none_or(|(_, e)| better(energy, e)) {
+                best = Some((index, energy));
+            }
+        }
+        best.unwrap().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_permutation(noise: &BlueNoise) {
+        let mut seen = vec![false; noise.len()];
+        for &rank in &noise.ranks {
+            assert!(!seen[rank as usize]);
+            seen[rank as usize] = true;
+        }
+    }
+
+    #[test]
+    fn ranks_are_permutation() {
+        assert_permutation(&BlueNoise::generate_2d([16, 16], 0));
+        assert_permutation(&BlueNoise::generate_2d([12, 20], 1));
+        assert_permutation(&BlueNoise::generate_3d([8, 8, 8], 2));
+        assert_permutation(&BlueNoise::generate_spatiotemporal([8, 8], 4, 3));
+    }
+
+    #[test]
+    fn thresholds_are_evenly_spread() {
+        // Every 4x4 block of a blue-noise mask thresholded at 50% should be roughly half set,
+        // unlike white noise.
+        let noise = BlueNoise::generate_2d([32, 32], 0);
+        for by in 0..8 {
+            for bx in 0..8 {
+                let count = (0..16)
+                    .filter(|i| {
+                        let (x, y) = (bx * 4 + i % 4, by * 4 + i / 4);
+                        noise.ranks[x + 32 * y] < 512
+                    })
+                    .count();
+                assert!((5..=11).contains(&count), "Block had {count} set texels.");
+            }
+        }
+    }
+
+    #[test]
+    fn spatiotemporal_slices() {
+        let noise = BlueNoise::generate_spatiotemporal([8, 8], 4, 0);
+        // Each slice should contain a similar range of values.
+        for z in 0..4 {
+            let slice = &noise.ranks[z * 64..(z + 1) * 64];
+            let below = slice.iter().filter(|&&r| r < 128).count();
+            assert!(
+                (24..=40).contains(&below),
+                "Slice {z} had {below} low texels."
+            );
+        }
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let dir = std::env::temp_dir().join(format!("yesod-blue-noise-{}", std::process::id()));
+        let noise = BlueNoise::cached(&dir, [8, 8, 2], BlueNoiseKind::Spatiotemporal, 5).unwrap();
+        let loaded = BlueNoise::cached(&dir, [8, 8, 2], BlueNoiseKind::Spatiotemporal, 5).unwrap();
+        assert_eq!(noise, loaded);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,6 +2,10 @@ use std::sync::LazyLock;
 
 use keter::lang::types::vector::{Vec2, Vec4};
 use keter::prelude::*;
+use keter::runtime::Device;
+
+use crate::color::Colormap;
+use crate::utils::memo;
 
 #[allow(clippy::type_complexity)]
 static DRAW_LINE_KERNEL: LazyLock<Kernel<fn(Tex2d<Vec4<f32>>, Vec2<f32>, Vec2<f32>, Vec4<f32>)>> =
@@ -23,3 +27,191 @@ pub fn draw_line(display: &Tex2d<Vec4<f32>>, start: Vec2<f32>, end: Vec2<f32>, c
         &color,
     );
 }
+
+// A 3x5 pixel font for numeric labels, with one row per 3 bits from the top.
+fn glyph(c: char) -> Option<u16> {
+    let rows: [u16; 5] = match c {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 1, 1],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        '.' => [0, 0, 0, 0, 2],
+        '-' => [0, 0, 7, 0, 0],
+        '+' => [0, 2, 7, 2, 0],
+        'e' => [7, 4, 7, 4, 7],
+        _ => return None,
+    };
+    Some(rows.iter().fold(0, |bits, row| (bits << 3) | row))
+}
+
+fn format_label(value: f32) -> String {
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(0.01..10000.0).contains(&magnitude) {
+        format!("{value:.1e}")
+    } else {
+        let label = format!("{value:.2}");
+        label
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    }
+}
+
+const GLYPH_SCALE: u32 = 2;
+const GLYPH_WIDTH: u32 = 4 * GLYPH_SCALE;
+const GLYPH_HEIGHT: u32 = 5 * GLYPH_SCALE;
+
+type BlitKernel = Kernel<fn(Tex2d<Vec4<f32>>, Buffer<Vec4<f32>>, u32, Vec2<u32>)>;
+
+fn blit_kernel(device: &Device) -> &'static BlitKernel {
+    memo(device.native_handle() as usize, || {
+        device.create_kernel::<fn(Tex2d<Vec4<f32>>, Buffer<Vec4<f32>>, u32, Vec2<u32>)>(&track!(
+            |display, image, width, offset| {
+                let pos = dispatch_id().xy();
+                let color = image.read(pos.x + pos.y * width);
+                if color.w > 0.0 {
+                    display.write(pos + offset, color);
+                }
+            }
+        ))
+    })
+}
+
+/// A horizontal color bar for a [`Colormap`] with evenly spaced labels below it, which is
+/// rendered once and can then be drawn every frame.
+pub struct Legend {
+    device: Device,
+    image: Buffer<Vec4<f32>>,
+    width: u32,
+    height: u32,
+}
+impl Legend {
+    /// Renders a legend for `colormap` spanning `range`, with a bar of `size` pixels and `ticks`
+    /// labels.
+    ///
+    /// The colors are display-encoded over a translucent background, as for an overlay.
+    pub fn new(colormap: Colormap, range: [f32; 2], ticks: u32, size: [u32; 2]) -> Self {
+        Self::new_on(&DEVICE, colormap, range, ticks, size)
+    }
+    pub fn new_on(
+        device: &Device,
+        colormap: Colormap,
+        range: [f32; 2],
+        ticks: u32,
+        size: [u32; 2],
+    ) -> Self {
+        const MARGIN: u32 = 4;
+        assert!(ticks >= 2, "A legend needs at least 2 ticks.");
+        let [bar_width, bar_height] = size;
+        assert!(
+            bar_width >= 2 && bar_height >= 1,
+            "The bar must be at least 2 by 1 pixels, got {size:?}."
+        );
+        let width = bar_width + 2 * MARGIN + GLYPH_WIDTH * 8;
+        let height = bar_height + 3 * MARGIN + GLYPH_HEIGHT + MARGIN;
+        // Leave room for labels hanging over either end of the bar.
+        let left = MARGIN + GLYPH_WIDTH * 4;
+        let mut image = vec![Vec4::new(0.0, 0.0, 0.0, 0.6); (width * height) as usize];
+        let mut set = |x: u32, y: u32, color: Vec4<f32>| {
+            if x < width && y < height {
+                image[(x + y * width) as usize] = color;
+            }
+        };
+        for x in 0..bar_width {
+            let c = colormap.eval_srgb(x as f32 / (bar_width - 1) as f32);
+            for y in 0..bar_height {
+                set(left + x, MARGIN + y, Vec4::new(c.x, c.y, c.z, 1.0));
+            }
+        }
+        let white = Vec4::new(1.0, 1.0, 1.0, 1.0);
+        for tick in 0..ticks {
+            let fraction = tick as f32 / (ticks - 1) as f32;
+            let x = left + (fraction * (bar_width - 1) as f32).round() as u32;
+            for y in MARGIN + bar_height..2 * MARGIN + bar_height {
+                set(x, y, white);
+            }
+            let label = format_label(range[0] + (range[1] - range[0]) * fraction);
+            let label_width = label.chars().count() as u32 * GLYPH_WIDTH - GLYPH_SCALE;
+            let start = (x + 1).saturating_sub(label_width / 2);
+            for (i, c) in label.chars().enumerate() {
+                let Some(bits) = glyph(c) else {
+                    continue;
+                };
+                for py in 0..5 * GLYPH_SCALE {
+                    for px in 0..3 * GLYPH_SCALE {
+                        let bit = 14 - (py / GLYPH_SCALE * 3 + px / GLYPH_SCALE);
+                        if (bits >> bit) & 1 != 0 {
+                            let x = start + i as u32 * GLYPH_WIDTH + px;
+                            set(x, 3 * MARGIN + bar_height + py, white);
+                        }
+                    }
+                }
+            }
+        }
+        Self {
+            device: device.clone(),
+            image: device.create_buffer_from_slice(&image),
+            width,
+            height,
+        }
+    }
+    /// The size of the legend including its labels and background, in pixels.
+    pub fn size(&self) -> [u32; 2] {
+        [self.width, self.height]
+    }
+    /// Draws the legend with its top left corner at `position`, clipping it to the display.
+    pub fn draw(&self, display: &Tex2d<Vec4<f32>>, position: [u32; 2]) {
+        let width = self.width.min(display.width().saturating_sub(position[0]));
+        let height = self
+            .height
+            .min(display.height().saturating_sub(position[1]));
+        if width == 0 || height == 0 {
+            return;
+        }
+        blit_kernel(&self.device).dispatch(
+            [width, height, 1],
+            display,
+            &self.image,
+            &self.width,
+            &Vec2::new(position[0], position[1]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::CPU_DEVICE;
+
+    #[test]
+    fn labels() {
+        assert_eq!(format_label(0.0), "0");
+        assert_eq!(format_label(0.5), "0.5");
+        assert_eq!(format_label(100.0), "100");
+        assert_eq!(format_label(-2.5), "-2.5");
+        assert_eq!(format_label(12345.0), "1.2e4");
+        assert_eq!(format_label(-0.004), "-4.0e-3");
+        for label in [format_label(-0.004), format_label(12345.0)] {
+            assert!(label.chars().all(|c| glyph(c).is_some()));
+        }
+    }
+
+    #[test]
+    fn legend() {
+        let legend = Legend::new_on(&CPU_DEVICE, Colormap::Viridis, [0.0, 1.0], 5, [100, 10]);
+        let [width, height] = legend.size();
+        assert_eq!([width, height], [100 + 8 + 64, 10 + 12 + 10 + 4]);
+        let image = legend.image.copy_to_vec();
+        assert_eq!(image.len(), (width * height) as usize);
+        // The first column of the bar, after the margin and room for a label.
+        let c = Colormap::Viridis.eval_srgb(0.0);
+        let pixel = image[(4 + 32 + 4 * width) as usize];
+        assert_eq!([pixel.x, pixel.y, pixel.z, pixel.w], [c.x, c.y, c.z, 1.0]);
+    }
+}
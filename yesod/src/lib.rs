@@ -1,12 +1,26 @@
 #![feature(array_windows)]
 
+extern crate self as yesod;
+
+// Used by the derive macros, so that dependents don't need to depend on `keter` directly.
+#[doc(hidden)]
+pub use keter;
+
 pub mod agx;
+pub mod bvh;
 pub mod camera;
 pub mod color;
+pub mod counter;
 pub mod direction;
 pub mod dither;
 pub mod drawing;
 pub mod printer;
 pub mod rand;
+pub mod sdf;
+pub mod sh;
 pub mod shapes;
+pub mod tonemap;
 pub mod utils;
+
+#[cfg(test)]
+mod tests;
@@ -1,98 +1,552 @@
 use std::cell::RefCell;
-use std::io::Write;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Display;
 use std::ptr::read_unaligned;
-use std::sync::{Arc, Mutex};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use keter::lang::types::vector::{Vector, VectorAlign};
+use keter::frame::Fence;
+use keter::graph::NodeConfigs;
+use keter::lang::types::vector::{Mat2, Mat3, Mat4, Vec2, Vec3, Vec4, Vector, VectorAlign};
 use keter::prelude::*;
+use keter::runtime::Device;
+use parking_lot::Mutex;
+pub use yesod_macro::PushBytes;
 
 #[cfg(feature = "global-print")]
 pub mod global;
+mod sink;
+#[cfg(feature = "tracing")]
+pub use sink::TracingSink;
+pub use sink::{CollectSink, PrintSink, WriteSink};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintKind {
+    Print,
+    /// A failed device assertion, which also records the `dispatch_id` of the failing thread.
+    Assert {
+        file: &'static str,
+        line: u32,
+    },
+}
 
 pub struct PrintType {
     closure: Box<dyn Fn(&mut Printer) -> String>,
     size: u32,
+    kind: PrintKind,
+}
+
+// The number of bits of a type id used for the index within a thread's types.
+const TYPE_INDEX_BITS: u32 = 20;
+
+static NEXT_REGISTRY_ID: AtomicUsize = AtomicUsize::new(0);
+
+// The part of a thread's types which other threads can access.
+#[derive(Default)]
+struct SharedTypes {
+    // The size and kind of each type, so that any thread can skip over their messages.
+    layouts: Vec<(u32, PrintKind)>,
+    // Messages decoded by other threads, which are handled by the next poll on the owning thread.
+    inbox: Vec<(u32, Vec<u8>)>,
+    exited: bool,
+}
+
+struct LocalTypes {
+    thread: u32,
+    types: Vec<Rc<PrintType>>,
+    shared: Arc<Mutex<SharedTypes>>,
+}
+impl Drop for LocalTypes {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock();
+        shared.exited = true;
+        shared.inbox.clear();
+    }
+}
+
+thread_local! {
+    // The types registered by this thread, for each registry.
+    static LOCAL_TYPES: RefCell<HashMap<usize, LocalTypes>> = RefCell::new(HashMap::new());
+}
+
+// Stores the types registered by each thread separately. Their closures are only ever run on the
+// thread that registered them, as they usually capture values which can't be sent between threads,
+// such as `Expr`s. Messages decoded on any other thread are forwarded to the registering thread.
+struct TypeRegistry {
+    id: usize,
+    threads: Mutex<Vec<Arc<Mutex<SharedTypes>>>>,
+}
+impl TypeRegistry {
+    fn new() -> Self {
+        Self {
+            id: NEXT_REGISTRY_ID.fetch_add(1, Ordering::Relaxed),
+            threads: Mutex::new(Vec::new()),
+        }
+    }
+    fn split(type_id: u32) -> (usize, usize) {
+        let thread = (type_id >> TYPE_INDEX_BITS) as usize;
+        let index = (type_id & ((1 << TYPE_INDEX_BITS) - 1)) as usize;
+        (thread, index)
+    }
+    fn push(&self, print_type: PrintType) -> u32 {
+        LOCAL_TYPES.with_borrow_mut(|local| {
+            let local = local.entry(self.id).or_insert_with(|| {
+                let mut threads = self.threads.lock();
+                let thread = threads.len() as u32;
+                // The last thread is left out, so that no type id is the end marker.
+                assert!(
+                    thread < (1 << (32 - TYPE_INDEX_BITS)) - 1,
+                    "Too many threads registering print types."
+                );
+                let shared = Arc::new(Mutex::new(SharedTypes::default()));
+                threads.push(shared.clone());
+                LocalTypes {
+                    thread,
+                    types: Vec::new(),
+                    shared,
+                }
+            });
+            let index = local.types.len() as u32;
+            assert!(
+                index < 1 << TYPE_INDEX_BITS,
+                "Too many print types registered on one thread."
+            );
+            local
+                .shared
+                .lock()
+                .layouts
+                .push((print_type.size, print_type.kind));
+            local.types.push(Rc::new(print_type));
+            (local.thread << TYPE_INDEX_BITS) | index
+        })
+    }
+    // The type is cloned out so that no borrow is held while its closure runs, which may print.
+    fn local(&self, type_id: u32) -> Option<Rc<PrintType>> {
+        let (thread, index) = Self::split(type_id);
+        LOCAL_TYPES.with_borrow(|local| {
+            let local = local.get(&self.id)?;
+            if local.thread as usize != thread {
+                return None;
+            }
+            local.types.get(index).cloned()
+        })
+    }
+    fn layout(&self, type_id: u32) -> Option<(u32, PrintKind)> {
+        let (thread, index) = Self::split(type_id);
+        let shared = self.threads.lock().get(thread)?.clone();
+        shared.lock().layouts.get(index).copied()
+    }
+    fn forward(&self, type_id: u32, data: Vec<u8>) {
+        let (thread, _) = Self::split(type_id);
+        let shared = self.threads.lock()[thread].clone();
+        let mut shared = shared.lock();
+        // Closures of exited threads are gone, so their messages can't be decoded.
+        if !shared.exited {
+            shared.inbox.push((type_id, data));
+        }
+    }
+    fn take_inbox(&self) -> Vec<(u32, Vec<u8>)> {
+        LOCAL_TYPES.with_borrow(|local| {
+            local
+                .get(&self.id)
+                .map(|local| std::mem::take(&mut local.shared.lock().inbox))
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Restricts which threads are able to print. Assertions are always recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrintFilter {
+    #[default]
+    All,
+    DispatchId([u32; 3]),
+    /// Only prints for threads with the given `dispatch_id().xy()`, such as the pixel under the cursor.
+    Pixel([u32; 2]),
+    None,
+}
+impl PrintFilter {
+    fn encode(self) -> Vec4<u32> {
+        match self {
+            PrintFilter::All => Vec4::new(0, 0, 0, 0),
+            PrintFilter::DispatchId([x, y, z]) => Vec4::new(x, y, z, 1),
+            PrintFilter::Pixel([x, y]) => Vec4::new(x, y, 0, 2),
+            PrintFilter::None => Vec4::new(0, 0, 0, 3),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssertionFailure {
+    pub file: &'static str,
+    pub line: u32,
+    pub dispatch_id: [u32; 3],
+    pub message: String,
+}
+impl Display for AssertionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "device assertion failed at {}:{} (dispatch id {:?}): {}",
+            self.file, self.line, self.dispatch_id, self.message
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssertionError {
+    pub failures: Vec<AssertionFailure>,
+}
+impl Display for AssertionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} device assertion(s) failed", self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "\n{failure}")?;
+        }
+        Ok(())
+    }
+}
+impl Error for AssertionError {}
+
+/// What happens to messages written once the buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Discards any message which doesn't fit.
+    #[default]
+    DropNewest,
+    /// Treats the buffer as a ring, overwriting the oldest messages.
+    DropOldest,
+}
+
+// Written in place of the type of the first message which didn't fit, marking the end of the data.
+const END_TYPE: u32 = u32::MAX;
+
+// The data copied back by a single flush, which is decoded once the fence is signaled.
+struct PendingFlush {
+    data: Arc<Mutex<Vec<u8>>>,
+    head: Arc<Mutex<[u64; 2]>>,
+    starts: Arc<Mutex<Vec<u64>>>,
+    fence: Fence,
 }
-impl PrintType {}
 
 pub struct PrintBuffer {
-    types: RefCell<Vec<PrintType>>,
+    device: Device,
+    types: TypeRegistry,
     data: Buffer<u8>,
+    // The number of bytes and messages written since the last flush.
     head: Buffer<u64>,
-    host_data: Arc<Mutex<Vec<u8>>>,
+    // The start of each message, used to find the messages which survived when wrapping.
+    starts: Buffer<u64>,
+    // The `PrintFilter`, with the mode stored in `w`.
+    filter: Buffer<Vec4<u32>>,
+    overflow: OverflowPolicy,
+    sink: Mutex<Box<dyn PrintSink>>,
+    pending: Mutex<VecDeque<PendingFlush>>,
 }
 impl PrintBuffer {
     pub fn new(capacity: usize) -> Self {
+        Self::new_on(&DEVICE, capacity)
+    }
+    pub fn new_on(device: &Device, capacity: usize) -> Self {
+        Self::with_overflow_policy_on(device, capacity, OverflowPolicy::DropNewest)
+    }
+    pub fn with_overflow_policy(capacity: usize, overflow: OverflowPolicy) -> Self {
+        Self::with_overflow_policy_on(&DEVICE, capacity, overflow)
+    }
+    pub fn with_overflow_policy_on(
+        device: &Device,
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> Self {
+        // Every message takes at least 4 bytes, so this can't run out before the data does.
+        let starts_len = match overflow {
+            OverflowPolicy::DropOldest => (capacity / 4).max(1),
+            OverflowPolicy::DropNewest => 1,
+        };
         Self {
-            types: RefCell::new(Vec::new()),
-            data: DEVICE.create_buffer::<u8>(capacity),
-            head: DEVICE.create_buffer_from_slice::<u64>(&[0]),
-            host_data: Arc::new(Mutex::new(vec![0; capacity])),
+            device: device.clone(),
+            types: TypeRegistry::new(),
+            data: device.create_buffer(capacity),
+            head: device.create_buffer_from_slice(&[0, 0]),
+            starts: device.create_buffer(starts_len),
+            filter: device.create_buffer_from_slice(&[PrintFilter::All.encode()]),
+            overflow,
+            sink: Mutex::new(Box::new(WriteSink::stdout())),
+            pending: Mutex::new(VecDeque::new()),
         }
     }
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow
+    }
+    pub fn set_sink(&self, sink: impl PrintSink + 'static) {
+        *self.sink.lock() = Box::new(sink);
+    }
+    pub fn set_filter(&self, filter: PrintFilter) {
+        self.filter.copy_from(&[filter.encode()]);
+    }
     pub fn add_type(&self, closure: impl Fn(&mut Printer) -> String + 'static) -> u32 {
-        let type_id = self.types.borrow().len() as u32;
+        self.add_type_with_kind(PrintKind::Print, closure)
+    }
+    pub fn add_type_with_kind(
+        &self,
+        kind: PrintKind,
+        closure: impl Fn(&mut Printer) -> String + 'static,
+    ) -> u32 {
         let mut printer = Printer::SizeQuery(PrintSize { size: 0 });
         closure(&mut printer);
-        let size = match printer {
+        let mut size = match printer {
             Printer::SizeQuery(size) => size.size,
             _ => panic!("Expected SizeQuery printer"),
         };
-        self.types.borrow_mut().push(PrintType {
+        if let PrintKind::Assert { .. } = kind {
+            size += size_of::<Vec3<u32>>() as u32;
+        }
+        self.types.push(PrintType {
             closure: Box::new(closure),
             size,
-        });
-        type_id
+            kind,
+        })
     }
     #[tracked]
+    fn passes_filter(&self) -> Expr<bool> {
+        let filter = self.filter.read(0);
+        let id = dispatch_id();
+        (filter.w == 0)
+            | ((filter.w == 1) & (id == filter.xyz()).all())
+            | ((filter.w == 2) & (id.xy() == filter.xy()).all())
+    }
+    fn enabled(&self, kind: PrintKind) -> Expr<bool> {
+        match kind {
+            PrintKind::Print => self.passes_filter(),
+            PrintKind::Assert { .. } => true.expr(),
+        }
+    }
+    /// Writes a message of the given type, which must have been registered on this thread.
     pub fn write(&self, type_id: u32) {
-        let types = self.types.borrow();
-        let print_type = &types[type_id as usize];
-        let head = self
-            .head
-            .atomic_ref(0)
-            .fetch_add(print_type.size as u64 + 4);
-        if head + print_type.size as u64 + 4 <= self.data.len_expr() {
-            let mut writer = PrintWriter {
-                data: self.data.view(..),
-                head,
-            };
-            type_id.expr().push_bytes(&mut writer);
-            let mut printer = Printer::Writer(writer);
-            (print_type.closure)(&mut printer);
+        let print_type = self
+            .types
+            .local(type_id)
+            .expect("Print types can only be written on the thread that registered them.");
+        self.write_type(type_id, &print_type);
+    }
+    #[tracked]
+    fn write_type(&self, type_id: u32, print_type: &PrintType) {
+        if self.enabled(print_type.kind) {
+            let size = print_type.size as u64 + 4;
+            let wrap = matches!(self.overflow, OverflowPolicy::DropOldest);
+            let capacity = self.data.len_expr();
+            let head = self.head.atomic_ref(0).fetch_add(size);
+            if (capacity >= size) & (wrap.expr() | (head + size <= capacity)) {
+                if wrap {
+                    let index = self.head.atomic_ref(1).fetch_add(1);
+                    self.starts.write(index % self.starts.len() as u64, head);
+                }
+                let mut writer = PrintWriter {
+                    data: self.data.view(..),
+                    head,
+                    capacity: wrap.then_some(capacity),
+                };
+                u32::push_bytes(type_id.expr(), &mut writer);
+                if let PrintKind::Assert { .. } = print_type.kind {
+                    Vec3::<u32>::push_bytes(dispatch_id(), &mut writer);
+                }
+                let mut printer = Printer::Writer(writer);
+                (print_type.closure)(&mut printer);
+            } else if !wrap {
+                // Otherwise the rest of the buffer would be decoded, which holds older messages.
+                if head + 4 <= capacity {
+                    let mut writer = PrintWriter {
+                        data: self.data.view(..),
+                        head,
+                        capacity: None,
+                    };
+                    u32::push_bytes(END_TYPE.expr(), &mut writer);
+                }
+            }
         }
     }
     pub fn print(&self, closure: impl Fn(&mut Printer) -> String + 'static) {
         let type_id = self.add_type(closure);
         self.write(type_id);
     }
-    pub fn flush(&self) {
-        self.data.copy_to(&mut self.host_data.lock().unwrap());
-        let mut size = 0;
-        self.head.copy_to(std::slice::from_mut(&mut size));
-        if size > self.data.len() as u64 {
-            eprintln!(
-                "Warning: print buffer overflow. Total memory used {} > buffer size {}",
-                size,
-                self.data.len()
-            );
-            size = self.data.len() as u64;
+    /// Records a failure if `cond` is false, which is returned as an error from [`PrintBuffer::flush`].
+    #[tracked]
+    pub fn assert(
+        &self,
+        cond: Expr<bool>,
+        file: &'static str,
+        line: u32,
+        closure: impl Fn(&mut Printer) -> String + 'static,
+    ) {
+        let type_id = self.add_type_with_kind(PrintKind::Assert { file, line }, closure);
+        if !cond {
+            self.write(type_id);
+        }
+    }
+    /// Copies the messages written so far back to the host and resets the buffer.
+    /// The messages are decoded by [`PrintBuffer::poll`] once the returned nodes finish executing.
+    pub fn flush_async(&self) -> NodeConfigs<'static> {
+        let pending = PendingFlush {
+            data: Arc::new(Mutex::new(vec![0; self.capacity()])),
+            head: Arc::new(Mutex::new([0; 2])),
+            starts: Arc::new(Mutex::new(vec![0; self.starts.len()])),
+            fence: Fence::new(),
+        };
+        let nodes = (
+            (
+                self.data.view(..).copy_to_shared(&pending.data),
+                self.head.view(..).copy_to_shared(&pending.head),
+                self.starts.view(..).copy_to_shared(&pending.starts),
+            ),
+            self.head.clear_on(&self.device),
+            pending.fence.signal_node(),
+        )
+            .chain();
+        self.pending.lock().push_back(pending);
+        nodes
+    }
+    /// Sends the messages of every completed [`PrintBuffer::flush_async`] to the sink,
+    /// returning an error if any assertions failed.
+    ///
+    /// Messages of types registered on other threads are forwarded to them, and handled by their next poll.
+    pub fn poll(&self) -> Result<(), AssertionError> {
+        let mut failures = vec![];
+        for (type_id, data) in self.types.take_inbox() {
+            let print_ty = self.types.local(type_id).unwrap();
+            let mut printer = Printer::Reader(PrintReader {
+                data: Arc::new(data),
+                head: 0,
+            });
+            self.run(&print_ty, &mut printer, &mut failures);
+        }
+        loop {
+            let pending = {
+                let mut pending = self.pending.lock();
+                match pending.front() {
+                    Some(flush) if flush.fence.is_signaled() => pending.pop_front().unwrap(),
+                    _ => break,
+                }
+            };
+            self.decode(pending, &mut failures);
+        }
+        self.sink.lock().flush();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(AssertionError { failures })
         }
+    }
+    /// Prints every message written since the last flush, returning an error if any assertions failed.
+    pub fn flush(&self) -> Result<(), AssertionError> {
+        self.flush_async()
+            .execute_in(&self.device.default_stream().scope());
+        let fence = self
+            .pending
+            .lock()
+            .back()
+            .map(|pending| pending.fence.clone());
+        if let Some(fence) = fence {
+            fence.wait();
+        }
+        self.poll()
+    }
+    fn decode(&self, pending: PendingFlush, failures: &mut Vec<AssertionFailure>) {
+        let data = std::mem::take(&mut *pending.data.lock());
+        let [size, count] = *pending.head.lock();
+        let capacity = data.len() as u64;
         let mut printer = Printer::Reader(PrintReader {
-            data: self.host_data.clone(),
+            data: Arc::new(data),
             head: 0,
         });
-        while printer.as_reader().head + 4 <= size {
-            let type_id = printer.as_reader().pop_type();
-            let print_ty = &self.types.borrow()[type_id as usize];
-            if printer.as_reader().head + print_ty.size as u64 > size {
-                break;
+        if size <= capacity {
+            while printer.as_reader().head + 4 <= size {
+                if !self.decode_message(&mut printer, size, failures) {
+                    break;
+                }
+            }
+            return;
+        }
+        match self.overflow {
+            OverflowPolicy::DropNewest => {
+                while printer.as_reader().head + 4 <= capacity {
+                    if !self.decode_message(&mut printer, capacity, failures) {
+                        break;
+                    }
+                }
+            }
+            OverflowPolicy::DropOldest => {
+                let starts = pending.starts.lock();
+                let mut starts = starts[..(count as usize).min(starts.len())]
+                    .iter()
+                    .copied()
+                    .filter(|&start| start >= size - capacity)
+                    .collect::<Vec<_>>();
+                starts.sort_unstable();
+                for start in starts {
+                    printer.as_reader().head = start;
+                    self.decode_message(&mut printer, size, failures);
+                }
+            }
+        }
+        eprintln!(
+            "Warning: print buffer overflow. Total memory used {} > buffer size {}",
+            size, capacity
+        );
+    }
+    // Returns false if there are no more messages before `end`.
+    fn decode_message(
+        &self,
+        printer: &mut Printer,
+        end: u64,
+        failures: &mut Vec<AssertionFailure>,
+    ) -> bool {
+        let type_id = printer.as_reader().pop_type();
+        if type_id == END_TYPE {
+            return false;
+        }
+        let Some((size, _)) = self.types.layout(type_id) else {
+            eprintln!("Warning: print buffer is corrupted, found unknown message type {type_id}");
+            return false;
+        };
+        if printer.as_reader().head + size as u64 > end {
+            return false;
+        }
+        match self.types.local(type_id) {
+            Some(print_ty) => self.run(&print_ty, printer, failures),
+            None => {
+                let data = printer.as_reader().read(size as usize, <[u8]>::to_vec);
+                self.types.forward(type_id, data);
+            }
+        }
+        true
+    }
+    fn run(
+        &self,
+        print_ty: &PrintType,
+        printer: &mut Printer,
+        failures: &mut Vec<AssertionFailure>,
+    ) {
+        match print_ty.kind {
+            PrintKind::Print => {
+                let output = (print_ty.closure)(printer);
+                self.sink.lock().print(&output);
+            }
+            PrintKind::Assert { file, line } => {
+                let dispatch_id = printer.load(dispatch_id);
+                let message = (print_ty.closure)(printer);
+                failures.push(AssertionFailure {
+                    file,
+                    line,
+                    dispatch_id: dispatch_id.into(),
+                    message,
+                });
             }
-            let output = (print_ty.closure)(&mut printer);
-            print!("{output}");
         }
-        std::io::stdout().flush().unwrap();
-        self.head.copy_from(&[0]);
     }
 }
 
@@ -110,25 +564,49 @@ impl PrintSize {
 pub struct PrintWriter {
     data: BufferView<u8>,
     head: Expr<u64>,
+    // Set if writes should wrap around the end of the buffer.
+    capacity: Option<Expr<u64>>,
 }
 impl PrintWriter {
-    #[tracked]
     fn write_byte(&mut self, byte: Expr<u8>) {
         let head = self.head;
-        self.data.write(head, byte);
+        let index = match self.capacity {
+            Some(capacity) => head % capacity,
+            None => head,
+        };
+        self.data.write(index, byte);
         self.head = head + 1;
     }
+    pub fn head(&self) -> Expr<u64> {
+        self.head
+    }
+    /// Moves the head to `offset` bytes after `start`, skipping over any padding.
+    #[tracked]
+    pub fn seek(&mut self, start: Expr<u64>, offset: usize) {
+        self.head = start + offset as u64;
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PrintReader {
-    data: Arc<Mutex<Vec<u8>>>,
+    data: Arc<Vec<u8>>,
+    // The offset of the next value, which wraps around the end of the data.
     head: u64,
 }
 impl PrintReader {
     fn read<T>(&mut self, size: usize, f: impl FnOnce(&[u8]) -> T) -> T {
-        let data = &self.data.lock().unwrap()[self.head as usize..(self.head as usize + size)];
-        let output = f(data);
+        let len = self.data.len();
+        let start = (self.head % len as u64) as usize;
+        let output = if start + size <= len {
+            f(&self.data[start..start + size])
+        } else {
+            let bytes = self.data[start..]
+                .iter()
+                .chain(&self.data[..start + size - len])
+                .copied()
+                .collect::<Vec<_>>();
+            f(&bytes)
+        };
         self.head += size as u64;
         output
     }
@@ -154,19 +632,16 @@ impl Printer {
         }
     }
     // Problem: Computing value can cause segfaults sometimes if not capturing.
-    pub fn load<T>(&mut self, value: impl Fn() -> Expr<T>) -> T
-    where
-        T: Default + Value,
-        Expr<T>: PushBytes,
-    {
+    pub fn load<T: PushBytes>(&mut self, value: impl Fn() -> Expr<T>) -> T {
         match self {
             Self::SizeQuery(size) => {
                 size.add(std::mem::size_of::<T>() as u32);
-                T::default()
+                // SAFETY: Values are plain-old-data, for which the zero bit pattern is valid.
+                unsafe { std::mem::zeroed() }
             }
             Self::Writer(writer) => {
-                value().push_bytes(writer);
-                T::default()
+                T::push_bytes(value(), writer);
+                unsafe { std::mem::zeroed() }
             }
             Self::Reader(reader) => reader.read(std::mem::size_of::<T>(), |data| unsafe {
                 read_unaligned(data.as_ptr() as *const T)
@@ -175,24 +650,27 @@ impl Printer {
     }
 }
 
-pub trait PushBytes {
-    fn push_bytes(self, writer: &mut PrintWriter);
+/// A value which can be written into a [`PrintBuffer`] from a kernel.
+///
+/// Implementations must write the value in its host memory layout, leaving the writer's head
+/// `size_of::<Self>()` bytes after where it started. Padding may be skipped using [`PrintWriter::seek`].
+pub trait PushBytes: Value {
+    fn push_bytes(value: Expr<Self>, writer: &mut PrintWriter);
 }
-impl PushBytes for Expr<u8> {
-    fn push_bytes(self, writer: &mut PrintWriter) {
-        writer.write_byte(self);
+impl PushBytes for u8 {
+    fn push_bytes(value: Expr<u8>, writer: &mut PrintWriter) {
+        writer.write_byte(value);
     }
 }
 macro_rules! gen_push_bytes {
     ($t:ty: $n:literal $(, $($tt:tt)*)?) => {
-        impl PushBytes for Expr<$t> {
-            fn push_bytes(self, writer: &mut PrintWriter) {
-                let bytes: Expr<[u8; $n]> = self.bitcast();
+        impl PushBytes for $t {
+            fn push_bytes(value: Expr<$t>, writer: &mut PrintWriter) {
+                let bytes: Expr<[u8; $n]> = value.bitcast();
                 for i in 0..$n {
                     let byte: Expr<u8> = bytes.read(i as u32);
                     writer.write_byte(byte);
                 }
-                // bytes.push_bytes(writer);
             }
         }
         $(gen_push_bytes!($($tt)*);)?
@@ -212,70 +690,261 @@ gen_push_bytes!(
     bool: 1
 );
 
-// TODO: Weird bug happens if we try to implement it directly.
-/*
-error[E0275]: overflow evaluating the requirement `Expr<keter::lang::types::vector::Vector<_, _>>: PushBytes`
-   --> yesod/examples/print.rs:12:25
-    |
-12  |                 printer.load(index),
-    |                         ^^^^
-    |
-    = help: consider increasing the recursion limit by adding a `#![recursion_limit = "256"]` attribute to your crate (`print`)
-    = note: required for `Expr<Vector<Vector<_, _>, _>>` to implement `PushBytes`
-    = note: 126 redundant requirements hidden
-    = note: required for `Expr<Vector<Vector<Vector<Vector<..., _>, _>, _>, _>>` to implement `PushBytes`
-note: required by a bound in `yesod::printer::Printer::<'a>::load`
-   --> /home/keter/Documents/sefirot/yesod/src/printer.rs:141:18
-    |
-138 |     pub fn load<T>(&mut self, value: Expr<T>) -> T
-    |            ---- required by a bound in this associated function
-...
-141 |         Expr<T>: PushBytes,
-    |                  ^^^^^^^^^ required by this bound in `Printer::<'a>::load`
-    = note: the full name for the type has been written to '/home/keter/Documents/sefirot/target/debug/examples/print-2a58c877743e6fbd.long-type-1279998347952659411.txt'
-    = note: consider using `--verbose` to print the full type name to the console
-*/
-macro_rules! gen_vector_push_bytes {
-    ($t:ty $(, $($tt:tt)*)?) => {
-        impl<const N: usize> PushBytes for Expr<Vector<$t, N>>
-        where
-            Expr<$t>: PushBytes,
-            Expr<[ $t; N ]>: From<Expr<Vector<$t, N>>>,
-            $t: VectorAlign<N>,
-        {
-            fn push_bytes(self, writer: &mut PrintWriter) {
-                let v: Expr<[ $t; N ]> = self.into();
-                for i in 0..N {
-                    let element: Expr<$t> = v.read(i as u32);
-                    element.push_bytes(writer);
-                }
-            }
+impl<T: PushBytes, const N: usize> PushBytes for [T; N] {
+    fn push_bytes(value: Expr<[T; N]>, writer: &mut PrintWriter) {
+        let start = writer.head();
+        for i in 0..N {
+            writer.seek(start, i * size_of::<T>());
+            T::push_bytes(value.read(i as u32), writer);
         }
-        $(gen_vector_push_bytes!($($tt)*);)?
+        writer.seek(start, size_of::<[T; N]>());
     }
 }
-gen_vector_push_bytes!(u8, u16, u32, u64, i8, i16, i32, i64, f16, f32, f64, bool);
-
-/*
-impl<T: Value, const N: usize> PushBytes for Expr<[T; N]>
+impl<T: PushBytes + VectorAlign<N>, const N: usize> PushBytes for Vector<T, N>
 where
-    Expr<T>: PushBytes,
+    Expr<[T; N]>: From<Expr<Vector<T, N>>>,
 {
-    fn push_bytes(self, writer: &mut PrintWriter) {
+    fn push_bytes(value: Expr<Vector<T, N>>, writer: &mut PrintWriter) {
+        let start = writer.head();
+        let elements: Expr<[T; N]> = value.into();
         for i in 0..N {
-            let element: Expr<T> = self.read(i as u32);
-            element.push_bytes(writer);
+            writer.seek(start, i * size_of::<T>());
+            T::push_bytes(elements.read(i as u32), writer);
         }
+        // Vectors may be padded, such as `Vec3<f32>` being 16 bytes.
+        writer.seek(start, size_of::<Vector<T, N>>());
     }
 }
-impl<const N: usize, T: VectorAlign<N>> PushBytes for Expr<Vector<T, N>>
-where
-    Expr<T>: PushBytes,
-    Expr<[T; N]>: From<Expr<Vector<T, N>>>,
-{
-    fn push_bytes(self, writer: &mut PrintWriter) {
-        let v: Expr<[T; N]> = self.into();
-        v.push_bytes(writer);
+macro_rules! gen_matrix_push_bytes {
+    ($($m:ty: $v:ty, $n:literal),*) => {
+        $(
+            impl PushBytes for $m {
+                fn push_bytes(value: Expr<$m>, writer: &mut PrintWriter) {
+                    let start = writer.head();
+                    for i in 0..$n {
+                        writer.seek(start, i * size_of::<$v>());
+                        <$v>::push_bytes(value.col(i as u32), writer);
+                    }
+                    writer.seek(start, size_of::<$m>());
+                }
+            }
+        )*
+    };
+}
+gen_matrix_push_bytes!(Mat2: Vec2<f32>, 2, Mat3: Vec3<f32>, 3, Mat4: Vec4<f32>, 4);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+
+    use super::*;
+    use crate::tests::CPU_DEVICE;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Value, PushBytes)]
+    struct Padded {
+        a: u32,
+        b: Vec3<f32>,
+        c: bool,
+    }
+
+    #[test]
+    fn round_trip() {
+        let structs = (0..4)
+            .map(|i| Padded {
+                a: i,
+                b: Vec3::new(1.0, 2.0, i as f32),
+                c: i % 2 == 1,
+            })
+            .collect::<Vec<_>>();
+        let arrays = (0..4).map(|i| [i as i16, -7, 300]).collect::<Vec<_>>();
+        let matrices = (0..4)
+            .map(|i| {
+                let i = i as f32;
+                Mat3::from_column_array(&[[i, 1.0, 2.0], [3.0, i, 4.0], [5.0, 6.0, i]])
+            })
+            .collect::<Vec<_>>();
+        let struct_buffer = CPU_DEVICE.create_buffer_from_slice(&structs);
+        let array_buffer = CPU_DEVICE.create_buffer_from_slice(&arrays);
+        let matrix_buffer = CPU_DEVICE.create_buffer_from_slice(&matrices);
+
+        let print_buffer = PrintBuffer::new_on(&CPU_DEVICE, 4096);
+        let loaded = Arc::new(Mutex::new(Vec::new()));
+        let kernel = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+            let i = dispatch_id().x;
+            let value = struct_buffer.read(i);
+            let array = array_buffer.read(i);
+            let matrix = matrix_buffer.read(i);
+            let loaded = loaded.clone();
+            print_buffer.print(move |printer| {
+                let value = printer.load(|| value);
+                let array = printer.load(|| array);
+                let matrix = printer.load(|| matrix);
+                loaded.lock().push((value, array, matrix));
+                String::new()
+            });
+        }));
+        // Recording the kernel also calls the closure.
+        loaded.lock().clear();
+        kernel.dispatch([4, 1, 1]);
+        print_buffer.flush().unwrap();
+
+        let mut loaded = loaded.lock().clone();
+        loaded.sort_by_key(|(value, _, _)| value.a);
+        let expected = (0..4)
+            .map(|i| (structs[i], arrays[i], matrices[i]))
+            .collect::<Vec<_>>();
+        assert_eq!(loaded, expected);
+    }
+
+    #[test]
+    fn assertions() {
+        let print_buffer = PrintBuffer::new_on(&CPU_DEVICE, 4096);
+        let kernel = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+            let i = dispatch_id().x;
+            print_buffer.assert(i != 2, "test.rs", 7, move |printer| {
+                format!("i = {}", printer.load(|| i))
+            });
+        }));
+        kernel.dispatch([4, 1, 1]);
+        let error = print_buffer.flush().unwrap_err();
+        assert_eq!(
+            error.failures,
+            [AssertionFailure {
+                file: "test.rs",
+                line: 7,
+                dispatch_id: [2, 0, 0],
+                message: "i = 2".to_string(),
+            }]
+        );
+        // Failures are cleared by flushing.
+        print_buffer.flush().unwrap();
+    }
+
+    #[test]
+    fn filter() {
+        let print_buffer = PrintBuffer::new_on(&CPU_DEVICE, 4096);
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let kernel = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+            let id = dispatch_id().xy();
+            let printed = printed.clone();
+            print_buffer.print(move |printer| {
+                let id: Vec2<u32> = printer.load(|| id);
+                printed.lock().push([id.x, id.y]);
+                String::new()
+            });
+        }));
+        let run = |filter| {
+            print_buffer.set_filter(filter);
+            printed.lock().clear();
+            kernel.dispatch([2, 2, 1]);
+            print_buffer.flush().unwrap();
+            let mut printed = printed.lock().clone();
+            printed.sort();
+            printed
+        };
+        assert_eq!(run(PrintFilter::All), [[0, 0], [0, 1], [1, 0], [1, 1]]);
+        assert_eq!(run(PrintFilter::Pixel([1, 0])), [[1, 0]]);
+        assert_eq!(run(PrintFilter::DispatchId([0, 1, 0])), [[0, 1]]);
+        assert!(run(PrintFilter::None).is_empty());
+    }
+
+    #[test]
+    fn flush_async() {
+        let print_buffer = PrintBuffer::new_on(&CPU_DEVICE, 4096);
+        let sink = CollectSink::new();
+        print_buffer.set_sink(sink.clone());
+        let kernel = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+            let i = dispatch_id().x;
+            print_buffer.print(move |printer| format!("{}", printer.load(|| i)));
+        }));
+        kernel.dispatch([3, 1, 1]);
+        let nodes = print_buffer.flush_async();
+        // Nothing is decoded until the copy has executed.
+        print_buffer.poll().unwrap();
+        assert!(sink.messages().is_empty());
+        nodes.execute_in(&CPU_DEVICE.default_stream().scope());
+        print_buffer.poll().unwrap();
+        let mut messages = sink.take();
+        messages.sort();
+        assert_eq!(messages, ["0", "1", "2"]);
+        print_buffer.poll().unwrap();
+        assert!(sink.messages().is_empty());
+    }
+
+    #[test]
+    fn drop_newest() {
+        // Fits exactly five 6 byte messages, or three 8 byte messages.
+        let print_buffer = PrintBuffer::new_on(&CPU_DEVICE, 30);
+        let sink = CollectSink::new();
+        print_buffer.set_sink(sink.clone());
+        let short = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+            let i = dispatch_id().x.cast_u16();
+            print_buffer.print(move |printer| format!("short {}", printer.load(|| i)));
+        }));
+        let long = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+            let i = dispatch_id().x;
+            print_buffer.print(move |printer| format!("long {}", printer.load(|| i)));
+        }));
+        short.dispatch([5, 1, 1]);
+        print_buffer.flush().unwrap();
+        assert_eq!(sink.take().len(), 5);
+
+        // The last 6 bytes aren't overwritten, but must not be decoded as a short message.
+        long.dispatch([5, 1, 1]);
+        print_buffer.flush().unwrap();
+        let messages = sink.take();
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|message| message.starts_with("long")));
+    }
+
+    #[test]
+    fn drop_oldest() {
+        // Fits four 8 byte messages.
+        let print_buffer =
+            PrintBuffer::with_overflow_policy_on(&CPU_DEVICE, 32, OverflowPolicy::DropOldest);
+        let sink = CollectSink::new();
+        print_buffer.set_sink(sink.clone());
+        let kernel = CPU_DEVICE.create_kernel::<fn(u32)>(&track!(|base| {
+            let i = base + dispatch_id().x;
+            print_buffer.print(move |printer| format!("{}", printer.load(|| i)));
+        }));
+        // Separate dispatches, so that later messages are always written after the ones they overwrite.
+        for base in [0, 2, 4] {
+            kernel.dispatch([2, 1, 1], &base);
+        }
+        print_buffer.flush().unwrap();
+        let mut messages = sink.take();
+        messages.sort();
+        assert_eq!(messages, ["2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn foreign_thread() {
+        let print_buffer = PrintBuffer::new_on(&CPU_DEVICE, 4096);
+        let sink = CollectSink::new();
+        print_buffer.set_sink(sink.clone());
+        let dispatched = Barrier::new(2);
+        let flushed = Barrier::new(2);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let kernel = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+                    let i = dispatch_id().x;
+                    print_buffer.print(move |printer| format!("{}", printer.load(|| i)));
+                }));
+                kernel.dispatch([2, 1, 1]);
+                dispatched.wait();
+                flushed.wait();
+                // The messages were decoded by the main thread, but are handled here.
+                print_buffer.poll().unwrap();
+            });
+            dispatched.wait();
+            print_buffer.flush().unwrap();
+            assert!(sink.messages().is_empty());
+            flushed.wait();
+        });
+        let mut messages = sink.take();
+        messages.sort();
+        assert_eq!(messages, ["0", "1"]);
     }
 }
- */
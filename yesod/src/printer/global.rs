@@ -1,50 +1,81 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{LazyLock, Mutex};
-
-use fragile::Fragile;
+use std::sync::{LazyLock, OnceLock};
 
 use super::*;
 
 static CAPACITY: AtomicUsize = AtomicUsize::new(1024 * 1024);
-static GLOBAL_PRINT_BUFFER: LazyLock<Fragile<PrintBuffer>> =
-    LazyLock::new(|| Fragile::new(PrintBuffer::new(CAPACITY.load(Ordering::SeqCst))));
+static GLOBAL_DEVICE: OnceLock<Device> = OnceLock::new();
+// Kernels may be built on any thread, as the buffer registers print types per thread.
+static GLOBAL_PRINT_BUFFER: LazyLock<PrintBuffer> = LazyLock::new(|| {
+    PrintBuffer::new_on(
+        GLOBAL_DEVICE.get().unwrap_or(&DEVICE),
+        CAPACITY.load(Ordering::SeqCst),
+    )
+});
 
-static CURRENT_PRINTER: Mutex<Option<Fragile<Printer>>> = Mutex::new(None);
+thread_local! {
+    // The printer of the print closure currently running on this thread.
+    static CURRENT_PRINTER: RefCell<Option<Printer>> = const { RefCell::new(None) };
+}
 
+/// Sets the capacity of the global print buffer. Must be called before anything is printed.
 pub fn set_capacity(capacity: usize) {
     CAPACITY.store(capacity, Ordering::SeqCst);
-    GLOBAL_PRINT_BUFFER.get();
+    LazyLock::force(&GLOBAL_PRINT_BUFFER);
+}
+/// Sets the device of the global print buffer, which defaults to [`DEVICE`].
+/// Must be called before the global print buffer is first used.
+pub fn set_device(device: Device) {
+    assert!(
+        GLOBAL_DEVICE.set(device).is_ok(),
+        "The global print device has already been set."
+    );
 }
 
 #[doc(hidden)]
-pub fn _host<T>(expr: impl Fn() -> Expr<T>) -> T
-where
-    T: Default + Value,
-    Expr<T>: PushBytes,
-{
-    let mut guard = CURRENT_PRINTER.lock().unwrap();
-    let printer = guard
-        .as_mut()
-        .expect("This can only be called inside a print closure.");
-    printer.get_mut().load(expr)
+pub fn _host<T: PushBytes>(expr: impl Fn() -> Expr<T>) -> T {
+    CURRENT_PRINTER.with_borrow_mut(|printer| {
+        printer
+            .as_mut()
+            .expect("This can only be called inside a print closure.")
+            .load(expr)
+    })
 }
 
-pub fn device_print(closure: impl Fn() -> String + 'static) {
-    GLOBAL_PRINT_BUFFER.get().print(move |printer| {
+// Wraps the closure so that `host!` can access the printer while it runs.
+fn with_current_printer(
+    closure: impl Fn() -> String + 'static,
+) -> impl Fn(&mut Printer) -> String + 'static {
+    move |printer| {
         let mut output = String::new();
         take_mut::take(printer, |printer| {
-            let mut guard = CURRENT_PRINTER.lock().unwrap();
-            *guard = Some(Fragile::new(printer));
-            drop(guard);
+            CURRENT_PRINTER.set(Some(printer));
             output = closure();
-            CURRENT_PRINTER.lock().unwrap().take().unwrap().into_inner()
+            CURRENT_PRINTER.take().unwrap()
         });
         output
-    });
+    }
+}
+
+pub fn device_print(closure: impl Fn() -> String + 'static) {
+    GLOBAL_PRINT_BUFFER.print(with_current_printer(closure));
 }
 pub fn device_println(closure: impl Fn() -> String + 'static) {
     device_print(move || format!("{}\n", closure()));
 }
+pub fn device_assert(
+    cond: Expr<bool>,
+    file: &'static str,
+    line: u32,
+    closure: impl Fn() -> String + 'static,
+) {
+    GLOBAL_PRINT_BUFFER.assert(cond, file, line, with_current_printer(closure));
+}
+pub fn set_print_filter(filter: PrintFilter) {
+    GLOBAL_PRINT_BUFFER.set_filter(filter);
+}
+pub fn set_print_sink(sink: impl PrintSink + 'static) {
+    GLOBAL_PRINT_BUFFER.set_sink(sink);
+}
 
 #[macro_export]
 macro_rules! device_print {
@@ -59,11 +90,91 @@ macro_rules! device_println {
     };
 }
 
+/// Records a failure if the condition is false for any thread,
+/// which is returned as an error from [`flush_printer`].
+#[macro_export]
+macro_rules! device_assert {
+    ($cond:expr $(,)?) => {
+        ::yesod::printer::global::device_assert(
+            ::yesod::keter::prelude::track!($cond),
+            file!(),
+            line!(),
+            move || stringify!($cond).to_string(),
+        )
+    };
+    ($cond:expr, $($arg:tt)*) => {
+        ::yesod::printer::global::device_assert(
+            ::yesod::keter::prelude::track!($cond),
+            file!(),
+            line!(),
+            move || format!($($arg)*),
+        )
+    };
+}
+
 #[macro_export]
 macro_rules! host {
     ($expr:expr) => {{ ::yesod::printer::global::_host(|| $expr) }};
 }
 
-pub fn flush_printer() {
-    GLOBAL_PRINT_BUFFER.get().flush();
+pub fn flush_printer() -> Result<(), AssertionError> {
+    GLOBAL_PRINT_BUFFER.flush()
+}
+pub fn flush_printer_async() -> NodeConfigs<'static> {
+    GLOBAL_PRINT_BUFFER.flush_async()
+}
+pub fn poll_printer() -> Result<(), AssertionError> {
+    GLOBAL_PRINT_BUFFER.poll()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::tests::CPU_DEVICE;
+    use crate::{device_assert, device_println, host};
+
+    // The tests share the global print buffer, so they run one at a time.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock() -> std::sync::MutexGuard<'static, ()> {
+        GLOBAL_DEVICE.get_or_init(|| CPU_DEVICE.clone());
+        LOCK.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    #[test]
+    fn global_assert() {
+        let _lock = lock();
+        let kernel = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+            let i = dispatch_id().x;
+            device_assert!(i != 1, "i = {}", host!(i));
+        }));
+        kernel.dispatch([4, 1, 1]);
+        let error = flush_printer().unwrap_err();
+        assert_eq!(error.failures.len(), 1);
+        assert_eq!(error.failures[0].dispatch_id, [1, 0, 0]);
+        assert_eq!(error.failures[0].message, "i = 1");
+    }
+
+    #[test]
+    fn captured_expr() {
+        let _lock = lock();
+        let sink = CollectSink::new();
+        set_print_sink(sink.clone());
+        let kernel = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+            let i = dispatch_id().x;
+            let square = i * i;
+            device_println!("{} squared is {}", host!(i), host!(square));
+        }));
+        kernel.dispatch([3, 1, 1]);
+        flush_printer().unwrap();
+        set_print_sink(WriteSink::stdout());
+        let mut messages = sink.take();
+        messages.sort();
+        assert_eq!(
+            messages,
+            ["0 squared is 0\n", "1 squared is 1\n", "2 squared is 4\n"]
+        );
+    }
 }
@@ -0,0 +1,89 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+/// A destination for decoded device messages, which may be flushed from any thread.
+pub trait PrintSink: Send {
+    fn print(&mut self, message: &str);
+    fn flush(&mut self) {}
+}
+
+/// Writes messages to an [`io::Write`](std::io::Write), such as stdout or a log file.
+#[derive(Debug)]
+pub struct WriteSink<W: Write>(pub W);
+impl WriteSink<std::io::Stdout> {
+    pub fn stdout() -> Self {
+        Self(std::io::stdout())
+    }
+}
+impl WriteSink<std::io::Stderr> {
+    pub fn stderr() -> Self {
+        Self(std::io::stderr())
+    }
+}
+impl<W: Write> PrintSink for WriteSink<W> {
+    fn print(&mut self, message: &str) {
+        self.0.write_all(message.as_bytes()).unwrap();
+    }
+    fn flush(&mut self) {
+        self.0.flush().unwrap();
+    }
+}
+
+/// Emits each message as a `tracing` event, with trailing newlines removed.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy)]
+pub struct TracingSink {
+    pub level: tracing::Level,
+}
+#[cfg(feature = "tracing")]
+impl Default for TracingSink {
+    fn default() -> Self {
+        Self {
+            level: tracing::Level::INFO,
+        }
+    }
+}
+#[cfg(feature = "tracing")]
+impl PrintSink for TracingSink {
+    fn print(&mut self, message: &str) {
+        use tracing::Level;
+        let message = message.trim_end_matches('\n');
+        // The level of `tracing::event!` must be a constant.
+        if self.level == Level::ERROR {
+            tracing::error!(target: "device", "{message}");
+        } else if self.level == Level::WARN {
+            tracing::warn!(target: "device", "{message}");
+        } else if self.level == Level::INFO {
+            tracing::info!(target: "device", "{message}");
+        } else if self.level == Level::DEBUG {
+            tracing::debug!(target: "device", "{message}");
+        } else {
+            tracing::trace!(target: "device", "{message}");
+        }
+    }
+}
+
+/// Collects every message, one per print call. Clones share the same messages.
+#[derive(Debug, Clone, Default)]
+pub struct CollectSink {
+    messages: Arc<Mutex<Vec<String>>>,
+}
+impl CollectSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn messages(&self) -> Vec<String> {
+        self.messages.lock().clone()
+    }
+    /// Removes and returns every message collected so far.
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.messages.lock())
+    }
+}
+impl PrintSink for CollectSink {
+    fn print(&mut self, message: &str) {
+        self.messages.lock().push(message.to_string());
+    }
+}
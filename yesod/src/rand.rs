@@ -1,9 +1,10 @@
-use std::f32::consts::PI;
+use std::f32::consts::{PI, TAU};
 
-use keter::lang::types::vector::{Vec2, Vec3};
+use keter::lang::types::vector::{Vec2, Vec3, Vec4};
 use keter::prelude::*;
 
-// TODO: https://www.reedbeta.com/blog/hash-functions-for-gpu-rendering/
+pub mod hash;
+pub mod sobol;
 
 // https://github.com/markjarzynski/PCG3D/blob/master/pcg3d.hlsl
 #[tracked]
@@ -24,6 +25,27 @@ pub fn pcg3d(v: Expr<Vec3<u32>>) -> Expr<Vec3<u32>> {
     **v
 }
 
+// https://jcgt.org/published/0009/03/02/
+#[tracked]
+pub fn pcg4d(v: Expr<Vec4<u32>>) -> Expr<Vec4<u32>> {
+    let v = v.var();
+    *v = v * 1664525u32 + 1013904223u32;
+
+    *v.x += v.y * v.w;
+    *v.y += v.z * v.x;
+    *v.z += v.x * v.y;
+    *v.w += v.y * v.z;
+
+    *v ^= v >> 16u32;
+
+    *v.x += v.y * v.w;
+    *v.y += v.z * v.x;
+    *v.z += v.x * v.y;
+    *v.w += v.y * v.z;
+
+    **v
+}
+
 #[tracked]
 pub fn pcg(v: Expr<u32>) -> Expr<u32> {
     let state = v * 747796405u32 + 2891336453u32;
@@ -68,6 +90,35 @@ pub fn r2(t: Expr<u32>) -> Expr<Vec2<f32>> {
     (t * a).cast_f32() / max as f32
 }
 
+/// The radical inverse of `index` in the given base, which is the `base` dimension of the Halton sequence.
+pub fn halton(index: Expr<u32>, base: u32) -> Expr<f32> {
+    if base == 2 {
+        halton_2(index)
+    } else {
+        radical_inverse(index, base)
+    }
+}
+#[tracked]
+fn halton_2(index: Expr<u32>) -> Expr<f32> {
+    (sobol::reverse_bits(index) >> 8).cast_f32() / (1 << 24) as f32
+}
+#[tracked]
+fn radical_inverse(index: Expr<u32>, base: u32) -> Expr<f32> {
+    let index = index.var();
+    let scale = 1.0_f32.var();
+    let result = 0.0_f32.var();
+    let inv_base = 1.0 / base as f32;
+    loop {
+        if index == 0 {
+            break;
+        }
+        *scale *= inv_base;
+        *result += scale * (index % base).cast_f32();
+        *index /= base;
+    }
+    keter::min(**result, 1.0 - f32::EPSILON / 2.0)
+}
+
 // https://extremelearning.com.au/a-simple-method-to-construct-isotropic-quasirandom-blue-noise-point-sequences/
 #[tracked]
 pub fn r2blue(t: Expr<u32>) -> Expr<Vec2<f32>> {
@@ -77,3 +128,335 @@ pub fn r2blue(t: Expr<u32>) -> Expr<Vec2<f32>> {
     let offset = PI.sqrt() * pcg3df(Vec3::expr(t, 3928, 1731)).xy();
     (r2_v + l * offset).fract()
 }
+
+const PCG32_MULTIPLIER: u64 = 6364136223846793005;
+
+/// A PCG32 random number generator, with a 64-bit state and a stream selected by `inc`.
+///
+/// Sampling functions are available on `Var<Rng>`, for use within kernels.
+/// Hemispheres are oriented around +Z.
+// https://www.pcg-random.org/download.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Value)]
+#[repr(C)]
+pub struct Rng {
+    pub state: u64,
+    pub inc: u64,
+}
+impl Rng {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG32_MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+    pub fn next_f32(&mut self) -> f32 {
+        hash::host::unit_f32(self.next_u32())
+    }
+
+    #[tracked]
+    pub fn new_var(seed: impl AsExpr<Value = u64>, stream: impl AsExpr<Value = u64>) -> Var<Rng> {
+        let rng = Rng::var_zeroed();
+        *rng.inc = (stream.as_expr() << 1) | 1;
+        rng.next_u32();
+        *rng.state += seed.as_expr();
+        rng.next_u32();
+        rng
+    }
+    /// Creates a generator with a separate stream for every thread of the dispatch,
+    /// which changes every frame.
+    #[tracked]
+    pub fn from_dispatch(frame: impl AsExpr<Value = u32>) -> Var<Rng> {
+        let id = dispatch_id();
+        let size = dispatch_size();
+        let index = id.x.cast_u64()
+            + size.x.cast_u64() * (id.y.cast_u64() + size.y.cast_u64() * id.z.cast_u64());
+        Rng::new_var(frame.as_expr().cast_u64(), index)
+    }
+}
+
+impl RngVar {
+    #[tracked]
+    pub fn next_u32(&self) -> Expr<u32> {
+        let old = **self.state;
+        *self.state = old * PCG32_MULTIPLIER + **self.inc;
+        let xorshifted = (((old >> 18) ^ old) >> 27).cast_u32();
+        let rot = (old >> 59).cast_u32();
+        (xorshifted >> rot) | (xorshifted << ((!rot + 1) & 31))
+    }
+    /// A uniform float in `[0, 1)`.
+    #[tracked]
+    pub fn next_f32(&self) -> Expr<f32> {
+        hash::unit_f32(self.next_u32())
+    }
+    #[tracked]
+    pub fn next_vec2(&self) -> Expr<Vec2<f32>> {
+        let x = self.next_f32();
+        Vec2::expr(x, self.next_f32())
+    }
+    #[tracked]
+    pub fn next_vec3(&self) -> Expr<Vec3<f32>> {
+        let x = self.next_f32();
+        let y = self.next_f32();
+        Vec3::expr(x, y, self.next_f32())
+    }
+    /// A uniform integer in `[lo, hi)`, without modulo bias.
+    ///
+    /// `hi` must be greater than `lo`, but `lo` is returned if they are equal.
+    #[tracked]
+    pub fn range_u32(
+        &self,
+        lo: impl AsExpr<Value = u32>,
+        hi: impl AsExpr<Value = u32>,
+    ) -> Expr<u32> {
+        let lo = lo.as_expr();
+        let bound = hi.as_expr() - lo;
+        let result = lo.var();
+        if bound != 0 {
+            // 2^32 mod bound, below which values are rejected.
+            let threshold = (!bound + 1) % bound;
+            let r = self.next_u32().var();
+            loop {
+                if r >= threshold {
+                    break;
+                }
+                *r = self.next_u32();
+            }
+            *result = lo + r % bound;
+        }
+        **result
+    }
+    /// A uniform integer in `[lo, hi)`, without modulo bias.
+    ///
+    /// `hi` must be greater than `lo`, but `lo` is returned if they are equal.
+    #[tracked]
+    pub fn range_i32(
+        &self,
+        lo: impl AsExpr<Value = i32>,
+        hi: impl AsExpr<Value = i32>,
+    ) -> Expr<i32> {
+        let lo = lo.as_expr();
+        let offset = self.range_u32(0_u32, (hi.as_expr() - lo).cast_u32());
+        lo + offset.cast_i32()
+    }
+    #[tracked]
+    pub fn uniform_disk(&self) -> Expr<Vec2<f32>> {
+        let u = self.next_vec2();
+        let r = u.x.sqrt();
+        let theta = TAU * u.y;
+        Vec2::expr(theta.cos(), theta.sin()) * r
+    }
+    #[tracked]
+    pub fn uniform_sphere(&self) -> Expr<Vec3<f32>> {
+        let u = self.next_vec2();
+        let z = 1.0 - 2.0 * u.x;
+        let r = keter::max(1.0 - z * z, 0.0).sqrt();
+        let phi = TAU * u.y;
+        Vec3::expr(r * phi.cos(), r * phi.sin(), z)
+    }
+    #[tracked]
+    pub fn uniform_hemisphere(&self) -> Expr<Vec3<f32>> {
+        let u = self.next_vec2();
+        let z = u.x;
+        let r = keter::max(1.0 - z * z, 0.0).sqrt();
+        let phi = TAU * u.y;
+        Vec3::expr(r * phi.cos(), r * phi.sin(), z)
+    }
+    /// Samples the hemisphere with a density proportional to `cos(theta) / PI`.
+    #[tracked]
+    pub fn cosine_hemisphere(&self) -> Expr<Vec3<f32>> {
+        let d = self.uniform_disk();
+        let z = keter::max(1.0 - d.dot(d), 0.0).sqrt();
+        d.extend(z)
+    }
+    /// Two independent standard normal samples, using the Box-Muller transform.
+    #[tracked]
+    pub fn normal2(&self) -> Expr<Vec2<f32>> {
+        let u = self.next_vec2();
+        // `1 - u` is in `(0, 1]`, avoiding `ln(0)`.
+        let r = (-2.0 * (1.0 - u.x).ln()).sqrt();
+        let theta = TAU * u.y;
+        Vec2::expr(theta.cos(), theta.sin()) * r
+    }
+    #[tracked]
+    pub fn normal(
+        &self,
+        mean: impl AsExpr<Value = f32>,
+        std_dev: impl AsExpr<Value = f32>,
+    ) -> Expr<f32> {
+        mean.as_expr() + std_dev.as_expr() * self.normal2().x
+    }
+    /// An exponentially distributed sample with the given rate.
+    #[tracked]
+    pub fn exponential(&self, rate: impl AsExpr<Value = f32>) -> Expr<f32> {
+        -(1.0 - self.next_f32()).ln() / rate.as_expr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::eval;
+
+    const N: u32 = 1 << 16;
+
+    fn mean(values: impl IntoIterator<Item = f32>) -> f32 {
+        let (sum, count) = values
+            .into_iter()
+            .fold((0.0, 0), |(sum, count), x| (sum + x as f64, count + 1));
+        (sum / count as f64) as f32
+    }
+
+    #[test]
+    fn pcg32_reference() {
+        // From the output of `pcg32-demo` in the reference implementation.
+        let mut rng = Rng::new(42, 54);
+        let expected = [
+            0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e,
+        ];
+        for x in expected {
+            assert_eq!(rng.next_u32(), x);
+        }
+    }
+
+    #[test]
+    fn device_matches_host() {
+        let values = eval::<u32>(N, |i| {
+            let rng = Rng::new_var(42_u64, i.cast_u64());
+            rng.next_u32();
+            rng.next_u32()
+        });
+        for (i, value) in values.into_iter().enumerate() {
+            let mut rng = Rng::new(42, i as u64);
+            rng.next_u32();
+            assert_eq!(value, rng.next_u32());
+        }
+    }
+
+    #[test]
+    fn uniform_f32() {
+        let values = eval::<f32>(N, |_| Rng::from_dispatch(0_u32).next_f32());
+        assert!(values.iter().all(|&x| (0.0..1.0).contains(&x)));
+        let m = mean(values.iter().copied());
+        assert!((m - 0.5).abs() < 0.01);
+        let var = mean(values.iter().map(|x| (x - m) * (x - m)));
+        assert!((var - 1.0 / 12.0).abs() < 0.005);
+    }
+
+    #[test]
+    fn range() {
+        let values = eval::<u32>(N, |_| Rng::from_dispatch(0_u32).range_u32(3_u32, 10_u32));
+        let mut counts = [0; 7];
+        for x in values {
+            assert!((3..10).contains(&x));
+            counts[x as usize - 3] += 1;
+        }
+        let expected = N as f32 / 7.0;
+        let chi2 = counts
+            .iter()
+            .map(|&c| (c as f32 - expected).powi(2) / expected)
+            .sum::<f32>();
+        // The 99.9% quantile for 6 degrees of freedom.
+        assert!(chi2 < 22.46, "chi2 = {chi2}");
+
+        let values = eval::<i32>(N, |_| Rng::from_dispatch(0_u32).range_i32(-4, 4));
+        assert!(values.iter().all(|x| (-4..4).contains(x)));
+        assert!((mean(values.iter().map(|&x| x as f32)) + 0.5).abs() < 0.05);
+
+        let values = eval::<u32>(16, |_| Rng::from_dispatch(0_u32).range_u32(5_u32, 5_u32));
+        assert!(values.iter().all(|&x| x == 5));
+    }
+
+    #[test]
+    fn disk() {
+        let values = eval::<Vec2<f32>>(N, |_| Rng::from_dispatch(0_u32).uniform_disk());
+        assert!(values.iter().all(|p| p.x * p.x + p.y * p.y <= 1.0 + 1e-5));
+        assert!(mean(values.iter().map(|p| p.x)).abs() < 0.01);
+        assert!(mean(values.iter().map(|p| p.y)).abs() < 0.01);
+        // The radius squared is uniform.
+        assert!((mean(values.iter().map(|p| p.x * p.x + p.y * p.y)) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn sphere() {
+        let values = eval::<Vec3<f32>>(N, |_| Rng::from_dispatch(0_u32).uniform_sphere());
+        let length = |p: &Vec3<f32>| (p.x * p.x + p.y * p.y + p.z * p.z).sqrt();
+        assert!(values.iter().all(|p| (length(p) - 1.0).abs() < 1e-4));
+        assert!(mean(values.iter().map(|p| p.x)).abs() < 0.01);
+        assert!(mean(values.iter().map(|p| p.y)).abs() < 0.01);
+        assert!(mean(values.iter().map(|p| p.z)).abs() < 0.01);
+        assert!((mean(values.iter().map(|p| p.z * p.z)) - 1.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn hemisphere() {
+        let values = eval::<Vec3<f32>>(N, |_| Rng::from_dispatch(0_u32).uniform_hemisphere());
+        assert!(values.iter().all(|p| p.z >= 0.0));
+        assert!((mean(values.iter().map(|p| p.z)) - 0.5).abs() < 0.01);
+        assert!(mean(values.iter().map(|p| p.x)).abs() < 0.01);
+    }
+
+    #[test]
+    fn cosine_hemisphere() {
+        let values = eval::<Vec3<f32>>(N, |_| Rng::from_dispatch(0_u32).cosine_hemisphere());
+        let length = |p: &Vec3<f32>| (p.x * p.x + p.y * p.y + p.z * p.z).sqrt();
+        assert!(
+            values
+                .iter()
+                .all(|p| p.z >= 0.0 && (length(p) - 1.0).abs() < 1e-4)
+        );
+        // E[cos(theta)] = 2/3 for a cosine-weighted hemisphere.
+        assert!((mean(values.iter().map(|p| p.z)) - 2.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn normal() {
+        let values = eval::<Vec2<f32>>(N, |_| Rng::from_dispatch(0_u32).normal2());
+        for x in [
+            values.iter().map(|p| p.x).collect::<Vec<_>>(),
+            values.iter().map(|p| p.y).collect::<Vec<_>>(),
+        ] {
+            let m = mean(x.iter().copied());
+            assert!(m.abs() < 0.02);
+            assert!((mean(x.iter().map(|x| x * x)) - 1.0).abs() < 0.03);
+        }
+        let values = eval::<f32>(N, |_| Rng::from_dispatch(0_u32).normal(3.0_f32, 2.0_f32));
+        assert!((mean(values.iter().copied()) - 3.0).abs() < 0.04);
+    }
+
+    #[test]
+    fn exponential() {
+        let values = eval::<f32>(N, |_| Rng::from_dispatch(0_u32).exponential(2.0_f32));
+        assert!(values.iter().all(|&x| x >= 0.0 && x.is_finite()));
+        assert!((mean(values.iter().copied()) - 0.5).abs() < 0.01);
+        // The variance is 1 / rate^2.
+        assert!((mean(values.iter().map(|x| (x - 0.5) * (x - 0.5))) - 0.25).abs() < 0.02);
+    }
+
+    #[test]
+    fn halton_sequence() {
+        let values = eval::<Vec2<f32>>(8, |i| Vec2::expr(halton(i, 2), halton(i, 3)));
+        let expected = [
+            (0.0, 0.0),
+            (0.5, 1.0 / 3.0),
+            (0.25, 2.0 / 3.0),
+            (0.75, 1.0 / 9.0),
+            (0.125, 4.0 / 9.0),
+            (0.625, 7.0 / 9.0),
+            (0.375, 2.0 / 9.0),
+            (0.875, 5.0 / 9.0),
+        ];
+        for (value, (x, y)) in values.into_iter().zip(expected) {
+            assert!((value.x - x).abs() < 1e-6 && (value.y - y).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use keter::lang::types::vector::{Vec2, Vec3, Vec4};
+use keter::prelude::*;
+
+use super::pcg4d;
+
+// Jarzynski and Olano 2020, Hash Functions for GPU Rendering.
+// https://jcgt.org/published/0009/03/02/
+// https://www.reedbeta.com/blog/hash-functions-for-gpu-rendering/
+
+/// An input to the hash functions, which is hashed as a sequence of words.
+pub trait HashInput: Copy {
+    fn words(self) -> Vec<Expr<u32>>;
+}
+impl HashInput for Expr<u32> {
+    fn words(self) -> Vec<Expr<u32>> {
+        vec![self]
+    }
+}
+impl HashInput for Expr<Vec2<u32>> {
+    fn words(self) -> Vec<Expr<u32>> {
+        vec![self.x, self.y]
+    }
+}
+impl HashInput for Expr<Vec3<u32>> {
+    fn words(self) -> Vec<Expr<u32>> {
+        vec![self.x, self.y, self.z]
+    }
+}
+impl HashInput for Expr<Vec4<u32>> {
+    fn words(self) -> Vec<Expr<u32>> {
+        vec![self.x, self.y, self.z, self.w]
+    }
+}
+
+const PRIME32_2: u32 = 2246822519;
+const PRIME32_3: u32 = 3266489917;
+const PRIME32_4: u32 = 668265263;
+const PRIME32_5: u32 = 374761393;
+
+fn rotl(x: Expr<u32>, r: u32) -> Expr<u32> {
+    (x << r) | (x >> (32 - r))
+}
+
+pub fn xxhash32(x: impl HashInput) -> Expr<u32> {
+    let words = x.words();
+    let (&last, rest) = words.split_last().unwrap();
+    let mut h = last + PRIME32_5;
+    for &w in rest {
+        h = h + w * PRIME32_3;
+        h = rotl(h, 17) * PRIME32_4;
+    }
+    if rest.is_empty() {
+        h = rotl(h, 17) * PRIME32_4;
+    }
+    h = (h ^ (h >> 15)) * PRIME32_2;
+    h = (h ^ (h >> 13)) * PRIME32_3;
+    h ^ (h >> 16)
+}
+
+/// The finalizer of MurmurHash3.
+#[tracked]
+pub fn fmix32(h: Expr<u32>) -> Expr<u32> {
+    let h = h ^ (h >> 16);
+    let h = h * 0x85ebca6b_u32;
+    let h = h ^ (h >> 13);
+    let h = h * 0xc2b2ae35_u32;
+    h ^ (h >> 16)
+}
+
+/// MurmurHash3 (x86, 32-bit) of the words, with a seed of zero.
+pub fn murmur3(x: impl HashInput) -> Expr<u32> {
+    let words = x.words();
+    let mut h = 0_u32.expr();
+    for &w in &words {
+        let k = rotl(w * 0xcc9e2d51_u32, 15) * 0x1b873593_u32;
+        h = h ^ k;
+        h = rotl(h, 13) * 5_u32 + 0xe6546b64_u32;
+    }
+    fmix32(h ^ (words.len() as u32 * 4))
+}
+
+#[tracked]
+fn wang1(x: Expr<u32>) -> Expr<u32> {
+    let x = (x ^ 61) ^ (x >> 16);
+    let x = x * 9;
+    let x = x ^ (x >> 4);
+    let x = x * 0x27d4eb2d_u32;
+    x ^ (x >> 15)
+}
+
+/// Thomas Wang's hash, nested as `wang(x + wang(y + ...))` for multiple words.
+pub fn wang(x: impl HashInput) -> Expr<u32> {
+    let words = x.words();
+    let (&last, rest) = words.split_last().unwrap();
+    rest.iter().rev().fold(wang1(last), |h, &w| wang1(w + h))
+}
+
+#[tracked]
+fn iqint1(n: Expr<u32>) -> Expr<u32> {
+    let n = (n << 13) ^ n;
+    n * (n * n * 15731 + 789221) + 1376312589
+}
+#[tracked]
+fn iqint2(x: Expr<Vec2<u32>>) -> Expr<u32> {
+    let q = ((x >> 1) ^ x.yx()) * 1103515245;
+    (q.x ^ (q.y >> 3)) * 1103515245
+}
+#[tracked]
+fn iqint3(x: Expr<Vec3<u32>>) -> Expr<Vec3<u32>> {
+    let x = ((x >> 8) ^ x.yzx()) * 1103515245;
+    let x = ((x >> 8) ^ x.yzx()) * 1103515245;
+    ((x >> 8) ^ x.yzx()) * 1103515245
+}
+
+/// Inigo Quilez's integer hashes, using the `x` component of the 3D hash for 3 words,
+/// and nesting the 1D hash as `iqint(w + iqint(x, y, z))` for 4 words.
+pub fn iqint(x: impl HashInput) -> Expr<u32> {
+    match x.words()[..] {
+        [x] => iqint1(x),
+        [x, y] => iqint2(Vec2::expr(x, y)),
+        [x, y, z] => iqint3(Vec3::expr(x, y, z)).x,
+        [x, y, z, w] => iqint1(w + iqint3(Vec3::expr(x, y, z)).x),
+        _ => unreachable!(),
+    }
+}
+
+/// The `x` component of [`pcg4d`](super::pcg4d), with the input padded with zeros.
+pub fn pcg4d_x(x: impl HashInput) -> Expr<u32> {
+    let words = x.words();
+    let word = |i: usize| words.get(i).copied().unwrap_or(0_u32.expr());
+    pcg4d(Vec4::expr(word(0), word(1), word(2), word(3))).x
+}
+
+#[tracked]
+pub fn hash_combine(seed: Expr<u32>, value: Expr<u32>) -> Expr<u32> {
+    seed ^ (value + 0x9e3779b9_u32 + (seed << 6) + (seed >> 2))
+}
+
+/// Converts the upper 24 bits of a hash to a float in `[0, 1)`.
+#[tracked]
+pub fn unit_f32(x: Expr<u32>) -> Expr<f32> {
+    (x >> 8).cast_f32() / (1 << 24) as f32
+}
+#[tracked]
+pub fn unit_vec2(x: Expr<Vec2<u32>>) -> Expr<Vec2<f32>> {
+    (x >> 8).cast_f32() / (1 << 24) as f32
+}
+#[tracked]
+pub fn unit_vec3(x: Expr<Vec3<u32>>) -> Expr<Vec3<f32>> {
+    (x >> 8).cast_f32() / (1 << 24) as f32
+}
+#[tracked]
+pub fn unit_vec4(x: Expr<Vec4<u32>>) -> Expr<Vec4<f32>> {
+    (x >> 8).cast_f32() / (1 << 24) as f32
+}
+
+/// Host-side implementations, for reference.
+pub mod host {
+    use super::{PRIME32_2, PRIME32_3, PRIME32_4, PRIME32_5};
+
+    pub fn xxhash32(words: &[u32]) -> u32 {
+        let (&last, rest) = words.split_last().unwrap();
+        let mut h = last.wrapping_add(PRIME32_5);
+        for &w in rest {
+            h = h.wrapping_add(w.wrapping_mul(PRIME32_3));
+            h = PRIME32_4.wrapping_mul(h.rotate_left(17));
+        }
+        if rest.is_empty() {
+            h = PRIME32_4.wrapping_mul(h.rotate_left(17));
+        }
+        h = PRIME32_2.wrapping_mul(h ^ (h >> 15));
+        h = PRIME32_3.wrapping_mul(h ^ (h >> 13));
+        h ^ (h >> 16)
+    }
+    pub fn fmix32(mut h: u32) -> u32 {
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x85ebca6b);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0xc2b2ae35);
+        h ^ (h >> 16)
+    }
+    pub fn murmur3(words: &[u32]) -> u32 {
+        let mut h = 0_u32;
+        for &w in words {
+            let k = w.wrapping_mul(0xcc9e2d51).rotate_left(15);
+            h ^= k.wrapping_mul(0x1b873593);
+            h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+        }
+        fmix32(h ^ (words.len() as u32 * 4))
+    }
+    fn wang1(mut x: u32) -> u32 {
+        x = (x ^ 61) ^ (x >> 16);
+        x = x.wrapping_mul(9);
+        x ^= x >> 4;
+        x = x.wrapping_mul(0x27d4eb2d);
+        x ^ (x >> 15)
+    }
+    pub fn wang(words: &[u32]) -> u32 {
+        let (&last, rest) = words.split_last().unwrap();
+        rest.iter()
+            .rev()
+            .fold(wang1(last), |h, &w| wang1(w.wrapping_add(h)))
+    }
+    fn iqint1(n: u32) -> u32 {
+        let n = (n << 13) ^ n;
+        n.wrapping_mul(n.wrapping_mul(n).wrapping_mul(15731).wrapping_add(789221))
+            .wrapping_add(1376312589)
+    }
+    fn iqint2([x, y]: [u32; 2]) -> u32 {
+        let qx = ((x >> 1) ^ y).wrapping_mul(1103515245);
+        let qy = ((y >> 1) ^ x).wrapping_mul(1103515245);
+        (qx ^ (qy >> 3)).wrapping_mul(1103515245)
+    }
+    fn iqint3(mut x: [u32; 3]) -> [u32; 3] {
+        for _ in 0..3 {
+            let [a, b, c] = x;
+            x = [
+                ((a >> 8) ^ b).wrapping_mul(1103515245),
+                ((b >> 8) ^ c).wrapping_mul(1103515245),
+                ((c >> 8) ^ a).wrapping_mul(1103515245),
+            ];
+        }
+        x
+    }
+    pub fn iqint(words: &[u32]) -> u32 {
+        match *words {
+            [x] => iqint1(x),
+            [x, y] => iqint2([x, y]),
+            [x, y, z] => iqint3([x, y, z])[0],
+            [x, y, z, w] => iqint1(w.wrapping_add(iqint3([x, y, z])[0])),
+            _ => panic!("Expected between 1 and 4 words."),
+        }
+    }
+    pub fn pcg4d(v: [u32; 4]) -> [u32; 4] {
+        let mut v = v.map(|x| x.wrapping_mul(1664525).wrapping_add(1013904223));
+        let mix = |v: &mut [u32; 4]| {
+            v[0] = v[0].wrapping_add(v[1].wrapping_mul(v[3]));
+            v[1] = v[1].wrapping_add(v[2].wrapping_mul(v[0]));
+            v[2] = v[2].wrapping_add(v[0].wrapping_mul(v[1]));
+            v[3] = v[3].wrapping_add(v[1].wrapping_mul(v[2]));
+        };
+        mix(&mut v);
+        v = v.map(|x| x ^ (x >> 16));
+        mix(&mut v);
+        v
+    }
+    pub fn pcg4d_x(words: &[u32]) -> u32 {
+        let word = |i: usize| words.get(i).copied().unwrap_or(0);
+        pcg4d([word(0), word(1), word(2), word(3)])[0]
+    }
+    pub fn hash_combine(seed: u32, value: u32) -> u32 {
+        seed ^ value
+            .wrapping_add(0x9e3779b9)
+            .wrapping_add(seed << 6)
+            .wrapping_add(seed >> 2)
+    }
+    pub fn unit_f32(x: u32) -> f32 {
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::eval;
+
+    const N: u32 = 4096;
+    // Spreads the index over every word, so that each word takes many values.
+    const MULTIPLIERS: [u32; 4] = [1, 0x9e3779b9, 0x85ebca6b, 0xc2b2ae35];
+    const OFFSETS: [u32; 4] = [0, 0x68e31da4, 0xb5297a4d, 0x1b56c4e9];
+
+    fn check(device: impl Fn(&[Expr<u32>]) -> Expr<u32>, host: impl Fn(&[u32]) -> u32) {
+        for n in 1..=4 {
+            let values = eval::<u32>(N, |i| {
+                let words = (0..n)
+                    .map(|j| i * MULTIPLIERS[j] + OFFSETS[j])
+                    .collect::<Vec<_>>();
+                device(&words)
+            });
+            for (i, x) in values.into_iter().enumerate() {
+                let words = (0..n)
+                    .map(|j| {
+                        (i as u32)
+                            .wrapping_mul(MULTIPLIERS[j])
+                            .wrapping_add(OFFSETS[j])
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(x, host(&words), "Mismatch for {n} words at index {i}.");
+            }
+        }
+    }
+
+    macro_rules! check_hash {
+        ($($name:ident),*) => {
+            $(
+                #[test]
+                fn $name() {
+                    check(
+                        |words| match *words {
+                            [x] => super::$name(x),
+                            [x, y] => super::$name(Vec2::expr(x, y)),
+                            [x, y, z] => super::$name(Vec3::expr(x, y, z)),
+                            [x, y, z, w] => super::$name(Vec4::expr(x, y, z, w)),
+                            _ => unreachable!(),
+                        },
+                        host::$name,
+                    );
+                }
+            )*
+        };
+    }
+    check_hash!(xxhash32, murmur3, wang, iqint, pcg4d_x);
+
+    #[test]
+    fn fmix32() {
+        check(
+            |words| super::fmix32(words[0]),
+            |words| host::fmix32(words[0]),
+        );
+    }
+
+    #[test]
+    fn hash_combine() {
+        check(
+            |words| super::hash_combine(words[0], words[0] * 3_u32),
+            |words| host::hash_combine(words[0], words[0].wrapping_mul(3)),
+        );
+    }
+
+    #[test]
+    fn unit_float() {
+        let values = eval::<f32>(N, |i| unit_f32(super::xxhash32(i)));
+        for (i, x) in values.into_iter().enumerate() {
+            assert!((0.0..1.0).contains(&x));
+            assert_eq!(x, host::unit_f32(host::xxhash32(&[i as u32])));
+        }
+        assert_eq!(host::unit_f32(u32::MAX), 1.0 - 1.0 / (1 << 24) as f32);
+    }
+}
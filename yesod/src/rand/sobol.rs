@@ -0,0 +1,300 @@
+use std::cell::Cell;
+
+use keter::lang::types::vector::{Vec2, Vec3};
+use keter::prelude::*;
+
+use super::hash::{hash_combine, unit_f32};
+
+/// The number of dimensions with direction numbers. Further dimensions are padded by reshuffling.
+pub const SOBOL_DIMS: usize = 16;
+
+// The degree, coefficients and initial direction numbers of the primitive polynomial for each dimension
+// after the first, from `new-joe-kuo-6.21201`.
+// https://web.maths.unsw.edu.au/~fkuo/sobol/
+const JOE_KUO: [(u32, u32, [u32; 6]); SOBOL_DIMS - 1] = [
+    (1, 0, [1, 0, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0, 0]),
+    (4, 4, [1, 3, 5, 13, 0, 0]),
+    (5, 2, [1, 1, 5, 5, 17, 0]),
+    (5, 4, [1, 1, 5, 5, 5, 0]),
+    (5, 7, [1, 1, 7, 11, 19, 0]),
+    (5, 11, [1, 1, 5, 1, 1, 0]),
+    (5, 13, [1, 1, 1, 3, 11, 0]),
+    (5, 14, [1, 3, 5, 5, 31, 0]),
+    (6, 1, [1, 3, 3, 9, 7, 49]),
+    (6, 13, [1, 1, 1, 15, 21, 21]),
+    (6, 16, [1, 3, 1, 13, 27, 49]),
+];
+
+const fn sobol_matrices() -> [[u32; 32]; SOBOL_DIMS] {
+    let mut matrices = [[0; 32]; SOBOL_DIMS];
+    // The first dimension is the van der Corput sequence.
+    let mut i = 0;
+    while i < 32 {
+        matrices[0][i] = 1 << (31 - i);
+        i += 1;
+    }
+    let mut dim = 1;
+    while dim < SOBOL_DIMS {
+        let (s, a, m) = JOE_KUO[dim - 1];
+        let s = s as usize;
+        let v = &mut matrices[dim];
+        let mut i = 0;
+        while i < 32 {
+            v[i] = if i < s {
+                m[i] << (31 - i)
+            } else {
+                let mut x = v[i - s] ^ (v[i - s] >> s);
+                let mut k = 1;
+                while k < s {
+                    if (a >> (s - 1 - k)) & 1 != 0 {
+                        x ^= v[i - k];
+                    }
+                    k += 1;
+                }
+                x
+            };
+            i += 1;
+        }
+        dim += 1;
+    }
+    matrices
+}
+
+/// The generator matrices of each dimension, with column `i` corresponding to bit `i` of the index.
+pub const SOBOL_MATRICES: [[u32; 32]; SOBOL_DIMS] = sobol_matrices();
+
+/// The `dim`th dimension of the `index`th point of the Sobol sequence, as a 0.32 fixed-point number.
+#[tracked]
+pub fn sobol(index: Expr<u32>, dim: u32) -> Expr<u32> {
+    let matrix = SOBOL_MATRICES[dim as usize].expr();
+    let result = 0_u32.var();
+    let index = index.var();
+    let bit = 0_u32.var();
+    loop {
+        if index == 0 {
+            break;
+        }
+        if (index & 1) != 0 {
+            *result ^= matrix.read(**bit);
+        }
+        *index >>= 1;
+        *bit += 1;
+    }
+    **result
+}
+
+#[tracked]
+pub fn reverse_bits(x: Expr<u32>) -> Expr<u32> {
+    let x = ((x >> 1) & 0x55555555) | ((x & 0x55555555) << 1);
+    let x = ((x >> 2) & 0x33333333) | ((x & 0x33333333) << 2);
+    let x = ((x >> 4) & 0x0f0f0f0f) | ((x & 0x0f0f0f0f) << 4);
+    let x = ((x >> 8) & 0x00ff00ff) | ((x & 0x00ff00ff) << 8);
+    (x >> 16) | (x << 16)
+}
+
+#[tracked]
+fn laine_karras_permutation(x: Expr<u32>, seed: Expr<u32>) -> Expr<u32> {
+    let x = x + seed;
+    let x = x ^ (x * 0x6c50b47c_u32);
+    let x = x ^ (x * 0xb82f1e52_u32);
+    let x = x ^ (x * 0xc7afe638_u32);
+    x ^ (x * 0x8d22f6e6_u32)
+}
+
+/// Applies a random Owen scramble to a 0.32 fixed-point number, keeping the stratification of sequences.
+// Burley 2020, Practical Hash-based Owen Scrambling.
+// https://jcgt.org/published/0009/04/01/
+#[tracked]
+pub fn owen_scramble(x: Expr<u32>, seed: Expr<u32>) -> Expr<u32> {
+    reverse_bits(laine_karras_permutation(reverse_bits(x), seed))
+}
+
+/// Shuffles the order of a sequence, for decorrelating dimensions which use the same index.
+pub fn shuffle(index: Expr<u32>, seed: Expr<u32>) -> Expr<u32> {
+    owen_scramble(index, seed)
+}
+
+/// The `dim`th dimension of a shuffled and scrambled Sobol sequence, as a 0.32 fixed-point number.
+///
+/// Dimensions beyond [`SOBOL_DIMS`] reuse the direction numbers with a different shuffle.
+#[tracked]
+pub fn scrambled_sobol(index: Expr<u32>, dim: u32, seed: Expr<u32>) -> Expr<u32> {
+    let group = dim / SOBOL_DIMS as u32;
+    let index = shuffle(index, hash_combine(seed, group.expr()));
+    let x = sobol(index, dim % SOBOL_DIMS as u32);
+    owen_scramble(x, hash_combine(seed, (dim + SOBOL_DIMS as u32).expr()))
+}
+
+/// Draws successive dimensions of a single sample of a scrambled Sobol sequence,
+/// so that the dimensions don't have to be tracked by hand.
+pub struct SobolSampler {
+    index: Expr<u32>,
+    seed: Expr<u32>,
+    dimension: Cell<u32>,
+}
+impl SobolSampler {
+    pub fn new(index: impl AsExpr<Value = u32>, seed: impl AsExpr<Value = u32>) -> Self {
+        Self {
+            index: index.as_expr(),
+            seed: seed.as_expr(),
+            dimension: Cell::new(0),
+        }
+    }
+    /// The next dimension to be drawn.
+    pub fn dimension(&self) -> u32 {
+        self.dimension.get()
+    }
+    pub fn skip(&self, dimensions: u32) {
+        self.dimension.set(self.dimension.get() + dimensions);
+    }
+    pub fn next_u32(&self) -> Expr<u32> {
+        let dim = self.dimension.get();
+        self.dimension.set(dim + 1);
+        scrambled_sobol(self.index, dim, self.seed)
+    }
+    /// A float in `[0, 1)`.
+    pub fn next_f32(&self) -> Expr<f32> {
+        unit_f32(self.next_u32())
+    }
+    pub fn next_vec2(&self) -> Expr<Vec2<f32>> {
+        let x = self.next_f32();
+        Vec2::expr(x, self.next_f32())
+    }
+    pub fn next_vec3(&self) -> Expr<Vec3<f32>> {
+        let x = self.next_f32();
+        let y = self.next_f32();
+        Vec3::expr(x, y, self.next_f32())
+    }
+}
+
+/// Host-side implementations, for reference.
+pub mod host {
+    use super::{SOBOL_DIMS, SOBOL_MATRICES};
+    use crate::rand::hash::host::hash_combine;
+
+    pub fn sobol(mut index: u32, dim: u32) -> u32 {
+        let matrix = &SOBOL_MATRICES[dim as usize];
+        let mut result = 0;
+        let mut bit = 0;
+        while index != 0 {
+            if index & 1 != 0 {
+                result ^= matrix[bit];
+            }
+            index >>= 1;
+            bit += 1;
+        }
+        result
+    }
+    fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+        x = x.wrapping_add(seed);
+        x ^= x.wrapping_mul(0x6c50b47c);
+        x ^= x.wrapping_mul(0xb82f1e52);
+        x ^= x.wrapping_mul(0xc7afe638);
+        x ^= x.wrapping_mul(0x8d22f6e6);
+        x
+    }
+    pub fn owen_scramble(x: u32, seed: u32) -> u32 {
+        laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+    }
+    pub fn shuffle(index: u32, seed: u32) -> u32 {
+        owen_scramble(index, seed)
+    }
+    pub fn scrambled_sobol(index: u32, dim: u32, seed: u32) -> u32 {
+        let group = dim / SOBOL_DIMS as u32;
+        let index = shuffle(index, hash_combine(seed, group));
+        let x = sobol(index, dim % SOBOL_DIMS as u32);
+        owen_scramble(x, hash_combine(seed, dim + SOBOL_DIMS as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand::hash::host::unit_f32;
+    use crate::tests::eval;
+
+    const DIMS: u32 = SOBOL_DIMS as u32 + 4;
+
+    fn assert_stratified(values: impl Iterator<Item = u32>, bits: u32) {
+        let mut seen = vec![false; 1 << bits];
+        for x in values {
+            let bin = (x >> (32 - bits)) as usize;
+            assert!(!seen[bin], "Bin {bin} was hit twice.");
+            seen[bin] = true;
+        }
+    }
+
+    #[test]
+    fn van_der_corput() {
+        let expected = [0.0, 0.5, 0.25, 0.75, 0.125, 0.625, 0.375, 0.875];
+        for (i, x) in expected.into_iter().enumerate() {
+            assert_eq!(unit_f32(host::sobol(i as u32, 0)), x);
+        }
+    }
+
+    #[test]
+    fn device_matches_host() {
+        for dim in 0..DIMS {
+            let values = eval::<u32>(4096, |i| sobol(i, dim % SOBOL_DIMS as u32));
+            for (i, x) in values.into_iter().enumerate() {
+                assert_eq!(x, host::sobol(i as u32, dim % SOBOL_DIMS as u32));
+            }
+            let values = eval::<u32>(4096, |i| scrambled_sobol(i, dim, 1234_u32.expr()));
+            for (i, x) in values.into_iter().enumerate() {
+                assert_eq!(x, host::scrambled_sobol(i as u32, dim, 1234));
+            }
+        }
+        let values = eval::<u32>(4096, |i| owen_scramble(i * 0x9e3779b9_u32, i));
+        for (i, x) in values.into_iter().enumerate() {
+            let i = i as u32;
+            assert_eq!(x, host::owen_scramble(i.wrapping_mul(0x9e3779b9), i));
+        }
+    }
+
+    #[test]
+    fn sampler_tracks_dimensions() {
+        let values = eval::<Vec3<f32>>(256, |i| {
+            let sampler = SobolSampler::new(i, 7_u32);
+            let _ = sampler.next_vec2();
+            sampler.skip(SOBOL_DIMS as u32);
+            assert_eq!(sampler.dimension(), SOBOL_DIMS as u32 + 2);
+            sampler.next_vec3()
+        });
+        for (i, v) in values.into_iter().enumerate() {
+            let dim = SOBOL_DIMS as u32 + 2;
+            let x = |d| unit_f32(host::scrambled_sobol(i as u32, dim + d, 7));
+            assert_eq!([v.x, v.y, v.z], [x(0), x(1), x(2)]);
+        }
+    }
+
+    #[test]
+    fn stratified() {
+        for dim in 0..DIMS {
+            assert_stratified((0..256).map(|i| host::sobol(i, dim % SOBOL_DIMS as u32)), 8);
+            assert_stratified((0..256).map(|i| host::scrambled_sobol(i, dim, 99)), 8);
+        }
+    }
+
+    #[test]
+    fn first_two_dimensions_form_a_net() {
+        // Every elementary interval of area 1/256 contains exactly one point.
+        for seed in [0, 1, 0xdeadbeef] {
+            for x_bits in 0..=8 {
+                let y_bits = 8 - x_bits;
+                let mut seen = vec![false; 256];
+                for i in 0..256 {
+                    let x = host::owen_scramble(host::sobol(i, 0), seed);
+                    let y = host::owen_scramble(host::sobol(i, 1), seed ^ 1);
+                    let bin = ((x as u64 >> (32 - x_bits)) << y_bits) as usize
+                        | (y as u64 >> (32 - y_bits)) as usize;
+                    assert!(!seen[bin]);
+                    seen[bin] = true;
+                }
+            }
+        }
+    }
+}
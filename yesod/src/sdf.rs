@@ -0,0 +1,345 @@
+// Scenes are closures of type `impl Fn(Expr<Vec3<f32>>) -> Expr<f32>`, built by transforming
+// the point before evaluating the primitives, and combining the results.
+// Most formulas are from https://iquilezles.org/articles/distfunctions/.
+
+use keter::lang::types::vector::{Mat3, Vec2, Vec3};
+use keter::prelude::*;
+
+#[tracked]
+pub fn sphere(p: Expr<Vec3<f32>>, radius: impl AsExpr<Value = f32>) -> Expr<f32> {
+    p.length() - radius.as_expr()
+}
+
+/// A box centred at the origin.
+#[tracked]
+pub fn cuboid(p: Expr<Vec3<f32>>, half_size: Expr<Vec3<f32>>) -> Expr<f32> {
+    let q = p.abs() - half_size;
+    keter::max(q, Vec3::splat_expr(0.0_f32)).length() + keter::min(q.reduce_max(), 0.0)
+}
+
+/// A box with edges rounded by `radius`, which stays within `half_size`.
+#[tracked]
+pub fn rounded_cuboid(
+    p: Expr<Vec3<f32>>,
+    half_size: Expr<Vec3<f32>>,
+    radius: impl AsExpr<Value = f32>,
+) -> Expr<f32> {
+    let radius = radius.as_expr();
+    cuboid(p, half_size - radius) - radius
+}
+
+/// A torus around the z axis, with the radius of the ring and of the tube in `radii`.
+#[tracked]
+pub fn torus(p: Expr<Vec3<f32>>, radii: Expr<Vec2<f32>>) -> Expr<f32> {
+    Vec2::expr(p.xy().length() - radii.x, p.z).length() - radii.y
+}
+
+#[tracked]
+pub fn capsule(
+    p: Expr<Vec3<f32>>,
+    a: Expr<Vec3<f32>>,
+    b: Expr<Vec3<f32>>,
+    radius: impl AsExpr<Value = f32>,
+) -> Expr<f32> {
+    let pa = p - a;
+    let ba = b - a;
+    let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+    (pa - ba * h).length() - radius.as_expr()
+}
+
+/// The half-space below the plane of points `q` with `normal.dot(q) == offset`.
+/// `normal` must be normalized.
+#[tracked]
+pub fn plane(
+    p: Expr<Vec3<f32>>,
+    normal: Expr<Vec3<f32>>,
+    offset: impl AsExpr<Value = f32>,
+) -> Expr<f32> {
+    p.dot(normal) - offset.as_expr()
+}
+
+#[tracked]
+pub fn union(a: Expr<f32>, b: Expr<f32>) -> Expr<f32> {
+    keter::min(a, b)
+}
+#[tracked]
+pub fn intersection(a: Expr<f32>, b: Expr<f32>) -> Expr<f32> {
+    keter::max(a, b)
+}
+/// Removes `b` from `a`.
+#[tracked]
+pub fn subtraction(a: Expr<f32>, b: Expr<f32>) -> Expr<f32> {
+    keter::max(a, -b)
+}
+
+/// A union which blends the surfaces together within distance `k`.
+// Quadratic polynomial smooth minimum.
+#[tracked]
+pub fn smooth_union(a: Expr<f32>, b: Expr<f32>, k: impl AsExpr<Value = f32>) -> Expr<f32> {
+    let k = k.as_expr() * 4.0;
+    let h = keter::max(k - (a - b).abs(), 0.0) / k;
+    keter::min(a, b) - h * h * k * 0.25
+}
+#[tracked]
+pub fn smooth_intersection(a: Expr<f32>, b: Expr<f32>, k: impl AsExpr<Value = f32>) -> Expr<f32> {
+    -smooth_union(-a, -b, k)
+}
+#[tracked]
+pub fn smooth_subtraction(a: Expr<f32>, b: Expr<f32>, k: impl AsExpr<Value = f32>) -> Expr<f32> {
+    -smooth_union(-a, b, k)
+}
+
+/// Repeats space every `period`, returning the position within the nearest cell.
+/// The shape in each cell must stay within half a period of its centre.
+#[tracked]
+pub fn repeat(p: Expr<Vec3<f32>>, period: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    p - period * (p / period).round()
+}
+
+/// Repeats space every `period`, for cells up to `limit` away from the origin.
+#[tracked]
+pub fn repeat_limited(
+    p: Expr<Vec3<f32>>,
+    period: Expr<Vec3<f32>>,
+    limit: Expr<Vec3<f32>>,
+) -> Expr<Vec3<f32>> {
+    p - period * (p / period).round().clamp(-limit, limit)
+}
+
+/// Moves a shape by `offset`.
+#[tracked]
+pub fn translate(p: Expr<Vec3<f32>>, offset: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    p - offset
+}
+
+/// Rotates a shape by `rotation`, which must be orthonormal.
+#[tracked]
+pub fn rotate(p: Expr<Vec3<f32>>, rotation: Expr<Mat3>) -> Expr<Vec3<f32>> {
+    rotation.transpose() * p
+}
+
+/// Scales the shape `f` uniformly, which unlike the other transforms also scales the distance.
+#[tracked]
+pub fn scale(
+    p: Expr<Vec3<f32>>,
+    factor: impl AsExpr<Value = f32>,
+    f: impl Fn(Expr<Vec3<f32>>) -> Expr<f32>,
+) -> Expr<f32> {
+    let factor = factor.as_expr();
+    f(p / factor) * factor
+}
+
+/// Settings for [`SphereTracer::trace`].
+#[derive(Debug, Clone, Copy)]
+pub struct SphereTracer {
+    pub max_steps: u32,
+    /// The distance at which the surface is hit, relative to the distance along the ray.
+    pub epsilon: f32,
+    pub min_t: f32,
+    pub max_t: f32,
+}
+impl Default for SphereTracer {
+    fn default() -> Self {
+        Self {
+            max_steps: 256,
+            epsilon: 1e-4,
+            min_t: 0.0,
+            max_t: 1000.0,
+        }
+    }
+}
+impl SphereTracer {
+    /// Returns the distance along the ray to the surface of `f`, or infinity if there is none
+    /// within `max_t` or `max_steps`. `dir` must be normalized.
+    #[tracked]
+    pub fn trace(
+        &self,
+        start: Expr<Vec3<f32>>,
+        dir: Expr<Vec3<f32>>,
+        f: impl Fn(Expr<Vec3<f32>>) -> Expr<f32>,
+    ) -> Expr<f32> {
+        let t = self.min_t.var();
+        let result = f32::INFINITY.var();
+        let steps = 0_u32.var();
+        loop {
+            if **steps >= self.max_steps || **t > self.max_t {
+                break;
+            }
+            let d = f(start + dir * **t);
+            if d < keter::max(**t, 1.0) * self.epsilon {
+                *result = **t;
+                break;
+            }
+            *t += d;
+            *steps += 1;
+        }
+        **result
+    }
+}
+
+/// The normalized gradient of `f`, using four samples at the corners of a tetrahedron.
+#[tracked]
+pub fn normal(
+    p: Expr<Vec3<f32>>,
+    f: impl Fn(Expr<Vec3<f32>>) -> Expr<f32>,
+    h: impl AsExpr<Value = f32>,
+) -> Expr<Vec3<f32>> {
+    let h = h.as_expr();
+    let mut gradient = Vec3::splat_expr(0.0_f32);
+    for k in [
+        Vec3::new(1.0, -1.0, -1.0),
+        Vec3::new(-1.0, -1.0, 1.0),
+        Vec3::new(-1.0, 1.0, -1.0),
+        Vec3::new(1.0, 1.0, 1.0),
+    ] {
+        let k = k.expr();
+        gradient = gradient + k * f(p + k * h);
+    }
+    gradient.normalize()
+}
+
+/// The fraction of light reaching `start` from direction `dir`, with penumbrae whose sharpness
+/// increases with `k`.
+// https://iquilezles.org/articles/rmshadows/, with the improvements for negative distances.
+#[tracked]
+pub fn soft_shadow(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    f: impl Fn(Expr<Vec3<f32>>) -> Expr<f32>,
+    min_t: impl AsExpr<Value = f32>,
+    max_t: impl AsExpr<Value = f32>,
+    k: impl AsExpr<Value = f32>,
+) -> Expr<f32> {
+    let max_t = max_t.as_expr();
+    let k = k.as_expr();
+    let result = 1.0_f32.var();
+    let t = min_t.as_expr().var();
+    let steps = 0_u32.var();
+    loop {
+        if **steps >= 256 || **t >= max_t || **result < -1.0 {
+            break;
+        }
+        let h = f(start + dir * **t);
+        *result = keter::min(**result, k * h / **t);
+        *t += h.clamp(0.005, 0.5);
+        *steps += 1;
+    }
+    let result = keter::max(**result, -1.0);
+    0.25 * (1.0 + result) * (1.0 + result) * (2.0 - result)
+}
+
+/// Approximate ambient occlusion at `p` with normal `n`, from `0` (occluded) to `1`.
+/// Upward facing surfaces, along +Z, are brightened.
+// https://iquilezles.org/articles/nvscene2008/rwwtt.pdf
+#[tracked]
+pub fn ambient_occlusion(
+    p: Expr<Vec3<f32>>,
+    n: Expr<Vec3<f32>>,
+    f: impl Fn(Expr<Vec3<f32>>) -> Expr<f32>,
+) -> Expr<f32> {
+    let mut occlusion = 0.0_f32.expr();
+    let mut weight = 1.0;
+    for h in (0..5).map(|i| 0.01 + 0.12 * i as f32 / 4.0) {
+        let d = f(p + n * h);
+        occlusion = occlusion + (h - d) * weight;
+        weight *= 0.95;
+    }
+    (1.0 - 3.0 * occlusion).clamp(0.0, 1.0) * (0.5 + 0.5 * n.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::eval;
+
+    fn scene(p: Expr<Vec3<f32>>) -> Expr<f32> {
+        union(
+            sphere(translate(p, Vec3::expr(0.0, 0.0, 1.0)), 1.0_f32),
+            plane(p, Vec3::expr(0.0, 0.0, 1.0), 0.0_f32),
+        )
+    }
+
+    #[test]
+    fn primitives() {
+        let values = eval::<f32>(1, |_| {
+            let p = Vec3::expr(2.0, 0.0, 0.0);
+            cuboid(p, Vec3::splat_expr(1.0_f32))
+                + rounded_cuboid(p, Vec3::splat_expr(1.0_f32), 0.5_f32) * 10.0
+                + torus(p, Vec2::expr(2.0, 0.5)) * 100.0
+        });
+        // 1 + 10 * 1 + 100 * -0.5.
+        assert!((values[0] - (1.0 + 10.0 - 50.0)).abs() < 1e-5);
+        let capsule = eval::<f32>(1, |_| {
+            capsule(
+                Vec3::expr(0.0, 3.0, 1.0),
+                Vec3::expr(0.0, 0.0, 0.0),
+                Vec3::expr(0.0, 0.0, 2.0),
+                1.0_f32,
+            )
+        });
+        assert!((capsule[0] - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn combinators() {
+        let values = eval::<f32>(
+            64,
+            track!(|i| {
+                let a = i.cast_f32() / 16.0 - 2.0;
+                let b = 1.0 - a;
+                let smooth = smooth_union(a, b, 0.5_f32);
+                // The smooth union is never above the union, and only differs within the blend.
+                let bad = smooth > union(a, b)
+                    || ((a - b).abs() > 2.0 && smooth != union(a, b))
+                    || subtraction(a, b) != intersection(a, -b);
+                bad.cast_f32()
+            }),
+        );
+        assert!(values.iter().all(|&x| x == 0.0));
+        let repeated = eval::<Vec3<f32>>(1, |_| {
+            repeat(Vec3::expr(7.5, -3.2, 0.4), Vec3::splat_expr(2.0_f32))
+        });
+        let expected = [-0.5, 0.8, 0.4];
+        for (x, y) in [repeated[0].x, repeated[0].y, repeated[0].z]
+            .into_iter()
+            .zip(expected)
+        {
+            assert!((x - y).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn trace_sphere() {
+        let tracer = SphereTracer::default();
+        let results = eval::<Vec3<f32>>(2, |i| {
+            let start = Vec3::expr(-5.0, 0.0, 1.0 + i.cast_f32() * 5.0);
+            let dir = Vec3::expr(1.0, 0.0, 0.0);
+            let t = tracer.trace(start, dir, scene);
+            let n = normal(start + dir * t, scene, 1e-3_f32);
+            Vec3::expr(t, n.x, n.z)
+        });
+        assert!((results[0].x - 4.0).abs() < 1e-3);
+        assert!((results[0].y + 1.0).abs() < 1e-2);
+        assert!(results[1].x.is_infinite());
+    }
+
+    #[test]
+    fn shading() {
+        let results = eval::<Vec2<f32>>(2, |i| {
+            // A point on the plane, either under the sphere or far away from it.
+            let p = Vec3::expr(0.3 + i.cast_f32() * 10.0, 0.0, 0.0);
+            let n = Vec3::expr(0.0, 0.0, 1.0);
+            let shadow = soft_shadow(
+                p,
+                Vec3::expr(-1.0, 0.0, 1.0).normalize(),
+                scene,
+                0.01_f32,
+                20.0_f32,
+                8.0_f32,
+            );
+            Vec2::expr(shadow, ambient_occlusion(p, n, scene))
+        });
+        assert!(results[0].x < 0.1 && results[1].x > 0.99);
+        assert!(results[0].y < results[1].y && results[1].y > 0.99);
+    }
+}
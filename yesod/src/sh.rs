@@ -0,0 +1,308 @@
+//! Real spherical harmonics up to L2, for storing low-frequency lighting such as irradiance.
+//!
+//! Coefficients are arrays of length [`L1`] or [`L2`], with one RGB value per basis function.
+// https://www.ppsloan.org/publications/StupidSH36.pdf
+
+use std::f32::consts::PI;
+
+use keter::lang::types::vector::Vec3;
+use keter::prelude::*;
+
+use crate::utils::FetchAddVector;
+
+/// The number of coefficients for bands 0 and 1.
+pub const L1: usize = 4;
+/// The number of coefficients for bands 0 to 2.
+pub const L2: usize = 9;
+
+const Y0: f32 = 0.282_094_8;
+const Y1: f32 = 0.488_602_5;
+const Y2: [f32; 3] = [1.092_548_4, 0.315_391_57, 0.546_274_2];
+
+// The band of each coefficient.
+fn band(index: usize) -> usize {
+    (index as f32).sqrt() as usize
+}
+
+/// The cosine lobe convolution factor of each band.
+// https://cseweb.ucsd.edu/~ravir/papers/envmap/envmap.pdf
+pub const COSINE_LOBE: [f32; 3] = [PI, 2.0 * PI / 3.0, PI / 4.0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    /// Smoothly fades out higher bands.
+    Hann,
+    /// Fades out higher bands less than [`Window::Hann`], keeping more detail.
+    Lanczos,
+}
+impl Window {
+    /// The factor applied to each band, where a larger `width` keeps more of the higher bands.
+    /// A width of one more than the highest band is typical.
+    // https://www.ppsloan.org/publications/shdering.pdf
+    pub fn factors(self, width: f32) -> [f32; 3] {
+        std::array::from_fn(|l| {
+            let x = l as f32 / width;
+            if x >= 1.0 {
+                return 0.0;
+            }
+            match self {
+                Window::Hann => 0.5 * (1.0 + (PI * x).cos()),
+                Window::Lanczos if l == 0 => 1.0,
+                Window::Lanczos => (PI * x).sin() / (PI * x),
+            }
+        })
+    }
+}
+
+/// The first `N` basis functions for a normalized direction.
+pub fn basis<const N: usize>(dir: Expr<Vec3<f32>>) -> [Expr<f32>; N] {
+    const { assert!(N == L1 || N == L2) };
+    let [x, y, z] = [dir.x, dir.y, dir.z];
+    let basis = [
+        Y0.expr(),
+        Y1 * y,
+        Y1 * z,
+        Y1 * x,
+        Y2[0] * x * y,
+        Y2[0] * y * z,
+        Y2[1] * (3.0 * z * z - 1.0),
+        Y2[0] * x * z,
+        Y2[2] * (x * x - y * y),
+    ];
+    std::array::from_fn(|i| basis[i])
+}
+
+/// Adds the projection of `value` from direction `dir` to the coefficients starting at
+/// `offset` in `buffer` atomically.
+///
+/// `value` should be weighted by the solid angle it represents, such as `4π / n` for `n`
+/// uniformly distributed samples.
+pub fn project<const N: usize>(
+    buffer: &Buffer<Vec3<f32>>,
+    offset: Expr<u32>,
+    dir: Expr<Vec3<f32>>,
+    value: Expr<Vec3<f32>>,
+) {
+    for (i, y) in basis::<N>(dir).into_iter().enumerate() {
+        buffer.atomic_ref(offset + i as u32).fetch_add(y * value);
+    }
+}
+
+/// Reads the coefficients starting at `offset` in `buffer`.
+pub fn load<const N: usize>(buffer: &Buffer<Vec3<f32>>, offset: Expr<u32>) -> [Expr<Vec3<f32>>; N] {
+    std::array::from_fn(|i| buffer.read(offset + i as u32))
+}
+
+/// Evaluates the function represented by the coefficients in the direction `dir`.
+pub fn evaluate<const N: usize>(
+    coefficients: [Expr<Vec3<f32>>; N],
+    dir: Expr<Vec3<f32>>,
+) -> Expr<Vec3<f32>> {
+    coefficients
+        .into_iter()
+        .zip(basis::<N>(dir))
+        .fold(Vec3::splat_expr(0.0), |sum, (c, y)| sum + y * c)
+}
+
+/// Scales each band by `factors`.
+pub fn scale_bands<const N: usize>(
+    coefficients: [Expr<Vec3<f32>>; N],
+    factors: [f32; 3],
+) -> [Expr<Vec3<f32>>; N] {
+    std::array::from_fn(|i| coefficients[i] * factors[band(i)])
+}
+
+/// Convolves radiance with the clamped cosine lobe, giving irradiance.
+pub fn convolve_cosine<const N: usize>(coefficients: [Expr<Vec3<f32>>; N]) -> [Expr<Vec3<f32>>; N] {
+    scale_bands(coefficients, COSINE_LOBE)
+}
+
+/// Applies a window to reduce ringing, such as negative lobes opposite bright lights.
+pub fn window<const N: usize>(
+    coefficients: [Expr<Vec3<f32>>; N],
+    window: Window,
+    width: f32,
+) -> [Expr<Vec3<f32>>; N] {
+    scale_bands(coefficients, window.factors(width))
+}
+
+/// The irradiance onto a surface with the given normal from radiance coefficients.
+/// Dividing by `π` and multiplying by the albedo gives the outgoing diffuse radiance.
+pub fn irradiance<const N: usize>(
+    coefficients: [Expr<Vec3<f32>>; N],
+    normal: Expr<Vec3<f32>>,
+) -> Expr<Vec3<f32>> {
+    keter::max(
+        evaluate(convolve_cosine(coefficients), normal),
+        Vec3::splat_expr(0.0),
+    )
+}
+
+/// Reference implementations on the host.
+pub mod host {
+    use glam::Vec3;
+
+    use super::*;
+
+    pub fn basis<const N: usize>(dir: Vec3) -> [f32; N] {
+        const { assert!(N == L1 || N == L2) };
+        let Vec3 { x, y, z } = dir;
+        let basis = [
+            Y0,
+            Y1 * y,
+            Y1 * z,
+            Y1 * x,
+            Y2[0] * x * y,
+            Y2[0] * y * z,
+            Y2[1] * (3.0 * z * z - 1.0),
+            Y2[0] * x * z,
+            Y2[2] * (x * x - y * y),
+        ];
+        std::array::from_fn(|i| basis[i])
+    }
+    pub fn project<const N: usize>(coefficients: &mut [Vec3; N], dir: Vec3, value: Vec3) {
+        for (c, y) in coefficients.iter_mut().zip(basis::<N>(dir)) {
+            *c += value * y;
+        }
+    }
+    pub fn evaluate<const N: usize>(coefficients: [Vec3; N], dir: Vec3) -> Vec3 {
+        coefficients
+            .into_iter()
+            .zip(basis::<N>(dir))
+            .map(|(c, y)| c * y)
+            .sum()
+    }
+    pub fn scale_bands<const N: usize>(coefficients: [Vec3; N], factors: [f32; 3]) -> [Vec3; N] {
+        std::array::from_fn(|i| coefficients[i] * factors[band(i)])
+    }
+    pub fn convolve_cosine<const N: usize>(coefficients: [Vec3; N]) -> [Vec3; N] {
+        scale_bands(coefficients, COSINE_LOBE)
+    }
+    pub fn window<const N: usize>(
+        coefficients: [Vec3; N],
+        window: Window,
+        width: f32,
+    ) -> [Vec3; N] {
+        scale_bands(coefficients, window.factors(width))
+    }
+    pub fn irradiance<const N: usize>(coefficients: [Vec3; N], normal: Vec3) -> Vec3 {
+        evaluate(convolve_cosine(coefficients), normal).max(Vec3::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3 as FVec3;
+
+    use super::*;
+    use crate::tests::{CPU_DEVICE, eval};
+
+    // Stratified directions covering the sphere, with the solid angle of each.
+    fn sphere_samples(n: u32) -> Vec<(FVec3, f32)> {
+        (0..n * n)
+            .map(|i| {
+                let z = 1.0 - 2.0 * ((i / n) as f32 + 0.5) / n as f32;
+                let phi = 2.0 * PI * ((i % n) as f32 + 0.5) / n as f32;
+                let r = (1.0 - z * z).sqrt();
+                (
+                    FVec3::new(r * phi.cos(), r * phi.sin(), z),
+                    4.0 * PI / (n * n) as f32,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn uniform_irradiance() {
+        let mut coefficients = [FVec3::ZERO; L2];
+        for (dir, weight) in sphere_samples(64) {
+            host::project(&mut coefficients, dir, FVec3::ONE * weight);
+        }
+        for normal in [
+            FVec3::X,
+            FVec3::NEG_Z,
+            FVec3::new(1.0, 2.0, 3.0).normalize(),
+        ] {
+            let irradiance = host::irradiance(coefficients, normal);
+            assert!((irradiance - PI).abs().max_element() < 1e-3, "{irradiance}");
+        }
+    }
+
+    #[test]
+    fn directional_light() {
+        let dir = FVec3::new(0.3, -0.4, 0.5).normalize();
+        let mut l1 = [FVec3::ZERO; L1];
+        let mut l2 = [FVec3::ZERO; L2];
+        host::project(&mut l1, dir, FVec3::ONE);
+        host::project(&mut l2, dir, FVec3::ONE);
+        // The convolved delta at its own direction is the sum of the lobe factors times
+        // (2l + 1) / 4π, which approaches the cosine's 1 as bands are added.
+        assert!((host::irradiance(l1, dir).x - 0.75).abs() < 1e-4);
+        assert!((host::irradiance(l2, dir).x - 1.0625).abs() < 1e-4);
+        // Ringing makes the irradiance negative away from the light, which windowing removes.
+        let min = |coefficients: [FVec3; L2]| {
+            sphere_samples(32)
+                .into_iter()
+                .map(|(normal, _)| host::evaluate(host::convolve_cosine(coefficients), normal).x)
+                .fold(f32::INFINITY, f32::min)
+        };
+        assert!(min(l2) < -0.03);
+        assert!(min(host::window(l2, Window::Hann, 2.0)) > -1e-5);
+    }
+
+    #[test]
+    fn window_factors() {
+        assert_eq!(Window::Hann.factors(3.0)[0], 1.0);
+        assert!((Window::Hann.factors(3.0)[2] - 0.25).abs() < 1e-6);
+        let lanczos = Window::Lanczos.factors(2.0);
+        assert_eq!([lanczos[0], lanczos[2]], [1.0, 0.0]);
+        assert!((lanczos[1] - 2.0 / PI).abs() < 1e-6);
+    }
+
+    #[test]
+    fn matches_host() {
+        let samples = sphere_samples(16);
+        let buffer = CPU_DEVICE.create_buffer_from_slice(&[Vec3::splat(0.0); L2]);
+        let dirs = CPU_DEVICE.create_buffer_from_slice(
+            &samples
+                .iter()
+                .map(|&(dir, _)| dir.into())
+                .collect::<Vec<Vec3<f32>>>(),
+        );
+        let radiance = |dir: FVec3| FVec3::new(1.0 + dir.x, 0.5 + dir.z * dir.z, dir.y.max(0.0));
+        let weight = samples[0].1;
+        CPU_DEVICE
+            .create_kernel::<fn()>(&track!(|| {
+                let dir = dirs.read(dispatch_id().x);
+                let value = Vec3::expr(1.0 + dir.x, 0.5 + dir.z * dir.z, keter::max(dir.y, 0.0));
+                project::<L2>(&buffer, 0_u32.expr(), dir, value * weight);
+            }))
+            .dispatch([samples.len() as u32, 1, 1]);
+
+        let mut expected = [FVec3::ZERO; L2];
+        for &(dir, weight) in &samples {
+            host::project(&mut expected, dir, radiance(dir) * weight);
+        }
+        let coefficients = buffer.copy_to_vec();
+        for (a, b) in coefficients.iter().zip(expected) {
+            let a: FVec3 = (*a).into();
+            assert!((a - b).abs().max_element() < 1e-4);
+        }
+
+        let normals = [FVec3::Z, FVec3::new(-1.0, 0.5, 0.2).normalize()];
+        let normal_buffer = CPU_DEVICE.create_buffer_from_slice(&normals.map(Vec3::<f32>::from));
+        let results = eval::<Vec3<f32>>(normals.len() as u32, |i| {
+            let coefficients = window(load::<L2>(&buffer, 0_u32.expr()), Window::Hann, 3.0);
+            irradiance(coefficients, normal_buffer.read(i))
+        });
+        let windowed = host::window(expected, Window::Hann, 3.0);
+        for (result, normal) in results.into_iter().zip(normals) {
+            let result: FVec3 = result.into();
+            let expected = host::irradiance(windowed, normal);
+            assert!(
+                (result - expected).abs().max_element() < 1e-4,
+                "{result} != {expected}"
+            );
+        }
+    }
+}
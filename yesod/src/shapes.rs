@@ -1,7 +1,18 @@
 use keter::lang::types::vector::{Vec2, Vec3};
 use keter::prelude::*;
 
-// TODO: Add ray offset fix: file:///home/keter/Downloads/unofficial_RayTracingGems_v1.9.pdf#page=120
+mod hierarchical_dda;
+mod lines;
+mod primitives;
+pub use hierarchical_dda::{DdaLevel, Occupancy, hierarchical_dda, pack_occupancy};
+pub use lines::{bresenham, dda_2d, supercover};
+pub use primitives::{
+    Hit, HitExpr, HitVar, intersect_box, intersect_capsule, intersect_cone, intersect_cylinder,
+    intersect_disc, intersect_plane, intersect_sphere_at, intersect_torus, intersect_triangle,
+    intersect_triangle_watertight, orthonormal_basis,
+};
+
+// Secondary rays should start at `crate::utils::offset_ray` to avoid self-intersection.
 
 #[tracked]
 pub fn intersect_aabb(
@@ -17,8 +28,8 @@ pub fn intersect_aabb(
     Vec2::expr(tmin, tmax)
 }
 
-// see file:///home/keter/Downloads/unofficial_RayTracingGems_v1.9.pdf#page=122 for precision improvements
-// does dir need to be normalized?
+/// Intersects a sphere at the origin, where `dir` must be normalized.
+/// [`intersect_sphere_at`] is more precise far from the sphere.
 #[tracked]
 pub fn intersect_sphere(
     start: Expr<Vec3<f32>>,
@@ -42,7 +53,7 @@ pub const TOTAL_MISS_STATE: u32 = 0b100;
 pub const LEAVE_STATE: u32 = 0b10;
 pub const HIT_STATE: u32 = 0b1;
 
-// Not the most optimal: see https://www.shadertoy.com/view/X3BXDd, https://www.shadertoy.com/view/X3SXDy
+// Not the most optimal, and `hierarchical_dda` skips empty space: see https://www.shadertoy.com/view/X3BXDd, https://www.shadertoy.com/view/X3SXDy
 #[tracked]
 pub fn dda(
     start: Expr<Vec3<f32>>,
@@ -0,0 +1,325 @@
+use keter::lang::types::vector::Vec3;
+use keter::prelude::*;
+
+use super::{HIT_STATE, LEAVE_STATE, TOTAL_MISS_STATE, intersect_aabb};
+
+/// Which cells of a coarse grid contain anything.
+#[derive(Debug, Clone, Copy)]
+pub enum Occupancy<'a> {
+    /// Cells with a non-zero texel are occupied.
+    Texture(&'a Tex3d<f32>),
+    /// One bit per cell, in x-major order within a grid of `size` cells.
+    /// See [`pack_occupancy`].
+    Bitmask {
+        bits: &'a Buffer<u32>,
+        size: [u32; 3],
+    },
+}
+impl Occupancy<'_> {
+    #[tracked]
+    pub fn is_occupied(&self, cell: Expr<Vec3<u32>>) -> Expr<bool> {
+        match self {
+            Occupancy::Texture(texture) => texture.read(cell) != 0.0,
+            Occupancy::Bitmask { bits, size } => {
+                let index = cell.x + size[0] * (cell.y + size[1] * cell.z);
+                (bits.read(index / 32) >> (index % 32)) & 1 != 0
+            }
+        }
+    }
+}
+
+/// Packs the occupancy of a grid of `size` cells into the layout of [`Occupancy::Bitmask`].
+pub fn pack_occupancy(size: [u32; 3], occupied: impl Fn([u32; 3]) -> bool) -> Vec<u32> {
+    let [w, h, d] = size;
+    let mut bits = vec![0; (w * h * d).div_ceil(32) as usize];
+    for z in 0..d {
+        for y in 0..h {
+            for x in 0..w {
+                if occupied([x, y, z]) {
+                    let index = x + w * (y + h * z);
+                    bits[(index / 32) as usize] |= 1 << (index % 32);
+                }
+            }
+        }
+    }
+    bits
+}
+
+/// A coarse level of a [`hierarchical_dda`].
+#[derive(Debug, Clone, Copy)]
+pub struct DdaLevel<'a> {
+    pub occupancy: Occupancy<'a>,
+    /// The width of each cell, in voxels.
+    pub cell_size: u32,
+}
+
+// The cell size of every level, including the voxels.
+fn cell_sizes(levels: &[DdaLevel]) -> Vec<u32> {
+    let mut sizes = levels
+        .iter()
+        .map(|level| level.cell_size)
+        .collect::<Vec<_>>();
+    sizes.push(1);
+    for pair in sizes.windows(2) {
+        assert!(
+            pair[0] > pair[1] && pair[0] % pair[1] == 0,
+            "Each cell size must be a multiple of the next, got {sizes:?}."
+        );
+    }
+    sizes
+}
+
+/// Walks the voxels along a ray like [`dda`](super::dda), skipping over the unoccupied cells
+/// of the coarser `levels`, which are ordered from coarsest to finest.
+///
+/// `f` is only called for voxels within occupied cells of every level, and the result has the
+/// same bits as [`dda`](super::dda). Voxel coordinates, and so `bounds`, must be non-negative.
+// Stepping happens at the current level, descending into occupied cells and ascending when
+// a step leaves the parent cell, so the cells are tracked exactly with integers.
+#[tracked]
+pub fn hierarchical_dda(
+    start: Expr<Vec3<f32>>,
+    ray_dir: Expr<Vec3<f32>>,
+    length: Expr<f32>,
+    bounds: (Expr<Vec3<f32>>, Expr<Vec3<f32>>),
+    levels: &[DdaLevel],
+    f: impl Fn(Expr<Vec3<i32>>, Expr<f32>, Expr<f32>) -> Expr<bool>,
+) -> Expr<u32> {
+    let sizes = cell_sizes(levels);
+    let finest = levels.len() as u32;
+    let cell_size = |level: Expr<u32>| {
+        let mut size = 1_u32.expr();
+        for (l, &s) in sizes.iter().enumerate() {
+            size = if level == l as u32 { s.expr() } else { size };
+        }
+        size.cast_i32()
+    };
+
+    let inv_dir = (ray_dir + f32::EPSILON).recip();
+    let interval = intersect_aabb(start, inv_dir, bounds.0, bounds.1);
+    let start_t = keter::max(interval.x, 0.0);
+    let end_t = keter::min(interval.y, length);
+    let state = 0_u32.var();
+    if interval.y < length {
+        *state |= LEAVE_STATE;
+    }
+    if end_t - start_t >= 0.01 {
+        let positive = inv_dir >= Vec3::splat_expr(0.0);
+        let step = positive.select(Vec3::splat_expr(1_i32), Vec3::splat_expr(-1_i32));
+        let far_side = positive.select(Vec3::splat_expr(1_i32), Vec3::splat_expr(0_i32));
+
+        let lower = bounds.0.floor().cast_i32();
+        let upper = bounds.1.ceil().cast_i32() - 1;
+        let voxel = (start + start_t * ray_dir)
+            .floor()
+            .cast_i32()
+            .clamp(lower, upper);
+        let level = 0_u32.var();
+        let cell = (voxel / sizes[0] as i32).var();
+        let t = start_t.var();
+
+        loop {
+            let size = cell_size(**level);
+            let side_t = (((**cell + far_side) * size).cast_f32() - start) * inv_dir;
+            let next_t = side_t.reduce_min();
+
+            let descend = false.var();
+            if **level == finest {
+                if f(**cell, **t, keter::min(next_t, end_t)) {
+                    *state |= HIT_STATE;
+                    break;
+                }
+            } else {
+                for (l, coarse) in levels.iter().enumerate() {
+                    if **level == l as u32 {
+                        *descend = coarse.occupancy.is_occupied(cell.cast_u32());
+                    }
+                }
+            }
+
+            if **descend {
+                // Enter the child containing the current point, which may lie on its boundary.
+                let child_size = cell_size(**level + 1);
+                let ratio = size / child_size;
+                let first = **cell * ratio;
+                let p = start + **t * ray_dir;
+                *cell = (p / child_size.cast_f32())
+                    .floor()
+                    .cast_i32()
+                    .clamp(first, first + ratio - 1);
+                *level += 1;
+            } else {
+                if next_t >= end_t {
+                    break;
+                }
+                *t = next_t;
+                let mask = side_t <= keter::min(side_t.yzx(), side_t.zxy());
+                let previous = (**cell).var();
+                *cell += mask.select(step, Vec3::splat_expr(0));
+                loop {
+                    if **level == 0 {
+                        break;
+                    }
+                    let ratio = cell_size(**level - 1) / cell_size(**level);
+                    let parent = **cell / ratio;
+                    let previous_parent = **previous / ratio;
+                    if (parent == previous_parent).all() {
+                        break;
+                    }
+                    *cell = parent;
+                    *previous = previous_parent;
+                    *level -= 1;
+                }
+            }
+        }
+    } else {
+        *state |= TOTAL_MISS_STATE;
+    }
+    **state
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3 as FVec3;
+    use keter::lang::types::vector::Vec2;
+
+    use super::*;
+    use crate::rand::Rng;
+    use crate::shapes::dda;
+    use crate::tests::{CPU_DEVICE, eval};
+
+    const SIZE: u32 = 32;
+
+    // Whether the ray starts near a face of the voxel grid or passes near an edge of it before
+    // `t = 100`, where rounding could make either walk take a different path.
+    fn near_edge(start: FVec3, dir: FVec3) -> bool {
+        let mut crossings = vec![];
+        for axis in 0..3 {
+            let inv_dir = 1.0 / (dir[axis] + f32::EPSILON) as f64;
+            for plane in 0..=SIZE {
+                let t = (plane as f64 - start[axis] as f64) * inv_dir;
+                if t.abs() < 1e-3 {
+                    return true;
+                }
+                if (0.0..100.0).contains(&t) {
+                    crossings.push((t, axis));
+                }
+            }
+        }
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+        crossings
+            .windows(2)
+            .any(|pair| pair[0].1 != pair[1].1 && pair[1].0 - pair[0].0 < 1e-3)
+    }
+
+    #[test]
+    fn matches_dda() {
+        // Bricks of 4 voxels, a quarter of which contain scattered voxels.
+        let mut rng = Rng::new(0, 0);
+        let bricks = (0..(SIZE / 4).pow(3))
+            .map(|_| rng.next_f32() < 0.25)
+            .collect::<Vec<_>>();
+        let voxels = (0..SIZE.pow(3))
+            .map(|i| {
+                let [x, y, z] = [i % SIZE, i / SIZE % SIZE, i / SIZE / SIZE];
+                let brick = x / 4 + SIZE / 4 * (y / 4 + SIZE / 4 * (z / 4));
+                rng.next_f32() < 0.2 && bricks[brick as usize]
+            })
+            .collect::<Vec<_>>();
+        let any_in = |[x, y, z]: [u32; 3], cell_size: u32| {
+            (0..cell_size.pow(3)).any(|i| {
+                let offset = [
+                    i % cell_size,
+                    i / cell_size % cell_size,
+                    i / cell_size / cell_size,
+                ];
+                let [x, y, z] = [
+                    x * cell_size + offset[0],
+                    y * cell_size + offset[1],
+                    z * cell_size + offset[2],
+                ];
+                voxels[(x + SIZE * (y + SIZE * z)) as usize]
+            })
+        };
+
+        let fine =
+            CPU_DEVICE.create_buffer_from_slice(&pack_occupancy([SIZE; 3], |cell| any_in(cell, 1)));
+        let fine = Occupancy::Bitmask {
+            bits: &fine,
+            size: [SIZE; 3],
+        };
+        let coarse = CPU_DEVICE.create_tex3d::<f32>(f32::natural_storage(), 4, 4, 4, 1);
+        coarse.view(0).copy_from(
+            &(0..64)
+                .map(|i| any_in([i % 4, i / 4 % 4, i / 16], 8) as u32 as f32)
+                .collect::<Vec<_>>(),
+        );
+        let brick_occupancy =
+            CPU_DEVICE.create_buffer_from_slice(&pack_occupancy([8; 3], |cell| any_in(cell, 4)));
+        let levels = [
+            DdaLevel {
+                occupancy: Occupancy::Texture(&coarse),
+                cell_size: 8,
+            },
+            DdaLevel {
+                occupancy: Occupancy::Bitmask {
+                    bits: &brick_occupancy,
+                    size: [8; 3],
+                },
+                cell_size: 4,
+            },
+        ];
+
+        // Rays near edges are skipped, as the walks may break ties differently there.
+        let mut rays = vec![];
+        while rays.len() < 2048 {
+            let mut next = || FVec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32());
+            let start = next() * SIZE as f32;
+            let dir = (next() - 0.5).normalize();
+            if !near_edge(start, dir) {
+                rays.push((Vec3::from(start), Vec3::from(dir)));
+            }
+        }
+        let (starts, dirs): (Vec<_>, Vec<_>) = rays.into_iter().unzip();
+        let starts = CPU_DEVICE.create_buffer_from_slice(&starts);
+        let dirs = CPU_DEVICE.create_buffer_from_slice(&dirs);
+
+        let results = eval::<Vec2<u32>>(
+            2048,
+            track!(|i| {
+                let start = starts.read(i);
+                let dir = dirs.read(i);
+                let bounds = (Vec3::splat_expr(0.0), Vec3::splat_expr(SIZE as f32));
+                let expected = Vec3::splat_expr(-1_i32).var();
+                let expected_state = dda(start, dir, 100.0_f32.expr(), bounds, |cell, _, _| {
+                    let occupied = fine.is_occupied(cell.cast_u32());
+                    if occupied {
+                        *expected = cell;
+                    }
+                    occupied
+                });
+                let hit = Vec3::splat_expr(-1_i32).var();
+                let state = hierarchical_dda(
+                    start,
+                    dir,
+                    100.0_f32.expr(),
+                    bounds,
+                    &levels,
+                    |cell, _, _| {
+                        let occupied = fine.is_occupied(cell.cast_u32());
+                        if occupied {
+                            *hit = cell;
+                        }
+                        occupied
+                    },
+                );
+                let matches = (**hit == **expected).all() && state == expected_state;
+                Vec2::expr(matches.cast_u32(), state & HIT_STATE)
+            }),
+        );
+        let mismatches = results.iter().filter(|x| x.x == 0).count();
+        assert_eq!(mismatches, 0, "{mismatches} rays differ.");
+        let hits = results.iter().filter(|x| x.y != 0).count();
+        assert!(hits > results.len() / 2, "Only {hits} rays hit.");
+    }
+}
@@ -0,0 +1,288 @@
+use keter::lang::types::vector::Vec2;
+use keter::prelude::*;
+
+use super::{HIT_STATE, LEAVE_STATE, TOTAL_MISS_STATE};
+
+/// Walks the cells of a 2D grid along a ray like [`dda`](super::dda), calling `f` with each
+/// cell and the interval of `t` spent within it, and returning the same bits.
+///
+/// Cells are visited in order, and both axes are stepped at once when passing exactly through
+/// a corner. For a segment, use the normalized direction and its length.
+#[tracked]
+pub fn dda_2d(
+    start: Expr<Vec2<f32>>,
+    ray_dir: Expr<Vec2<f32>>,
+    length: Expr<f32>,
+    bounds: (Expr<Vec2<f32>>, Expr<Vec2<f32>>),
+    f: impl Fn(Expr<Vec2<i32>>, Expr<f32>, Expr<f32>) -> Expr<bool>,
+) -> Expr<u32> {
+    let inv_dir = (ray_dir + f32::EPSILON).recip();
+    let t0 = (bounds.0 - start) * inv_dir;
+    let t1 = (bounds.1 - start) * inv_dir;
+    let interval = Vec2::expr(
+        keter::min(t0, t1).reduce_max(),
+        keter::max(t0, t1).reduce_min(),
+    );
+    let start_t = keter::max(interval.x, 0.0);
+    let end_t = keter::min(interval.y, length);
+    let state = 0_u32.var();
+    if interval.y < length {
+        *state |= LEAVE_STATE;
+    }
+    if end_t - start_t >= 0.0 {
+        let positive = inv_dir >= Vec2::splat_expr(0.0);
+        let step = positive.select(Vec2::splat_expr(1_i32), Vec2::splat_expr(-1_i32));
+        let far_side = positive.select(Vec2::splat_expr(1_i32), Vec2::splat_expr(0_i32));
+
+        let lower = bounds.0.floor().cast_i32();
+        let upper = bounds.1.ceil().cast_i32() - 1;
+        let cell = (start + start_t * ray_dir)
+            .floor()
+            .cast_i32()
+            .clamp(lower, upper)
+            .var();
+        let t = start_t.var();
+        loop {
+            let side_t = ((**cell + far_side).cast_f32() - start) * inv_dir;
+            let next_t = side_t.reduce_min();
+            if f(**cell, **t, keter::min(next_t, end_t)) {
+                *state |= HIT_STATE;
+                break;
+            }
+            if next_t >= end_t {
+                break;
+            }
+            *t = next_t;
+            let mask = side_t <= side_t.yx();
+            *cell += mask.select(step, Vec2::splat_expr(0));
+        }
+    } else {
+        *state |= TOTAL_MISS_STATE;
+    }
+    **state
+}
+
+#[tracked]
+fn step_towards(start: Expr<Vec2<i32>>, end: Expr<Vec2<i32>>) -> Expr<Vec2<i32>> {
+    (end >= start).select(Vec2::splat_expr(1), Vec2::splat_expr(-1))
+}
+
+/// Calls `f` with each cell of the 8-connected Bresenham line from `start` to `end` inclusive,
+/// stopping and returning true if it returns true.
+#[tracked]
+pub fn bresenham(
+    start: Expr<Vec2<i32>>,
+    end: Expr<Vec2<i32>>,
+    f: impl Fn(Expr<Vec2<i32>>) -> Expr<bool>,
+) -> Expr<bool> {
+    let delta = (end - start).abs();
+    let step = step_towards(start, end);
+    let error = (delta.x - delta.y).var();
+    let cell = start.var();
+    let hit = false.var();
+    loop {
+        if f(**cell) {
+            *hit = true;
+            break;
+        }
+        if (**cell == end).all() {
+            break;
+        }
+        let doubled = 2 * **error;
+        if doubled >= -delta.y {
+            *error -= delta.y;
+            *cell += Vec2::expr(step.x, 0);
+        }
+        if doubled <= delta.x {
+            *error += delta.x;
+            *cell += Vec2::expr(0, step.y);
+        }
+    }
+    **hit
+}
+
+/// Calls `f` with every cell touched by the segment between the centers of `start` and `end`,
+/// stopping and returning true if it returns true.
+///
+/// The cells are 4-connected, except that when the segment passes exactly through a corner,
+/// both cells beside the corner are visited before the diagonal one.
+// https://www.redblobgames.com/grids/line-drawing/#supercover
+#[tracked]
+pub fn supercover(
+    start: Expr<Vec2<i32>>,
+    end: Expr<Vec2<i32>>,
+    f: impl Fn(Expr<Vec2<i32>>) -> Expr<bool>,
+) -> Expr<bool> {
+    let delta = (end - start).abs();
+    let step = step_towards(start, end);
+    // The last cell on the line, and the number of steps taken along each axis to reach it.
+    let pos = start.var();
+    let progress = Vec2::splat_expr(0_i32).var();
+    // The cell to visit next, which is beside `pos` while passing through a corner.
+    let cell = start.var();
+    let corner = 0_u32.var();
+    let hit = false.var();
+    loop {
+        if f(**cell) {
+            *hit = true;
+            break;
+        }
+        if **corner == 1 {
+            *cell = **pos + Vec2::expr(0, step.y);
+            *corner = 2;
+        } else {
+            if **corner == 2 {
+                *pos += step;
+                *progress += Vec2::splat_expr(1);
+                *corner = 0;
+            } else {
+                if (**progress >= delta).all() {
+                    break;
+                }
+                let progress_so_far = **progress;
+                let decision =
+                    (1 + 2 * progress_so_far.x) * delta.y - (1 + 2 * progress_so_far.y) * delta.x;
+                if decision == 0 {
+                    *corner = 1;
+                } else if decision < 0 {
+                    *pos += Vec2::expr(step.x, 0);
+                    *progress += Vec2::expr(1, 0);
+                } else {
+                    *pos += Vec2::expr(0, step.y);
+                    *progress += Vec2::expr(0, 1);
+                }
+            }
+            *cell = if **corner == 1 {
+                **pos + Vec2::expr(step.x, 0)
+            } else {
+                **pos
+            };
+        }
+    }
+    **hit
+}
+
+#[cfg(test)]
+mod tests {
+    use keter::lang::types::vector::Vec4;
+
+    use super::*;
+    use crate::tests::eval;
+
+    // Returns the first `n` cells visited by `walk`, with `i32::MIN` past the end.
+    fn visited(n: u32, walk: impl Fn(&dyn Fn(Expr<Vec2<i32>>) -> Expr<bool>)) -> Vec<[i32; 2]> {
+        eval::<Vec2<i32>>(
+            n,
+            track!(|i| {
+                let count = 0_u32.var();
+                let cell = Vec2::splat_expr(i32::MIN).var();
+                walk(&|c: Expr<Vec2<i32>>| {
+                    if **count == i {
+                        *cell = c;
+                    }
+                    *count += 1;
+                    false.expr()
+                });
+                **cell
+            }),
+        )
+        .into_iter()
+        .map(|c| [c.x, c.y])
+        .collect()
+    }
+
+    fn line(start: [i32; 2], end: [i32; 2]) -> (Expr<Vec2<i32>>, Expr<Vec2<i32>>) {
+        (
+            Vec2::new(start[0], start[1]).expr(),
+            Vec2::new(end[0], end[1]).expr(),
+        )
+    }
+
+    #[test]
+    fn bresenham_cells() {
+        let cells = visited(7, |f| {
+            let (start, end) = line([0, 0], [5, -2]);
+            bresenham(start, end, f);
+        });
+        let end = [i32::MIN; 2];
+        assert_eq!(
+            cells,
+            [[0, 0], [1, 0], [2, -1], [3, -1], [4, -2], [5, -2], end]
+        );
+        let cells = visited(4, |f| {
+            let (start, end) = line([2, 3], [2, 1]);
+            bresenham(start, end, f);
+        });
+        assert_eq!(cells, [[2, 3], [2, 2], [2, 1], end]);
+    }
+
+    #[test]
+    fn supercover_cells() {
+        let end = [i32::MIN; 2];
+        let cells = visited(7, |f| {
+            let (start, end) = line([0, 0], [3, 1]);
+            supercover(start, end, f);
+        });
+        assert_eq!(cells, [[0, 0], [1, 0], [2, 0], [1, 1], [2, 1], [3, 1], end]);
+        let cells = visited(8, |f| {
+            let (start, end) = line([0, 0], [-2, -2]);
+            supercover(start, end, f);
+        });
+        assert_eq!(
+            cells,
+            [
+                [0, 0],
+                [-1, 0],
+                [0, -1],
+                [-1, -1],
+                [-2, -1],
+                [-1, -2],
+                [-2, -2],
+                end
+            ]
+        );
+    }
+
+    #[test]
+    fn dda_2d_cells() {
+        let results = eval::<Vec4<f32>>(
+            6,
+            track!(|i| {
+                let start = Vec2::expr(0.5, 0.25);
+                let dir = Vec2::<f32>::expr(3.0, 1.0).normalize();
+                let bounds = (Vec2::splat_expr(0.0), Vec2::splat_expr(10.0));
+                let count = 0_u32.var();
+                let result = Vec4::splat_expr(-1.0_f32).var();
+                let state = dda_2d(start, dir, 10.0_f32.sqrt(), bounds, |cell, t0, t1| {
+                    if **count == i {
+                        *result = Vec4::expr(cell.x.cast_f32(), cell.y.cast_f32(), t0, t1);
+                    }
+                    *count += 1;
+                    false.expr()
+                });
+                if i == 5 {
+                    *result = Vec4::expr(state.cast_f32(), 0.0, 0.0, 0.0);
+                }
+                **result
+            }),
+        );
+        let scale = 10.0_f32.sqrt();
+        let expected = [
+            [0.0, 0.0, 0.0, scale / 6.0],
+            [1.0, 0.0, scale / 6.0, scale / 2.0],
+            [2.0, 0.0, scale / 2.0, scale * 0.75],
+            [2.0, 1.0, scale * 0.75, scale * 5.0 / 6.0],
+            [3.0, 1.0, scale * 5.0 / 6.0, scale],
+        ];
+        for (result, expected) in results.iter().zip(expected) {
+            let result = [result.x, result.y, result.z, result.w];
+            for (a, b) in result.into_iter().zip(expected) {
+                assert!(
+                    (a - b).abs() < 1e-4,
+                    "Expected {expected:?}, got {result:?}."
+                );
+            }
+        }
+        assert_eq!(results[5].x, 0.0);
+    }
+}
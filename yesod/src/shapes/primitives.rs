@@ -0,0 +1,834 @@
+use std::f32::consts::{PI, TAU};
+
+use keter::lang::types::vector::{Mat3, Vec2, Vec3};
+use keter::prelude::*;
+
+use crate::printer::PushBytes;
+
+/// A ray intersection, with `t` in units of the ray direction.
+///
+/// The normal always points out of the surface, even when the ray starts inside.
+/// A miss has `t` set to infinity.
+#[derive(Debug, Clone, Copy, Value, PushBytes)]
+#[repr(C)]
+pub struct Hit {
+    pub t: f32,
+    pub normal: Vec3<f32>,
+    pub uv: Vec2<f32>,
+}
+impl Hit {
+    #[tracked]
+    pub fn miss() -> Var<Hit> {
+        let hit = Hit::var_zeroed();
+        *hit.t = f32::INFINITY;
+        hit
+    }
+}
+impl HitExpr {
+    #[tracked]
+    pub fn is_hit(&self) -> Expr<bool> {
+        self.t < f32::INFINITY
+    }
+}
+
+/// Returns two unit vectors which are perpendicular to `n` and to each other.
+// Duff et al. 2017, Building an Orthonormal Basis, Revisited.
+#[tracked]
+pub fn orthonormal_basis(n: Expr<Vec3<f32>>) -> (Expr<Vec3<f32>>, Expr<Vec3<f32>>) {
+    let sign = if n.z >= 0.0 {
+        1.0_f32.expr()
+    } else {
+        (-1.0_f32).expr()
+    };
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::expr(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::expr(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+// The angle of `v` around `axis`, in `[0, 1)`.
+#[tracked]
+fn angle_around(v: Expr<Vec3<f32>>, axis: Expr<Vec3<f32>>) -> Expr<f32> {
+    let (t, b) = orthonormal_basis(axis);
+    v.dot(b).atan2(v.dot(t)) / TAU + 0.5
+}
+
+// The smallest positive root of `t`, which must be sorted, or infinity.
+#[tracked]
+fn nearest_positive(t: Expr<Vec2<f32>>) -> Expr<f32> {
+    if t.x > 0.0 {
+        t.x
+    } else if t.y > 0.0 {
+        t.y
+    } else {
+        f32::INFINITY.expr()
+    }
+}
+
+/// Möller–Trumbore intersection, with the barycentric weights of `v1` and `v2` as the UV.
+/// The normal follows the winding order.
+// Möller and Trumbore 1997, Fast, Minimum Storage Ray/Triangle Intersection.
+#[tracked]
+pub fn intersect_triangle(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    v0: Expr<Vec3<f32>>,
+    v1: Expr<Vec3<f32>>,
+    v2: Expr<Vec3<f32>>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let e1 = v1 - v0;
+    let e2 = v2 - v0;
+    let p = dir.cross(e2);
+    let det = e1.dot(p);
+    let inv_det = 1.0 / det;
+    let s = start - v0;
+    let u = s.dot(p) * inv_det;
+    let q = s.cross(e1);
+    let v = dir.dot(q) * inv_det;
+    let t = e2.dot(q) * inv_det;
+    if det != 0.0 && u >= 0.0 && v >= 0.0 && u + v <= 1.0 && t > 0.0 {
+        *hit.t = t;
+        *hit.normal = e1.cross(e2).normalize();
+        *hit.uv = Vec2::expr(u, v);
+    }
+    **hit
+}
+
+// The component of `v` along axis `k`.
+#[tracked]
+fn component(v: Expr<Vec3<f32>>, k: Expr<u32>) -> Expr<f32> {
+    if k == 0 {
+        v.x
+    } else if k == 1 {
+        v.y
+    } else {
+        v.z
+    }
+}
+
+/// Watertight intersection, which never misses along shared edges and vertices, but is slower
+/// than [`intersect_triangle`]. The UV and normal match [`intersect_triangle`].
+// Woop et al. 2013, Watertight Ray/Triangle Intersection.
+#[tracked]
+pub fn intersect_triangle_watertight(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    v0: Expr<Vec3<f32>>,
+    v1: Expr<Vec3<f32>>,
+    v2: Expr<Vec3<f32>>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    // Permute the axes so that the ray is mostly along z, keeping the winding order.
+    let abs_dir = dir.abs();
+    let kz = if abs_dir.x > abs_dir.y && abs_dir.x > abs_dir.z {
+        0_u32.expr()
+    } else if abs_dir.y > abs_dir.z {
+        1_u32.expr()
+    } else {
+        2_u32.expr()
+    };
+    let kx = (kz + 1) % 3;
+    let ky = (kx + 1) % 3;
+    let flip = component(dir, kz) < 0.0;
+    let (kx, ky) = (if flip { ky } else { kx }, if flip { kx } else { ky });
+    let permute =
+        |v: Expr<Vec3<f32>>| Vec3::expr(component(v, kx), component(v, ky), component(v, kz));
+    let d = permute(dir);
+    let shear = Vec3::expr(d.x / d.z, d.y / d.z, 1.0 / d.z);
+
+    let a = permute(v0 - start);
+    let b = permute(v1 - start);
+    let c = permute(v2 - start);
+    let ax = a.x - shear.x * a.z;
+    let ay = a.y - shear.y * a.z;
+    let bx = b.x - shear.x * b.z;
+    let by = b.y - shear.y * b.z;
+    let cx = c.x - shear.x * c.z;
+    let cy = c.y - shear.y * c.z;
+
+    // The scaled barycentric coordinates of `v0`, `v1` and `v2`.
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+    let det = u + v + w;
+    let outside = (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0);
+    if !outside && det != 0.0 {
+        let t = (u * a.z + v * b.z + w * c.z) * shear.z / det;
+        if t > 0.0 {
+            *hit.t = t;
+            *hit.normal = (v1 - v0).cross(v2 - v0).normalize();
+            *hit.uv = Vec2::expr(v, w) / det;
+        }
+    }
+    **hit
+}
+
+/// Intersects the plane of points `p` with `normal.dot(p) == offset`, with the UV being the
+/// position along the [`orthonormal_basis`] of the normal.
+#[tracked]
+pub fn intersect_plane(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    normal: Expr<Vec3<f32>>,
+    offset: Expr<f32>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let t = (offset - normal.dot(start)) / normal.dot(dir);
+    if t > 0.0 && t < f32::INFINITY {
+        let pos = start + dir * t;
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        *hit.t = t;
+        *hit.normal = normal;
+        *hit.uv = Vec2::expr(pos.dot(tangent), pos.dot(bitangent));
+    }
+    **hit
+}
+
+/// Intersects a one-sided disc, with the UV being the distance from the centre relative to the
+/// radius and the angle around the normal.
+#[tracked]
+pub fn intersect_disc(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    center: Expr<Vec3<f32>>,
+    normal: Expr<Vec3<f32>>,
+    radius: Expr<f32>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let t = (center - start).dot(normal) / normal.dot(dir);
+    let offset = start + dir * t - center;
+    if t > 0.0 && t < f32::INFINITY && offset.length_squared() <= radius.sqr() {
+        *hit.t = t;
+        *hit.normal = normal;
+        *hit.uv = Vec2::expr(offset.length() / radius, angle_around(offset, normal));
+    }
+    **hit
+}
+
+/// Intersects a box rotated by `rotation`, whose columns are the axes of the box.
+/// The UV is the position within the face that was hit.
+#[tracked]
+pub fn intersect_box(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    center: Expr<Vec3<f32>>,
+    half_size: Expr<Vec3<f32>>,
+    rotation: Expr<Mat3>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let inv_rotation = rotation.transpose();
+    let start = inv_rotation * (start - center);
+    let dir = inv_rotation * dir;
+    let inv_dir = dir.recip();
+    let t0 = (-half_size - start) * inv_dir;
+    let t1 = (half_size - start) * inv_dir;
+    let t_near = keter::min(t0, t1);
+    let t_far = keter::max(t0, t1);
+    let near = t_near.reduce_max();
+    let far = t_far.reduce_min();
+    if near <= far && far > 0.0 {
+        // Leaving the box if the ray starts inside.
+        let inside = near <= 0.0;
+        let t = if inside { far } else { near };
+        let mask = if inside {
+            t_far == Vec3::splat_expr(far)
+        } else {
+            t_near == Vec3::splat_expr(near)
+        };
+        let sign = if inside { dir.signum() } else { -dir.signum() };
+        let local_normal = mask.select(sign, Vec3::splat_expr(0.0_f32));
+        let uvw = (start + dir * t) / half_size * 0.5 + 0.5;
+        *hit.t = t;
+        *hit.normal = rotation * local_normal;
+        *hit.uv = if mask.x {
+            uvw.yz()
+        } else if mask.y {
+            uvw.xz()
+        } else {
+            uvw.xy()
+        };
+    }
+    **hit
+}
+
+/// Intersects a sphere with the given center, from inside or outside.
+/// `dir` must be normalized. The UV is the azimuth around +Z and the polar angle from +Z.
+// Uses the more precise discriminant from Haines et al. 2019, Precision Improvements for
+// Ray/Sphere Intersection (Ray Tracing Gems, chapter 7).
+#[tracked]
+pub fn intersect_sphere_at(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    center: Expr<Vec3<f32>>,
+    radius: Expr<f32>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let f = start - center;
+    let b = -f.dot(dir);
+    let l = f + b * dir;
+    let discriminant = radius.sqr() - l.length_squared();
+    if discriminant >= 0.0 {
+        // Avoids cancellation by never subtracting values of similar size.
+        let c = f.length_squared() - radius.sqr();
+        let q = b + if b >= 0.0 {
+            discriminant.sqrt()
+        } else {
+            -discriminant.sqrt()
+        };
+        let t0 = c / q;
+        let t1 = q;
+        let t = nearest_positive(Vec2::expr(keter::min(t0, t1), keter::max(t0, t1)));
+        if t < f32::INFINITY {
+            let normal = (f + dir * t) / radius;
+            *hit.t = t;
+            *hit.normal = normal;
+            *hit.uv = Vec2::expr(
+                normal.y.atan2(normal.x) / TAU + 0.5,
+                normal.z.clamp(-1.0, 1.0).acos() / PI,
+            );
+        }
+    }
+    **hit
+}
+
+/// Intersects a capsule from outside, around the segment from `a` to `b`.
+/// `dir` must be normalized. The UV is the angle around the axis and the position along it.
+// https://iquilezles.org/articles/intersectors/
+#[tracked]
+pub fn intersect_capsule(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    a: Expr<Vec3<f32>>,
+    b: Expr<Vec3<f32>>,
+    radius: Expr<f32>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let ba = b - a;
+    let oa = start - a;
+    let baba = ba.dot(ba);
+    let bard = ba.dot(dir);
+    let baoa = ba.dot(oa);
+    let k2 = baba - bard * bard;
+    let k1 = baba * dir.dot(oa) - baoa * bard;
+    let k0 = baba * oa.dot(oa) - baoa * baoa - radius.sqr() * baba;
+    let h = k1 * k1 - k2 * k0;
+    if h >= 0.0 {
+        let t = (-k1 - h.sqrt()) / k2;
+        let y = baoa + t * bard;
+        let t = if y > 0.0 && y < baba {
+            t
+        } else {
+            // The hemispherical caps.
+            let oc = if y <= 0.0 { oa } else { start - b };
+            let b = dir.dot(oc);
+            let h = b * b - oc.dot(oc) + radius.sqr();
+            if h > 0.0 {
+                -b - h.sqrt()
+            } else {
+                f32::INFINITY.expr()
+            }
+        };
+        if t > 0.0 && t < f32::INFINITY {
+            let pa = start + dir * t - a;
+            let along = (pa.dot(ba) / baba).clamp(0.0, 1.0);
+            let normal = (pa - ba * along) / radius;
+            *hit.t = t;
+            *hit.normal = normal;
+            *hit.uv = Vec2::expr(angle_around(normal, ba.normalize()), along);
+        }
+    }
+    **hit
+}
+
+/// Intersects a capped cylinder from outside, around the segment from `a` to `b`.
+/// `dir` must be normalized. The UV is the angle around the axis and the position along it.
+// https://iquilezles.org/articles/intersectors/
+#[tracked]
+pub fn intersect_cylinder(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    a: Expr<Vec3<f32>>,
+    b: Expr<Vec3<f32>>,
+    radius: Expr<f32>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let ba = b - a;
+    let oc = start - a;
+    let baba = ba.dot(ba);
+    let bard = ba.dot(dir);
+    let baoc = ba.dot(oc);
+    let k2 = baba - bard * bard;
+    let k1 = baba * oc.dot(dir) - baoc * bard;
+    let k0 = baba * oc.dot(oc) - baoc * baoc - radius.sqr() * baba;
+    let h = k1 * k1 - k2 * k0;
+    if h >= 0.0 {
+        let h = h.sqrt();
+        let t = (-k1 - h) / k2;
+        let y = baoc + t * bard;
+        if y > 0.0 && y < baba {
+            if t > 0.0 {
+                let normal = (oc + t * dir - ba * y / baba) / radius;
+                *hit.t = t;
+                *hit.normal = normal;
+                *hit.uv = Vec2::expr(angle_around(normal, ba.normalize()), y / baba);
+            }
+        } else {
+            // The cap facing the ray, which is also correct when the ray is parallel to the axis.
+            let cap = if bard > 0.0 { 0.0_f32.expr() } else { baba };
+            let t = (cap - baoc) / bard;
+            let radial = oc + t * dir - ba * (cap / baba);
+            if radial.length_squared() < radius.sqr() && t > 0.0 {
+                let axis = ba / baba.sqrt();
+                *hit.t = t;
+                *hit.normal = if bard > 0.0 { -axis } else { axis };
+                *hit.uv = Vec2::expr(angle_around(radial, axis), cap / baba);
+            }
+        }
+    }
+    **hit
+}
+
+/// Intersects a capped cone from outside, with radius `radius_a` at `a` and `radius_b` at `b`.
+/// `dir` must be normalized. The UV is the angle around the axis and the position along it.
+// https://iquilezles.org/articles/intersectors/
+#[tracked]
+pub fn intersect_cone(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    a: Expr<Vec3<f32>>,
+    b: Expr<Vec3<f32>>,
+    radius_a: Expr<f32>,
+    radius_b: Expr<f32>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let ba = b - a;
+    let oa = start - a;
+    let ob = start - b;
+    let axis = ba.normalize();
+    let m0 = ba.dot(ba);
+    let m1 = oa.dot(ba);
+    let m2 = dir.dot(ba);
+    let m3 = dir.dot(oa);
+    let m5 = oa.dot(oa);
+    let m9 = ob.dot(ba);
+
+    let on_cap = false.var();
+    if m1 < 0.0 {
+        if (oa * m2 - dir * m1).length_squared() < radius_a.sqr() * m2 * m2 {
+            let t = -m1 / m2;
+            *on_cap = true;
+            if t > 0.0 {
+                *hit.t = t;
+                *hit.normal = -axis;
+                *hit.uv = Vec2::expr(angle_around(oa + dir * t, axis), 0.0);
+            }
+        }
+    } else if m9 > 0.0 {
+        let t = -m9 / m2;
+        if (ob + dir * t).length_squared() < radius_b.sqr() {
+            *on_cap = true;
+            if t > 0.0 {
+                *hit.t = t;
+                *hit.normal = axis;
+                *hit.uv = Vec2::expr(angle_around(ob + dir * t, axis), 1.0);
+            }
+        }
+    }
+    if !on_cap {
+        let rr = radius_a - radius_b;
+        let hy = m0 + rr * rr;
+        let k2 = m0 * m0 - m2 * m2 * hy;
+        let k1 = m0 * m0 * m3 - m1 * m2 * hy + m0 * radius_a * (rr * m2);
+        let k0 = m0 * m0 * m5 - m1 * m1 * hy + m0 * radius_a * (rr * m1 * 2.0 - m0 * radius_a);
+        let h = k1 * k1 - k2 * k0;
+        if h >= 0.0 {
+            let t = (-k1 - h.sqrt()) / k2;
+            let y = m1 + t * m2;
+            if y >= 0.0 && y <= m0 && t > 0.0 {
+                let pa = oa + t * dir;
+                *hit.t = t;
+                *hit.normal = (m0 * (m0 * pa + rr * ba * radius_a) - ba * hy * y).normalize();
+                *hit.uv = Vec2::expr(angle_around(pa, axis), y / m0);
+            }
+        }
+    }
+    **hit
+}
+
+/// Intersects a torus around the z axis at the origin, with the radius of the ring and of the
+/// tube in `radii`. `dir` must be normalized, and other tori can be intersected by transforming
+/// the ray. The UV is the angle around the ring and around the tube.
+// https://iquilezles.org/articles/intersectors/, solving the quartic analytically.
+#[tracked]
+pub fn intersect_torus(
+    start: Expr<Vec3<f32>>,
+    dir: Expr<Vec3<f32>>,
+    radii: Expr<Vec2<f32>>,
+) -> Expr<Hit> {
+    let hit = Hit::miss();
+    let ring2 = radii.x * radii.x;
+    let tube2 = radii.y * radii.y;
+    let m = start.dot(start);
+    let n = start.dot(dir);
+    // Bounding sphere.
+    if n * n - m + (radii.x + radii.y).sqr() >= 0.0 {
+        let k = (m - tube2 - ring2) / 2.0;
+        let k3 = n.var();
+        let k2 = (n * n + ring2 * dir.z * dir.z + k).var();
+        let k1 = (k * n + ring2 * start.z * dir.z).var();
+        let k0 = (k * k + ring2 * start.z * start.z - ring2 * tube2).var();
+        // Solving for 1 / t instead keeps the cubic resolvent from being degenerate.
+        let inverted = (n * (n * n - **k2) + **k1).abs() < 0.01;
+        if inverted {
+            let (old_k1, old_k3) = (**k1, **k3);
+            let inv_k0 = k0.recip();
+            *k0 = inv_k0;
+            *k1 = old_k3 * inv_k0;
+            *k2 = k2 * inv_k0;
+            *k3 = old_k1 * inv_k0;
+        }
+        let (k0, k1, k2, k3) = (**k0, **k1, **k2, **k3);
+        let c2 = (2.0 * k2 - 3.0 * k3 * k3) / 3.0;
+        let c1 = (k3 * (k3 * k3 - k2) + k1) * 2.0;
+        let c0 = (k3 * (k3 * (-3.0 * k3 * k3 + 4.0 * k2) - 8.0 * k1) + 4.0 * k0) / 3.0;
+        let q = c2 * c2 + c0;
+        let r = 3.0 * c0 * c2 - c2 * c2 * c2 - c1 * c1;
+        let h = r * r - q * q * q;
+        let z = if h < 0.0 {
+            let sq = q.sqrt();
+            2.0 * sq * ((r / (sq * q)).clamp(-1.0, 1.0).acos() / 3.0).cos()
+        } else {
+            let sq = (h.sqrt() + r.abs()).powf(1.0 / 3.0);
+            r.signum() * (sq + q / sq).abs()
+        };
+        let z = c2 - z;
+        let d1 = (z - 3.0 * c2).var();
+        let d2 = (z * z - 3.0 * c0).var();
+        let valid = true.var();
+        if d1.abs() < 1.0e-4 {
+            *valid = **d2 >= 0.0;
+            *d2 = d2.sqrt();
+        } else {
+            *valid = **d1 >= 0.0;
+            *d1 = (**d1 / 2.0).sqrt();
+            *d2 = c1 / **d1;
+        }
+        if valid {
+            let (d1, d2) = (**d1, **d2);
+            let result = f32::INFINITY.var();
+            let root = |t: Expr<f32>| if inverted { 2.0 / t } else { t };
+            for (offset, h) in [(-d1, d1 * d1 - z + d2), (d1, d1 * d1 - z - d2)] {
+                if h > 0.0 {
+                    let h = h.sqrt();
+                    let t1 = root(offset - h - k3);
+                    let t2 = root(offset + h - k3);
+                    if t1 > 0.0 {
+                        *result = keter::min(**result, t1);
+                    }
+                    if t2 > 0.0 {
+                        *result = keter::min(**result, t2);
+                    }
+                }
+            }
+            let result = **result;
+            if result < f32::INFINITY {
+                let pos = start + dir * result;
+                let ring_dist = pos.xy().length() - radii.x;
+                *hit.t = result;
+                *hit.normal =
+                    (pos * (pos.dot(pos) - tube2 - ring2 * Vec3::expr(1.0, 1.0, -1.0))).normalize();
+                *hit.uv = Vec2::expr(
+                    pos.y.atan2(pos.x) / TAU + 0.5,
+                    pos.z.atan2(ring_dist) / TAU + 0.5,
+                );
+            }
+        }
+    }
+    **hit
+}
+
+#[cfg(test)]
+mod tests {
+    use keter::lang::types::vector::Vec4;
+
+    use super::*;
+    use crate::tests::eval;
+
+    fn hit_to_vec(hit: Expr<Hit>) -> Expr<Vec4<f32>> {
+        hit.normal.extend(hit.t)
+    }
+
+    fn check(
+        shape: impl Fn(Expr<Vec3<f32>>, Expr<Vec3<f32>>) -> Expr<Hit>,
+        start: [f32; 3],
+        dir: [f32; 3],
+        expected_t: f32,
+        expected_normal: [f32; 3],
+    ) {
+        let result = eval(1, |_| {
+            let dir = Vec3::new(dir[0], dir[1], dir[2]).expr().normalize();
+            hit_to_vec(shape(Vec3::new(start[0], start[1], start[2]).expr(), dir))
+        })[0];
+        assert!(
+            (result.w - expected_t).abs() < 1e-5 * expected_t.max(1.0) || result.w == expected_t,
+            "Expected t = {expected_t}, got {}.",
+            result.w
+        );
+        if expected_t.is_finite() {
+            let normal = [result.x, result.y, result.z];
+            for i in 0..3 {
+                assert!(
+                    (normal[i] - expected_normal[i]).abs() < 1e-4,
+                    "Expected normal {expected_normal:?}, got {normal:?}."
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn triangles_agree() {
+        let triangle = |watertight: bool| {
+            move |start, dir| {
+                let v0 = Vec3::expr(-1.0, -1.0, 2.0);
+                let v1 = Vec3::expr(1.0, -1.0, 2.0);
+                let v2 = Vec3::expr(0.0, 1.0, 2.0);
+                if watertight {
+                    intersect_triangle_watertight(start, dir, v0, v1, v2)
+                } else {
+                    intersect_triangle(start, dir, v0, v1, v2)
+                }
+            }
+        };
+        for watertight in [false, true] {
+            check(
+                triangle(watertight),
+                [0.0; 3],
+                [0.0, 0.0, 1.0],
+                2.0,
+                [0.0, 0.0, 1.0],
+            );
+            check(
+                triangle(watertight),
+                [0.0; 3],
+                [0.0, 0.0, -1.0],
+                f32::INFINITY,
+                [0.0; 3],
+            );
+            check(
+                triangle(watertight),
+                [5.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0],
+                f32::INFINITY,
+                [0.0; 3],
+            );
+        }
+        let uvs = eval(64, |i| {
+            let start = Vec3::expr(
+                (i % 8).cast_f32() / 4.0 - 1.0,
+                (i / 8).cast_f32() / 4.0 - 1.0,
+                -1.0,
+            );
+            // Chosen so that no ray passes exactly through an edge.
+            let dir = Vec3::expr(0.13, 0.21, 1.0);
+            let v0 = Vec3::expr(-1.0, -1.0, 2.0);
+            let v1 = Vec3::expr(1.0, -1.0, 2.0);
+            let v2 = Vec3::expr(0.0, 1.0, 2.0);
+            let a = intersect_triangle(start, dir, v0, v1, v2);
+            let b = intersect_triangle_watertight(start, dir, v0, v1, v2);
+            Vec4::expr(
+                a.uv.x - b.uv.x,
+                a.uv.y - b.uv.y,
+                a.t - b.t,
+                a.is_hit().cast_f32() - b.is_hit().cast_f32(),
+            )
+        });
+        for x in uvs {
+            // Both miss, or both hit at the same point.
+            assert!(
+                x.w == 0.0
+                    && (x.z.is_nan() || (x.x.abs() < 1e-5 && x.y.abs() < 1e-5 && x.z.abs() < 1e-5))
+            );
+        }
+    }
+
+    #[test]
+    fn planes() {
+        check(
+            |s, d| intersect_plane(s, d, Vec3::expr(0.0, 0.0, 1.0), 2.0_f32.expr()),
+            [0.0; 3],
+            [0.0, 0.0, 1.0],
+            2.0,
+            [0.0, 0.0, 1.0],
+        );
+        let disc = |s, d| {
+            intersect_disc(
+                s,
+                d,
+                Vec3::expr(0.0, 0.0, 2.0),
+                Vec3::expr(0.0, 0.0, -1.0),
+                1.0_f32.expr(),
+            )
+        };
+        check(
+            disc,
+            [0.5, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            2.0,
+            [0.0, 0.0, -1.0],
+        );
+        check(
+            disc,
+            [1.5, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            f32::INFINITY,
+            [0.0; 3],
+        );
+    }
+
+    #[test]
+    fn boxes() {
+        let rotated = |s, d| {
+            let c = std::f32::consts::FRAC_1_SQRT_2;
+            let rotation: Mat3 = glam::Mat3::from_cols(
+                glam::Vec3::new(c, c, 0.0),
+                glam::Vec3::new(-c, c, 0.0),
+                glam::Vec3::Z,
+            )
+            .into();
+            intersect_box(
+                s,
+                d,
+                Vec3::expr(3.0, 0.0, 0.0),
+                Vec3::splat_expr(1.0),
+                rotation.expr(),
+            )
+        };
+        let c = std::f32::consts::FRAC_1_SQRT_2;
+        // The box is rotated 45 degrees around z, so this hits the face just below its corner.
+        check(
+            rotated,
+            [0.0, -0.3, 0.5],
+            [1.0, 0.0, 0.0],
+            3.3 - 2.0_f32.sqrt(),
+            [-c, -c, 0.0],
+        );
+        check(
+            rotated,
+            [3.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            1.0,
+            [0.0, 0.0, 1.0],
+        );
+    }
+
+    #[test]
+    fn spheres() {
+        let sphere = |s, d| intersect_sphere_at(s, d, Vec3::expr(0.0, 5.0, 0.0), 2.0_f32.expr());
+        check(sphere, [0.0; 3], [0.0, 1.0, 0.0], 3.0, [0.0, -1.0, 0.0]);
+        check(
+            sphere,
+            [0.0, 5.0, 0.0],
+            [0.0, 1.0, 0.0],
+            2.0,
+            [0.0, 1.0, 0.0],
+        );
+        check(
+            sphere,
+            [0.0, 10.0, 0.0],
+            [0.0, 1.0, 0.0],
+            f32::INFINITY,
+            [0.0; 3],
+        );
+        // Far away, where the naive discriminant loses most of its precision.
+        let far = |s, d| intersect_sphere_at(s, d, Vec3::expr(0.0, 10000.0, 0.0), 0.01_f32.expr());
+        check(far, [0.0; 3], [0.0, 1.0, 0.0], 9999.99, [0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn capsules_and_cylinders() {
+        let (a, b) = (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let capsule = |s, d| intersect_capsule(s, d, a.expr(), b.expr(), 0.5_f32.expr());
+        check(
+            capsule,
+            [-3.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            2.5,
+            [-1.0, 0.0, 0.0],
+        );
+        check(
+            capsule,
+            [0.0, 0.0, 5.0],
+            [0.0, 0.0, -1.0],
+            3.5,
+            [0.0, 0.0, 1.0],
+        );
+        let cylinder = |s, d| intersect_cylinder(s, d, a.expr(), b.expr(), 0.5_f32.expr());
+        check(
+            cylinder,
+            [-3.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            2.5,
+            [-1.0, 0.0, 0.0],
+        );
+        check(
+            cylinder,
+            [0.0, 0.0, 5.0],
+            [0.0, 0.0, -1.0],
+            4.0,
+            [0.0, 0.0, 1.0],
+        );
+        check(
+            cylinder,
+            [0.0, 0.0, -5.0],
+            [0.0, 0.0, 1.0],
+            4.0,
+            [0.0, 0.0, -1.0],
+        );
+        let cone = |s, d| intersect_cone(s, d, a.expr(), b.expr(), 1.0_f32.expr(), 0.0_f32.expr());
+        // The side is at a radius of 0.5 halfway up.
+        let s = 1.0 / 5.0_f32.sqrt();
+        check(
+            cone,
+            [-3.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            2.5,
+            [-2.0 * s, 0.0, s],
+        );
+        check(
+            cone,
+            [0.0, 0.0, -5.0],
+            [0.0, 0.0, 1.0],
+            4.0,
+            [0.0, 0.0, -1.0],
+        );
+    }
+
+    #[test]
+    fn tori() {
+        let torus = |s, d| intersect_torus(s, d, Vec2::expr(2.0, 0.5));
+        check(
+            torus,
+            [-5.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            2.5,
+            [-1.0, 0.0, 0.0],
+        );
+        check(
+            torus,
+            [0.0, 0.0, 5.0],
+            [0.0, 0.0, -1.0],
+            f32::INFINITY,
+            [0.0; 3],
+        );
+        check(
+            torus,
+            [2.0, 0.0, 5.0],
+            [0.0, 0.0, -1.0],
+            4.5,
+            [0.0, 0.0, 1.0],
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use std::sync::LazyLock;
+
+use keter::prelude::*;
+use keter::runtime::Device;
+
+// Tests run on the CPU backend, so that they don't require a GPU.
+pub static CPU_DEVICE: LazyLock<Device> = LazyLock::new(|| {
+    let ctx = keter::Context::new(std::env::current_exe().unwrap());
+    ctx.create_device(keter::DeviceType::Cpu)
+});
+
+/// Evaluates `f` on the device for every index in `0..n`.
+pub fn eval<T: Value>(n: u32, f: impl Fn(Expr<u32>) -> Expr<T>) -> Vec<T> {
+    let buffer = CPU_DEVICE.create_buffer::<T>(n as usize);
+    let kernel = CPU_DEVICE.create_kernel::<fn()>(&track!(|| {
+        let i = dispatch_id().x;
+        buffer.write(i, f(i));
+    }));
+    kernel.dispatch([n, 1, 1]);
+    buffer.copy_to_vec()
+}
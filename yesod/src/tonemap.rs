@@ -0,0 +1,250 @@
+use keter::lang::types::vector::{Mat3, Vec3};
+use keter::prelude::*;
+
+use crate::agx::{AgXParameters, agx_tonemap};
+
+/// Maps scene-referred colors to display-referred linear colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tonemapper {
+    /// Passes colors through unchanged.
+    Linear,
+    /// See [`reinhard_extended`].
+    ReinhardExtended { white: f32 },
+    /// See [`aces_fitted`].
+    AcesFitted,
+    /// See [`pbr_neutral`].
+    PbrNeutral,
+    /// See [`uchimura`].
+    Uchimura(UchimuraParameters),
+    /// See [`lottes`].
+    Lottes(LottesParameters),
+    /// See [`agx_tonemap`], with an optional look.
+    AgX(Option<AgXParameters>),
+}
+impl Tonemapper {
+    #[tracked]
+    pub fn tonemap(self, color: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+        match self {
+            Tonemapper::Linear => color,
+            Tonemapper::ReinhardExtended { white } => reinhard_extended(color, white),
+            Tonemapper::AcesFitted => aces_fitted(color),
+            Tonemapper::PbrNeutral => pbr_neutral(color),
+            Tonemapper::Uchimura(params) => uchimura(color, params),
+            Tonemapper::Lottes(params) => lottes(color, params),
+            // AgX outputs values encoded for a 2.2 gamma display, so they are decoded to match.
+            Tonemapper::AgX(params) => keter::max(agx_tonemap(color, params), 0.0).powf(2.2),
+        }
+    }
+}
+
+/// Reinhard's operator per channel, extended so that `white` maps to 1.
+// https://www-old.cs.utah.edu/docs/techreports/2002/pdf/UUCS-02-001.pdf
+#[tracked]
+pub fn reinhard_extended(color: Expr<Vec3<f32>>, white: f32) -> Expr<Vec3<f32>> {
+    color * (1.0 + color / (white * white)) / (1.0 + color)
+}
+
+#[tracked]
+fn rrt_and_odt_fit(v: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    a / b
+}
+
+/// Stephen Hill's fit of the ACES reference rendering and output transforms.
+// https://github.com/TheRealMJP/BakingLab/blob/master/BakingLab/ACES.hlsl
+#[tracked]
+pub fn aces_fitted(color: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    // sRGB to ACES, including the RRT saturation.
+    let input = Mat3::from_column_array(&[
+        [0.59719, 0.07600, 0.02840],
+        [0.35458, 0.90834, 0.13383],
+        [0.04823, 0.01566, 0.83777],
+    ]);
+    // ODT saturation and ACES to sRGB.
+    let output = Mat3::from_column_array(&[
+        [1.60475, -0.10208, -0.00327],
+        [-0.53108, 1.10813, -0.07276],
+        [-0.07367, -0.00605, 1.07602],
+    ]);
+    let color = rrt_and_odt_fit(input.expr() * color);
+    (output.expr() * color).clamp(0.0, 1.0)
+}
+
+/// The Khronos PBR Neutral operator, which keeps base colors unchanged under
+/// unit illumination.
+// https://github.com/KhronosGroup/ToneMapping/blob/main/PBR_Neutral/README.md
+#[tracked]
+pub fn pbr_neutral(color: Expr<Vec3<f32>>) -> Expr<Vec3<f32>> {
+    let start_compression = 0.8_f32 - 0.04;
+    let desaturation = 0.15_f32;
+
+    let x = color.reduce_min();
+    let offset = if x < 0.08 {
+        x - 6.25 * x * x
+    } else {
+        0.04_f32.expr()
+    };
+    let color = color - offset;
+
+    let peak = color.reduce_max();
+    if peak < start_compression {
+        color
+    } else {
+        let d = 1.0 - start_compression;
+        let new_peak = 1.0 - d * d / (peak + d - start_compression);
+        let color = color * (new_peak / peak);
+        let g = 1.0 - 1.0 / (desaturation * (peak - new_peak) + 1.0);
+        color.lerp(Vec3::splat_expr(new_peak), Vec3::splat_expr(g))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UchimuraParameters {
+    pub max_brightness: f32,
+    pub contrast: f32,
+    pub linear_start: f32,
+    pub linear_length: f32,
+    pub black_tightness: f32,
+    pub pedestal: f32,
+}
+impl Default for UchimuraParameters {
+    fn default() -> Self {
+        Self {
+            max_brightness: 1.0,
+            contrast: 1.0,
+            linear_start: 0.22,
+            linear_length: 0.4,
+            black_tightness: 1.33,
+            pedestal: 0.0,
+        }
+    }
+}
+
+/// Uchimura's operator from Gran Turismo Sport, with a power toe, a linear section,
+/// and an exponential shoulder.
+// https://www.slideshare.net/nikuque/hdr-theory-and-practicce-jp
+#[tracked]
+pub fn uchimura(color: Expr<Vec3<f32>>, params: UchimuraParameters) -> Expr<Vec3<f32>> {
+    let UchimuraParameters {
+        max_brightness: p,
+        contrast: a,
+        linear_start: m,
+        linear_length: l,
+        black_tightness: c,
+        pedestal: b,
+    } = params;
+    let l0 = (p - m) * l / a;
+    let s0 = m + l0;
+    let s1 = m + a * l0;
+    let c2 = a * p / (p - s1);
+    let cp = -c2 / p;
+
+    let toe_t = (color / m).clamp(0.0, 1.0);
+    let w0 = 1.0 - toe_t * toe_t * (3.0 - 2.0 * toe_t);
+    let w2 = (color >= s0).select(Vec3::splat_expr(1.0), Vec3::splat_expr(0.0));
+    let w1 = 1.0 - w0 - w2;
+
+    let toe = m * (color / m).powf(c) + b;
+    let shoulder = p - (p - s1) * (cp * (color - s0)).exp();
+    let linear = m + a * (color - m);
+    toe * w0 + linear * w1 + shoulder * w2
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LottesParameters {
+    pub contrast: f32,
+    pub shoulder: f32,
+    pub hdr_max: f32,
+    pub mid_in: f32,
+    pub mid_out: f32,
+}
+impl Default for LottesParameters {
+    fn default() -> Self {
+        Self {
+            contrast: 1.6,
+            shoulder: 0.977,
+            hdr_max: 8.0,
+            mid_in: 0.18,
+            mid_out: 0.267,
+        }
+    }
+}
+
+/// Lottes' operator, which maps `mid_in` to `mid_out` and `hdr_max` to 1.
+// https://gpuopen.com/wp-content/uploads/2016/03/GdcVdrLottes.pdf
+#[tracked]
+pub fn lottes(color: Expr<Vec3<f32>>, params: LottesParameters) -> Expr<Vec3<f32>> {
+    let LottesParameters {
+        contrast: a,
+        shoulder: d,
+        hdr_max,
+        mid_in,
+        mid_out,
+    } = params;
+    let denominator = (hdr_max.powf(a * d) - mid_in.powf(a * d)) * mid_out;
+    let b = (-mid_in.powf(a) + hdr_max.powf(a) * mid_out) / denominator;
+    let c = (hdr_max.powf(a * d) * mid_in.powf(a) - hdr_max.powf(a) * mid_in.powf(a * d) * mid_out)
+        / denominator;
+    color.powf(a) / (color.powf(a * d) * b + c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::eval;
+
+    #[test]
+    fn operators_are_monotonic() {
+        let tonemappers = [
+            Tonemapper::ReinhardExtended { white: 4.0 },
+            Tonemapper::AcesFitted,
+            Tonemapper::PbrNeutral,
+            Tonemapper::Uchimura(UchimuraParameters::default()),
+            Tonemapper::Lottes(LottesParameters::default()),
+            Tonemapper::AgX(None),
+        ];
+        for tonemapper in tonemappers {
+            let results = eval::<f32>(
+                200,
+                track!(|i| {
+                    let x = (i.cast_f32() * 0.04 - 6.0).exp2();
+                    tonemapper.tonemap(Vec3::splat_expr(x)).y
+                }),
+            );
+            for (i, pair) in results.windows(2).enumerate() {
+                assert!(
+                    pair[1] >= pair[0] - 1e-5,
+                    "{tonemapper:?} decreases at {i}: {pair:?}."
+                );
+            }
+            let last = results[results.len() - 1];
+            assert!(
+                (0.0..=1.0001).contains(&results[0]) && (0.5..=1.0001).contains(&last),
+                "{tonemapper:?} maps to {} to {last}.",
+                results[0]
+            );
+        }
+    }
+
+    #[test]
+    fn known_values() {
+        let results = eval::<Vec3<f32>>(
+            3,
+            track!(|i| {
+                let white = Vec3::splat_expr(4.0_f32);
+                if i == 0 {
+                    reinhard_extended(white, 4.0)
+                } else if i == 1 {
+                    lottes(Vec3::splat_expr(0.18), LottesParameters::default())
+                } else {
+                    pbr_neutral(Vec3::expr(0.5, 0.3, 0.2))
+                }
+            }),
+        );
+        assert!((results[0].x - 1.0).abs() < 1e-5);
+        assert!((results[1].x - 0.267).abs() < 1e-4);
+        // Colors below the compression threshold only lose the offset.
+        assert!((results[2].x - 0.46).abs() < 1e-5 && (results[2].z - 0.16).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,61 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::*;
+
+fn derive_push_bytes_impl(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            input.span(),
+            "`PushBytes` can only be derived for structs.",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new(
+            data.fields.span(),
+            "`PushBytes` can only be derived for structs with named fields.",
+        ));
+    };
+    let printer_path: Path = parse_quote!(::yesod::printer);
+
+    let mut generics = input.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for field in &fields.named {
+        let ty = &field.ty;
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: #printer_path::PushBytes));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let pushes = fields.named.iter().map(|field| {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        quote! {
+            writer.seek(start, ::std::mem::offset_of!(Self, #ident));
+            <#ty as #printer_path::PushBytes>::push_bytes(value.#ident, writer);
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics #printer_path::PushBytes for #name #ty_generics #where_clause {
+            fn push_bytes(
+                value: ::yesod::keter::prelude::Expr<Self>,
+                writer: &mut #printer_path::PrintWriter,
+            ) {
+                let start = writer.head();
+                #(#pushes)*
+                writer.seek(start, ::std::mem::size_of::<Self>());
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(PushBytes)]
+pub fn derive_push_bytes(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive_push_bytes_impl(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}